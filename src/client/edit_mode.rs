@@ -2,7 +2,8 @@ use crate::{
     client::{
         edit_mode_utils::{
             apply_standard_transform, combo_box_for_enum, combo_box_for_materials, edit_option,
-            edit_rotation, edit_vec2, labelled_widget,
+            edit_rotation, edit_tags, edit_vec2, format_area, format_length,
+            is_extreme_aspect_ratio, labelled_widget, Units,
         },
         networking::save_layout,
         vec2_to_egui_pos, HomeFlow,
@@ -11,20 +12,34 @@ use crate::{
         color::Color,
         furniture::{ChairType, Furniture, FurnitureType, TableType},
         layout::{
-            Action, GlobalMaterial, Home, Light, MultiLight, Opening, OpeningType, Operation,
-            Outline, Room, Sensor, TileOptions, Walls, Zone,
+            Action, GlobalMaterial, Home, Light, LightKind, MultiLight, Opening, OpeningType,
+            Operation, Outline, Room, Scene, Sensor, SunConfig, TileOptions, Walls, Zone,
         },
-        utils::Material,
+        schedule::parse_room_schedule,
+        shape::polygons_are_valid,
+        utils::{rotate_point_i32, Material, RoundFactor},
+        validate::LayoutWarning,
+        HAArea,
     },
 };
+use ahash::AHashMap;
 use egui::{
-    collapsing_header::CollapsingState, Align2, Button, Color32, CursorIcon, DragValue,
-    PointerButton, TextEdit, Ui, Window,
+    collapsing_header::CollapsingState, Align2, Button, Color32, CursorIcon, DragValue, Key,
+    KeyboardShortcut, Modifiers, PointerButton, Slider, TextEdit, TextureHandle, Ui, Window,
 };
 use glam::{dvec2 as vec2, DVec2 as Vec2};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, time::Duration};
 use uuid::Uuid;
 
+const ROTATE_STEP: i32 = 15;
+// Meters the arrow keys move the selection, the fine step is used while holding shift
+const NUDGE_STEP: f64 = 0.05;
+const NUDGE_STEP_FINE: f64 = 0.01;
+const MAX_RECENT_FURNITURE: usize = 6;
+const MAX_UNDO_HISTORY: usize = 50;
+const SEARCH_FIELD_ID: &str = "Find Query";
+
 nestify::nest! {
     #[derive(Default)]
     pub struct EditDetails {
@@ -37,15 +52,100 @@ nestify::nest! {
             pub start_pos: Vec2,
             pub start_size: Vec2,
             pub start_rotation: i32,
+            // Degrees added to the start rotation by pressing R mid drag
+            pub rotation_offset: i32,
+            // Set once the pointer leaves the dead zone, until then the object stays put
+            pub past_threshold: bool,
+            // Ids of the copies made while array dragging furniture
+            pub array: Option<Vec<Uuid>>,
+            // Other selected rooms moving along with the dragged one, with their start positions
+            pub group: Vec<(Uuid, Vec2)>,
         }>,
-        pub selected_id: Option<Uuid>,
+        pub selected_ids: Vec<Uuid>,
         pub selected_type: Option<ObjectType>,
+        // World position a shift drag box selection started from
+        pub box_select: Option<Vec2>,
+        // World space vertices placed so far while drawing a freeform room
+        pub drawing_room: Option<Vec<Vec2>>,
+        // World space points of the measuring ruler, toggled with M
+        pub ruler: Option<Vec<Vec2>>,
+        // Last time drawing, the ruler or the eyedropper was in use
+        pub tool_time: f64,
+        // Cell size of the absolute snapping grid, None when only snapping to edges
+        pub grid_size: Option<f64>,
+        pub show_grid: bool,
         pub preview_edits: bool,
         pub resize_enabled: bool,
         pub material_editor_open: bool,
+        // Next click copies the material of the object under it onto the selected room
+        pub eyedropper: bool,
+        // Results of the last layout check while its window is open
+        pub layout_warnings: Option<Vec<LayoutWarning>>,
+        // Pasted room schedule while the import window is open
+        pub import_text: Option<String>,
+        // Query typed into the find window while it's open
+        pub search: Option<String>,
+        // Room or furniture having its name edited in place
+        pub renaming: Option<pub struct RenameDetails {
+            pub id: Uuid,
+            pub original_name: String,
+            // Edited in the find window rather than on the canvas
+            pub in_find: bool,
+        }>,
+        // Most recently picked furniture types this session, newest first
+        pub recent_furniture: Vec<FurnitureType>,
+        // Serialised layouts for undo and redo, snapshot is the last settled layout
+        #>[derive(Default)]
+        pub history: pub struct EditHistory {
+            pub undo: Vec<Vec<u8>>,
+            pub redo: Vec<Vec<u8>>,
+            pub snapshot: Option<Vec<u8>>,
+        },
     }
 }
 
+impl EditHistory {
+    /// Take the settled layout, pushing the last one to undo if it changed
+    fn record(&mut self, current: Vec<u8>) {
+        if self.snapshot.as_ref() == Some(&current) {
+            return;
+        }
+        if let Some(previous) = self.snapshot.replace(current) {
+            self.undo.push(previous);
+            if self.undo.len() > MAX_UNDO_HISTORY {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+    }
+
+    /// Step back a layout, returning the one to restore
+    fn undo(&mut self) -> Option<&[u8]> {
+        self.snapshot.as_ref()?;
+        let previous = self.undo.pop()?;
+        self.redo.extend(self.snapshot.replace(previous));
+        self.snapshot.as_deref()
+    }
+
+    /// Step forward a layout, returning the one to restore
+    fn redo(&mut self) -> Option<&[u8]> {
+        self.snapshot.as_ref()?;
+        let next = self.redo.pop()?;
+        self.undo.extend(self.snapshot.replace(next));
+        self.snapshot.as_deref()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+    Top,
+    Middle,
+    Bottom,
+}
+
 #[derive(Debug)]
 pub struct HoverDetails {
     pub id: Uuid,
@@ -93,6 +193,13 @@ pub struct EditResponse {
     pub snap_line_y: Option<f64>,
 }
 
+/// Copied objects, kept on the system clipboard as RON so they can be pasted between sessions
+#[derive(Serialize, Deserialize)]
+enum ClipboardContents {
+    Rooms(Vec<Room>),
+    Furniture(Box<Furniture>),
+}
+
 #[derive(Clone, Copy)]
 enum AlterObject {
     None,
@@ -100,12 +207,86 @@ enum AlterObject {
     MoveUp,
     MoveDown,
     Duplicate,
+    ApplyMaterialToType,
+    BringToFront,
+    SendToBack,
 }
 
 impl HomeFlow {
     pub fn edit_mode_settings(&mut self, ui: &mut Ui) {
         if self.edit_mode.enabled {
             ui.checkbox(&mut self.edit_mode.resize_enabled, "Resizing");
+            ui.horizontal(|ui| {
+                let history = &self.edit_mode.history;
+                let (can_undo, can_redo) = (!history.undo.is_empty(), !history.redo.is_empty());
+                if ui
+                    .add_enabled(can_undo, Button::new("Undo"))
+                    .on_hover_text("Ctrl+Z")
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(can_redo, Button::new("Redo"))
+                    .on_hover_text("Ctrl+Y")
+                    .clicked()
+                {
+                    self.redo();
+                }
+            });
+            edit_option(
+                ui,
+                "Snap Grid",
+                &mut self.edit_mode.grid_size,
+                || 0.25,
+                |ui, grid_size| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(grid_size)
+                                .range(0.01..=5.0)
+                                .speed(0.01)
+                                .suffix("m"),
+                        );
+                        ui.checkbox(&mut self.edit_mode.show_grid, "Show");
+                    });
+                },
+            );
+            ui.collapsing("New Room Walls", |ui| {
+                for (wall_side, flag) in [
+                    ("Left", Walls::LEFT),
+                    ("Top", Walls::TOP),
+                    ("Right", Walls::RIGHT),
+                    ("Bottom", Walls::BOTTOM),
+                ] {
+                    let mut is_wall = self.device.new_room_walls.contains(flag);
+                    if ui.checkbox(&mut is_wall, wall_side).changed() {
+                        self.device.new_room_walls.set(flag, is_wall);
+                    }
+                }
+                ui.label("Sides shared with an existing room are left open");
+            });
+            if let Some(rendered_data) = &self.layout.rendered_data {
+                ui.label(format!(
+                    "Floor Area: {}",
+                    format_area(
+                        rendered_data.floor_area,
+                        self.device.units,
+                        self.device.coordinate_precision
+                    )
+                ));
+            }
+            if ui
+                .button("Check Layout")
+                .on_hover_text(
+                    "List missing materials, empty shapes, stray openings and duplicate ids",
+                )
+                .clicked()
+            {
+                self.edit_mode.layout_warnings = Some(self.layout.validate());
+            }
+            if ui.button("Find").on_hover_text("Ctrl+F").clicked() {
+                self.open_search(ui.ctx());
+            }
             if ui.button("Materials Editor").clicked() {
                 self.edit_mode.material_editor_open = !self.edit_mode.material_editor_open;
             }
@@ -120,7 +301,7 @@ impl HomeFlow {
                     .duration(Some(Duration::from_secs(2)));
                 save_layout(
                     &self.host,
-                    &self.stored.auth_token,
+                    &self.account.auth_token,
                     &self.layout,
                     move |result| match result {
                         Ok(()) => {
@@ -140,6 +321,17 @@ impl HomeFlow {
                 self.layout_server = self.layout.clone();
                 self.edit_mode.enabled = false;
             }
+            if self.layout.demo_states.is_some() {
+                if ui.button("Clear Demo States").clicked() {
+                    self.layout.demo_states = None;
+                }
+            } else if ui
+                .button("Capture Demo States")
+                .on_hover_text("Save the current states into the layout, shown when offline")
+                .clicked()
+            {
+                self.layout.demo_states = Some(self.capture_states());
+            }
             if ui.button("Discard Edits").clicked() {
                 self.layout = self.layout_server.clone();
                 self.edit_mode.enabled = false;
@@ -185,6 +377,291 @@ impl HomeFlow {
         else if ui.button("Edit Mode").clicked() {
             self.edit_mode.enabled = true;
         }
+        if ui
+            .button("Fit Home")
+            .on_hover_text("Frame the whole home (F)")
+            .clicked()
+        {
+            self.zoom_to_fit();
+        }
+        if ui
+            .button("Copy View")
+            .on_hover_text("Copy the current view to the clipboard as an image")
+            .clicked()
+        {
+            self.copy_view_to_clipboard(ui.ctx());
+        }
+        ui.checkbox(&mut self.device.show_room_power, "Room Power");
+        ui.checkbox(&mut self.device.show_operations, "Show Operations");
+        ui.checkbox(&mut self.device.stream_states, "Live States")
+            .on_hover_text("Receive state changes as they happen instead of polling for them");
+        ui.checkbox(&mut self.device.temperature_overlay, "Temperature Overlay")
+            .on_hover_text("Tint rooms blue to red from their temperature sensors");
+        labelled_widget(ui, "Presence Trail", |ui| {
+            ui.add(
+                DragValue::new(&mut self.device.presence_trail_seconds)
+                    .speed(1.0)
+                    .range(0.0..=600.0)
+                    .suffix("s"),
+            )
+            .on_hover_text("How long presence points leave a fading trail, zero to hide it");
+        });
+        combo_box_for_enum(ui, "Easing", &mut self.device.easing, "Easing");
+        ui.collapsing("Furniture Hover", |ui| {
+            ui.checkbox(&mut self.device.hover_in_view_mode, "In View Mode");
+            labelled_widget(ui, "Speed", |ui| {
+                ui.add(Slider::new(&mut self.device.hover_speed, 1.0..=30.0));
+            });
+            labelled_widget(ui, "Strength", |ui| {
+                ui.add(Slider::new(&mut self.device.hover_strength, 0.0..=2.0))
+                    .on_hover_text("How far chairs pull out and drawers and doors open");
+            });
+        });
+        ui.collapsing("Sun Path", |ui| {
+            ui.checkbox(&mut self.device.show_sun_path, "Show");
+            let editing = self.edit_mode.enabled;
+            let mut daylight = self.layout.sun.is_some();
+            if ui
+                .add_enabled(editing, egui::Checkbox::new(&mut daylight, "Daylight"))
+                .on_hover_text("Sunlight through windows at this time, toggled in edit mode")
+                .changed()
+            {
+                self.layout.sun = daylight.then(SunConfig::default);
+            }
+            labelled_widget(ui, "Day", |ui| {
+                ui.add(Slider::new(&mut self.device.sun_day, 1..=365))
+                    .on_hover_text("Day of the year, 172 is the summer solstice");
+            });
+            labelled_widget(ui, "Hour", |ui| {
+                ui.add(Slider::new(&mut self.device.sun_hour, 0.0..=24.0).step_by(0.25));
+            });
+            if let Some(sun) = &mut self.layout.sun {
+                labelled_widget(ui, "Intensity", |ui| {
+                    ui.add_enabled(editing, Slider::new(&mut sun.intensity, 0.0..=2.0));
+                });
+            }
+            let location = &mut self.layout.location;
+            labelled_widget(ui, "Latitude", |ui| {
+                ui.add_enabled(
+                    editing,
+                    DragValue::new(&mut location.latitude)
+                        .range(-90.0..=90.0)
+                        .speed(0.1)
+                        .suffix("°"),
+                );
+            });
+            labelled_widget(ui, "North", |ui| {
+                ui.add_enabled(
+                    editing,
+                    DragValue::new(&mut location.north_rotation)
+                        .range(0.0..=360.0)
+                        .suffix("°"),
+                )
+                .on_hover_text("Compass bearing the top of the plan faces, editable in edit mode");
+            });
+        });
+        ui.collapsing("Layers", |ui| {
+            let mut tags: BTreeSet<&String> = self.device.hidden_tags.iter().collect();
+            for room in &self.layout.rooms {
+                tags.extend(&room.tags);
+                for furniture in &room.furniture {
+                    tags.extend(&furniture.tags);
+                }
+            }
+            if tags.is_empty() {
+                ui.label("Add tags to rooms or furniture to show and hide them together");
+            }
+            let mut toggled = None;
+            for tag in tags {
+                let mut visible = !self.device.hidden_tags.contains(tag);
+                if ui.checkbox(&mut visible, tag.as_str()).changed() {
+                    toggled = Some((tag.clone(), visible));
+                }
+            }
+            if let Some((tag, visible)) = toggled {
+                if visible {
+                    self.device.hidden_tags.remove(&tag);
+                } else {
+                    self.device.hidden_tags.insert(tag);
+                }
+            }
+            if !self.device.hidden_tags.is_empty() && ui.button("Show All").clicked() {
+                self.device.hidden_tags.clear();
+            }
+        });
+        ui.collapsing("Scenes", |ui| {
+            let editing = self.edit_mode.enabled;
+            let mut activate = None;
+            let mut capture = None;
+            let mut delete = None;
+            for (index, scene) in self.layout.scenes.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("▶")
+                        .on_hover_text(format!("Set the {} lights", scene.lights.len()))
+                        .clicked()
+                    {
+                        activate = Some(scene.id);
+                    }
+                    if editing {
+                        TextEdit::singleline(&mut scene.name)
+                            .min_size(egui::vec2(80.0, 0.0))
+                            .show(ui);
+                        if ui
+                            .button("Update")
+                            .on_hover_text("Replace with the current light states")
+                            .clicked()
+                        {
+                            capture = Some(index);
+                        }
+                        if ui.button("Delete").clicked() {
+                            delete = Some(index);
+                        }
+                    } else {
+                        ui.label(&scene.name);
+                    }
+                });
+            }
+            if editing
+                && ui
+                    .button("Capture Scene")
+                    .on_hover_text("Save the current light states as a new scene")
+                    .clicked()
+            {
+                let name = format!("Scene {}", self.layout.scenes.len() + 1);
+                let scene = self.capture_scene(&name);
+                self.layout.scenes.push(scene);
+            }
+            if let Some(index) = capture {
+                let scene = &self.layout.scenes[index];
+                let (id, name) = (scene.id, scene.name.clone());
+                self.layout.scenes[index] = Scene {
+                    id,
+                    ..self.capture_scene(&name)
+                };
+            }
+            if let Some(index) = delete {
+                self.layout.scenes.remove(index);
+            }
+            if let Some(scene_id) = activate {
+                self.activate_scene(scene_id);
+            }
+        });
+        ui.collapsing("Export", |ui| {
+            labelled_widget(ui, "Resolution", |ui| {
+                ui.add(
+                    DragValue::new(&mut self.device.export_resolution)
+                        .range(10.0..=1000.0)
+                        .suffix(" px/m"),
+                );
+            });
+            labelled_widget(ui, "Margin", |ui| {
+                ui.add(
+                    DragValue::new(&mut self.device.export_margin)
+                        .speed(0.1)
+                        .range(0.0..=10.0)
+                        .suffix("m"),
+                )
+                .on_hover_text("Space left around the plan in the image");
+            });
+            ui.checkbox(&mut self.device.export_lighting, "Include Lighting");
+            ui.checkbox(&mut self.device.export_operations, "Include Operations");
+            if ui
+                .button("Export PNG")
+                .on_hover_text(
+                    "Save the selected rooms, or the whole layout, as a new image in the working \
+                     directory, hidden phases and tags are left out",
+                )
+                .clicked()
+            {
+                self.export_png(ui.ctx());
+            }
+        });
+        combo_box_for_enum(ui, "Units", &mut self.device.units, "Units");
+        labelled_widget(ui, "Precision", |ui| {
+            ui.add(
+                DragValue::new(&mut self.device.coordinate_precision)
+                    .range(0..=4)
+                    .suffix(" dp"),
+            )
+            .on_hover_text("Decimal places shown for positions and sizes");
+        });
+        labelled_widget(ui, "Drag Threshold", |ui| {
+            ui.add(
+                DragValue::new(&mut self.device.drag_threshold)
+                    .range(0.0..=30.0)
+                    .suffix(" px"),
+            )
+            .on_hover_text("How far the pointer moves before a click becomes a drag");
+        });
+        labelled_widget(ui, "Wall Snap", |ui| {
+            ui.add(
+                DragValue::new(&mut self.device.wall_snap_distance)
+                    .range(0.0..=50.0)
+                    .suffix(" px"),
+            )
+            .on_hover_text("How close furniture needs to be to a wall to snap flush against it");
+        });
+        labelled_widget(ui, "Floor Opacity", |ui| {
+            ui.add(Slider::new(&mut self.device.floor_opacity, 0.0..=1.0))
+                .on_hover_text("See through floors, for tracing over a background");
+        });
+        combo_box_for_enum(
+            ui,
+            "Grid Visibility",
+            &mut self.device.grid_visibility,
+            "Grid",
+        );
+        combo_box_for_enum(
+            ui,
+            "Furniture Detail",
+            &mut self.device.furniture_detail,
+            "Furniture Detail",
+        );
+        combo_box_for_enum(
+            ui,
+            "Shadow Order",
+            &mut self.device.shadow_order,
+            "Wall Shadows",
+        );
+        ui.collapsing("Camera", |ui| {
+            labelled_widget(ui, "Min Zoom", |ui| {
+                ui.add(
+                    DragValue::new(&mut self.device.zoom_min)
+                        .range(1.0..=self.device.zoom_max)
+                        .suffix(" px/m"),
+                );
+            });
+            labelled_widget(ui, "Max Zoom", |ui| {
+                ui.add(
+                    DragValue::new(&mut self.device.zoom_max)
+                        .range(self.device.zoom_min..=2000.0)
+                        .suffix(" px/m"),
+                );
+            });
+            ui.checkbox(&mut self.device.keep_home_on_screen, "Keep Home On Screen")
+                .on_hover_text("Stop panning before the home leaves the view");
+        });
+        ui.collapsing("Double Click", |ui| {
+            combo_box_for_enum(
+                ui,
+                "Double Click Room",
+                &mut self.device.double_click_room,
+                "Room",
+            );
+            combo_box_for_enum(
+                ui,
+                "Double Click Light",
+                &mut self.device.double_click_light,
+                "Light",
+            );
+            combo_box_for_enum(
+                ui,
+                "Double Click Furniture",
+                &mut self.device.double_click_furniture,
+                "Furniture",
+            );
+        });
         if ui.button("Refresh").clicked() {
             self.edit_mode.enabled = false;
             self.layout = Home::empty();
@@ -194,6 +671,33 @@ impl HomeFlow {
 
     pub fn run_edit_mode(&mut self, response: &egui::Response, ui: &Ui) -> EditResponse {
         if !self.edit_mode.enabled {
+            self.edit_mode.history = EditHistory::default();
+            return EditResponse {
+                used_dragged: false,
+                hovered_id: None,
+                snap_line_x: None,
+                snap_line_y: None,
+            };
+        }
+
+        if self.edit_mode.drawing_room.is_some()
+            || self.edit_mode.ruler.is_some()
+            || self.edit_mode.eyedropper
+        {
+            self.edit_mode.tool_time = self.time;
+        }
+
+        let toggle_ruler = ui.input(|i| i.key_pressed(Key::M))
+            && ui.ctx().memory(|m| m.focused().is_none())
+            && self.edit_mode.drawing_room.is_none();
+        if toggle_ruler {
+            self.edit_mode.ruler = match self.edit_mode.ruler {
+                Some(_) => None,
+                None => Some(Vec::new()),
+            };
+        }
+        if self.edit_mode.ruler.is_some() {
+            self.measure(response, ui);
             return EditResponse {
                 used_dragged: false,
                 hovered_id: None,
@@ -202,8 +706,34 @@ impl HomeFlow {
             };
         }
 
+        if self.edit_mode.eyedropper {
+            self.pick_material(response, ui);
+            return EditResponse {
+                used_dragged: false,
+                hovered_id: None,
+                snap_line_x: None,
+                snap_line_y: None,
+            };
+        }
+
+        if self.edit_mode.drawing_room.is_some() {
+            return EditResponse {
+                used_dragged: self.draw_room(response, ui),
+                hovered_id: None,
+                snap_line_x: None,
+                snap_line_y: None,
+            };
+        }
+
         let snap_enabled = !ui.input(|i| i.modifiers.shift); // Shift to disable snap
+        let array_enabled = ui.input(|i| i.modifiers.alt); // Alt to array furniture
+        let (units, precision) = (self.device.units, self.device.coordinate_precision);
         let hover_details = self.hover_select(response, ui);
+        self.handle_clipboard(ui, hover_details.as_ref());
+        // A right click that didn't turn into a pan opens the context menu
+        if response.clicked_by(PointerButton::Secondary) {
+            self.open_context_menu();
+        }
 
         // Cursor for hovered
         let can_drag = hover_details.as_ref().is_some_and(|h| h.can_drag);
@@ -254,40 +784,87 @@ impl HomeFlow {
                     start_pos: hover_details.pos,
                     start_size: hover_details.size,
                     start_rotation: hover_details.rotation,
+                    rotation_offset: 0,
+                    past_threshold: false,
+                    array: (array_enabled
+                        && hover_details.object_type == ObjectType::Furniture
+                        && matches!(hover_details.manipulation_type, ManipulationType::Move))
+                    .then(Vec::new),
+                    group: if hover_details.object_type == ObjectType::Room {
+                        self.layout
+                            .rooms
+                            .iter()
+                            .filter(|r| {
+                                r.id != hover_details.id
+                                    && !r.locked
+                                    && self.edit_mode.selected_ids.contains(&r.id)
+                            })
+                            .map(|r| (r.id, r.pos))
+                            .collect()
+                    } else {
+                        Vec::new()
+                    },
                 });
             }
         }
 
-        let mut used_dragged = false;
+        let mut used_dragged = self.box_select(response, ui, hover_details.is_none());
         let mut snap_line_x = None;
         let mut snap_line_y = None;
 
+        // R rotates dragged furniture, shift+R the other way
+        if let Some(drag_data) = self.edit_mode.drag_data.as_mut().filter(|d| {
+            d.object_type == ObjectType::Furniture
+                && d.array.is_none()
+                && matches!(d.manipulation_type, ManipulationType::Move)
+        }) {
+            let (rotate, reverse) = ui.input(|i| (i.key_pressed(Key::R), i.modifiers.shift));
+            if rotate {
+                let step = if reverse { -ROTATE_STEP } else { ROTATE_STEP };
+                drag_data.rotation_offset = (drag_data.rotation_offset + step) % 360;
+            }
+        }
+
+        // Small movements while clicking to select shouldn't nudge the object, anything egui
+        // still counts as a click is never a drag
+        if let Some(start) = self.edit_mode.drag_data.as_ref().map(|d| d.mouse_start_pos) {
+            let moved = self.mouse_pos.distance(self.world_to_screen(start));
+            let max_click_dist = ui.ctx().options(|o| o.input_options.max_click_dist);
+            let threshold = self.device.drag_threshold.max(f64::from(max_click_dist));
+            if let Some(drag_data) = &mut self.edit_mode.drag_data {
+                drag_data.past_threshold |= moved > threshold;
+            }
+        }
+
         if response.dragged_by(PointerButton::Primary) {
-            if let Some(drag_data) = &self.edit_mode.drag_data {
+            if self
+                .edit_mode
+                .drag_data
+                .as_ref()
+                .is_some_and(|d| !d.past_threshold)
+            {
+                used_dragged = true;
+            } else if self
+                .edit_mode
+                .drag_data
+                .as_ref()
+                .is_some_and(|d| d.array.is_some())
+            {
+                used_dragged = true;
+                self.handle_array_drag(ui, snap_enabled);
+            } else if let Some(drag_data) = &self.edit_mode.drag_data {
                 used_dragged = true;
 
                 let (new_pos, new_rotation, snap_x, snap_y) =
                     self.handle_drag(drag_data, snap_enabled);
-                Window::new("Dragging Info")
-                    .fixed_pos(vec2_to_egui_pos(
-                        self.world_to_screen(self.mouse_pos_world) + vec2(0.0, -60.0),
-                    ))
-                    .fixed_size([200.0, 0.0])
-                    .pivot(Align2::CENTER_CENTER)
-                    .title_bar(false)
-                    .resizable(false)
-                    .interactable(false)
-                    .show(ui.ctx(), |ui| {
-                        ui.label(format!("Pos: ({:.3}m, {:.3}m)", new_pos.x, new_pos.y));
-                        if drag_data.start_size.length() > 0.0 {
-                            ui.label(format!(
-                                "Size: ({:.3}m, {:.3}m)",
-                                drag_data.start_size.x, drag_data.start_size.y
-                            ));
-                        }
-                    });
-
                 let delta = new_pos - drag_data.start_pos;
+                let mut new_size = drag_data.start_size;
+                // Stacked furniture is stored relative to what it sits on
+                let stack_parent = self.layout.rooms.iter().find_map(|room| {
+                    let furniture = room.furniture.iter().find(|f| f.id == drag_data.id)?;
+                    room.stack_parent(furniture)
+                        .map(|parent| room.furniture_placement(parent))
+                });
                 for room in &mut self.layout.rooms {
                     if drag_data.id == room.id {
                         apply_standard_transform(
@@ -298,9 +875,40 @@ impl HomeFlow {
                             new_pos,
                             Vec2::ZERO,
                         );
+                        new_size = room.size;
+                        if !matches!(drag_data.manipulation_type, ManipulationType::Move) {
+                            room.snap_openings();
+                        }
+                    } else if let Some((_, start_pos)) =
+                        drag_data.group.iter().find(|(id, _)| *id == room.id)
+                    {
+                        room.pos = *start_pos + delta;
                     } else {
+                        let mut operation_moved = false;
                         for operation in &mut room.operations {
-                            if operation.id == drag_data.id {
+                            if operation.id != drag_data.id {
+                                continue;
+                            }
+                            operation_moved = true;
+                            let is_start = match drag_data.manipulation_type {
+                                ManipulationType::ResizeLeft => Some(true),
+                                ManipulationType::ResizeRight => Some(false),
+                                _ => None,
+                            };
+                            if let Some(is_start) =
+                                is_start.filter(|_| operation.action == Action::Wall)
+                            {
+                                // Wall ends move freely, pivoting around the other end
+                                let direction =
+                                    rotate_point_i32(vec2(1.0, 0.0), -drag_data.start_rotation);
+                                let length = drag_data.start_size.x;
+                                let fixed = if is_start {
+                                    drag_data.start_pos + direction * length
+                                } else {
+                                    drag_data.start_pos - direction * length
+                                };
+                                operation.move_wall_end(room.pos, fixed, new_pos, is_start);
+                            } else {
                                 apply_standard_transform(
                                     &mut operation.pos,
                                     &mut operation.size,
@@ -310,6 +918,11 @@ impl HomeFlow {
                                     room.pos,
                                 );
                             }
+                            new_size = operation.size;
+                        }
+                        // Keep openings on the walls the operation reshaped
+                        if operation_moved {
+                            room.snap_openings();
                         }
                         for zone in &mut room.zones {
                             if zone.id == drag_data.id {
@@ -321,6 +934,7 @@ impl HomeFlow {
                                     new_pos,
                                     room.pos,
                                 );
+                                new_size = zone.size;
                             }
                         }
                         for opening in &mut room.openings {
@@ -336,148 +950,1166 @@ impl HomeFlow {
                         }
                         for furniture in &mut room.furniture {
                             if furniture.id == drag_data.id {
+                                let (origin, parent_rotation) =
+                                    stack_parent.unwrap_or((room.pos, 0));
                                 apply_standard_transform(
                                     &mut furniture.pos,
                                     &mut furniture.size,
                                     drag_data,
                                     delta,
                                     new_pos,
-                                    room.pos,
+                                    origin,
                                 );
+                                furniture.pos = rotate_point_i32(furniture.pos, parent_rotation);
+                                furniture.rotation = new_rotation - parent_rotation;
+                                new_size = furniture.size;
                             }
                         }
                     }
                 }
+
+                let resizing = !matches!(drag_data.manipulation_type, ManipulationType::Move);
+                Window::new("Dragging Info")
+                    .fixed_pos(vec2_to_egui_pos(
+                        self.world_to_screen(self.mouse_pos_world) + vec2(0.0, -60.0),
+                    ))
+                    .fixed_size([200.0, 0.0])
+                    .pivot(Align2::CENTER_CENTER)
+                    .title_bar(false)
+                    .resizable(false)
+                    .interactable(false)
+                    .show(ui.ctx(), |ui| {
+                        ui.label(format!(
+                            "Pos: ({}, {})",
+                            format_length(new_pos.x, units, precision),
+                            format_length(new_pos.y, units, precision)
+                        ));
+                        if new_size.length() > 0.0 {
+                            ui.label(format!(
+                                "Size: ({}, {})",
+                                format_length(new_size.x, units, precision),
+                                format_length(new_size.y, units, precision)
+                            ));
+                        }
+                        if resizing && is_extreme_aspect_ratio(new_size) {
+                            ui.colored_label(
+                                Color32::from_rgb(255, 190, 60),
+                                "⚠ Very thin shape, check this resize was intended",
+                            );
+                        }
+                    });
+
                 snap_line_x = snap_x;
                 snap_line_y = snap_y;
             }
         }
-        if !mouse_down {
-            self.edit_mode.drag_data = None;
+        if !mouse_down {
+            self.edit_mode.drag_data = None;
+        }
+        if self.edit_mode.drag_data.is_none() {
+            self.nudge_selected(ui);
+        }
+
+        let mut window_open: bool = true;
+        match *self.edit_mode.selected_ids.as_slice() {
+            [] => {}
+            [selected_id] => {
+                Window::new(format!("Edit {selected_id}"))
+                    .default_pos(vec2_to_egui_pos(vec2(self.canvas_center.x, 20.0)))
+                    .fixed_size([0.0, 0.0])
+                    .pivot(Align2::CENTER_TOP)
+                    .movable(true)
+                    .resizable(false)
+                    .collapsible(true)
+                    .open(&mut window_open)
+                    .show(ui.ctx(), |ui| self.edit_widgets(ui, selected_id));
+            }
+            _ => {
+                Window::new("Edit Selection")
+                    .default_pos(vec2_to_egui_pos(vec2(self.canvas_center.x, 20.0)))
+                    .fixed_size([0.0, 0.0])
+                    .pivot(Align2::CENTER_TOP)
+                    .movable(true)
+                    .resizable(false)
+                    .collapsible(true)
+                    .open(&mut window_open)
+                    .show(ui.ctx(), |ui| self.selection_summary(ui));
+            }
+        }
+        if !window_open {
+            self.edit_mode.selected_ids.clear();
+            self.edit_mode.selected_type = None;
+        }
+
+        let single_room_selected = self.edit_mode.selected_ids.len() == 1;
+        Window::new("Edit Materials".to_string())
+            .fixed_pos(vec2_to_egui_pos(vec2(
+                self.canvas_center.x,
+                self.canvas_center.y,
+            )))
+            .fixed_size([300.0, 0.0])
+            .pivot(Align2::CENTER_CENTER)
+            .open(&mut self.edit_mode.material_editor_open)
+            .show(ui.ctx(), |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.horizontal(|ui| {
+                        let materials = &self.layout.materials;
+                        let textures = &self.textures;
+                        edit_option(
+                            ui,
+                            "Wall Material",
+                            &mut self.layout.wall_material,
+                            || {
+                                materials
+                                    .first()
+                                    .map(|m| m.name.clone())
+                                    .unwrap_or_default()
+                            },
+                            |ui, wall_material| {
+                                combo_box_for_materials(ui, "Walls", materials, textures, wall_material);
+                            },
+                        );
+                        ui.add_enabled_ui(single_room_selected, |ui| {
+                            ui.toggle_value(&mut self.edit_mode.eyedropper, "Eyedropper")
+                                .on_hover_text(
+                                    "Click a room or furniture to copy its material onto the selected room",
+                                )
+                                .on_disabled_hover_text("Select a room to copy a material onto");
+                        });
+                    });
+                    ui.separator();
+
+                    let num_objects = self.layout.materials.len();
+                    let mut alterations = vec![AlterObject::None; num_objects];
+                    for (index, material) in self.layout.materials.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label("Material");
+                            TextEdit::singleline(&mut material.name)
+                                .min_size(egui::vec2(100.0, 0.0))
+                                .desired_width(0.0)
+                                .show(ui);
+                            combo_box_for_enum(
+                                ui,
+                                format!("Material {index}"),
+                                &mut material.material,
+                                "",
+                            );
+                            ui.color_edit_button_srgba_unmultiplied(material.tint.mut_array());
+                            labelled_widget(ui, "Scale", |ui| {
+                                ui.add(
+                                    Slider::new(&mut material.scale, 0.1..=10.0)
+                                        .logarithmic(true)
+                                        .suffix("x"),
+                                );
+                            });
+                            labelled_widget(ui, "Angle", |ui| {
+                                ui.add(
+                                    DragValue::new(&mut material.rotation)
+                                        .range(0..=359)
+                                        .suffix("°"),
+                                )
+                                .on_hover_text("Turn the texture and tiles, to run planks along a room");
+                            });
+
+                            edit_option(
+                                ui,
+                                "Tiles",
+                                &mut material.tiles,
+                                TileOptions::default,
+                                |ui, tiles| {
+                                    labelled_widget(ui, "Spacing", |ui| {
+                                        ui.add(
+                                            DragValue::new(&mut tiles.spacing)
+                                                .speed(0.1)
+                                                .range(0.01..=5.0)
+                                                .suffix("m")
+                                                .fixed_decimals(precision),
+                                        );
+                                    });
+                                    combo_box_for_enum(
+                                        ui,
+                                        format!("Tile Pattern {index}"),
+                                        &mut tiles.pattern,
+                                        "Pattern",
+                                    );
+                                    ui.checkbox(&mut tiles.grout, "Grout");
+                                    if tiles.grout {
+                                        labelled_widget(ui, "Width", |ui| {
+                                            ui.add(
+                                                DragValue::new(&mut tiles.grout_width)
+                                                    .speed(0.1)
+                                                    .range(0.01..=5.0)
+                                                    .suffix("m")
+                                                    .fixed_decimals(precision),
+                                            );
+                                        });
+                                        labelled_widget(ui, "", |ui| {
+                                            ui.color_edit_button_srgba_unmultiplied(
+                                                tiles.grout_color.mut_array(),
+                                            );
+                                        });
+                                    }
+                                    edit_option(
+                                        ui,
+                                        "Alternate",
+                                        &mut tiles.alternate_tint,
+                                        || Color::from_rgb(200, 200, 200),
+                                        |ui, tint| {
+                                            ui.color_edit_button_srgba_unmultiplied(
+                                                tint.mut_array(),
+                                            );
+                                        },
+                                    );
+                                },
+                            );
+
+                            if ui.button("Delete").clicked() {
+                                alterations[index] = AlterObject::Delete;
+                            }
+                            if index > 0 && ui.button("^").clicked() {
+                                alterations[index] = AlterObject::MoveUp;
+                            }
+                            if index < num_objects - 1 && ui.button("v").clicked() {
+                                alterations[index] = AlterObject::MoveDown;
+                            }
+                        });
+                    }
+                    for (index, alteration) in alterations.into_iter().enumerate().rev() {
+                        match alteration {
+                            AlterObject::Delete => {
+                                // Move anything still using the material onto the first remaining
+                                // one, undo restores both the material and its references
+                                let removed = self.layout.materials.remove(index);
+                                if let Some(replacement) =
+                                    self.layout.materials.first().map(|m| m.name.clone())
+                                {
+                                    let changed =
+                                        self.layout.replace_material(&removed.name, &replacement);
+                                    if changed > 0 {
+                                        self.toasts
+                                            .lock()
+                                            .info(format!(
+                                                "Moved {changed} uses of {} to {replacement}",
+                                                removed.name
+                                            ))
+                                            .duration(Some(Duration::from_secs(3)));
+                                    }
+                                }
+                            }
+                            AlterObject::MoveUp => {
+                                self.layout.materials.swap(index, index - 1);
+                            }
+                            AlterObject::MoveDown => {
+                                self.layout.materials.swap(index, index + 1);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // Add button
+                    if ui.button("Add Material").clicked() {
+                        self.layout.materials.push(GlobalMaterial {
+                            name: "New Material".to_string(),
+                            material: Material::Empty,
+                            tint: Color::WHITE,
+                            scale: 1.0,
+                            rotation: 0,
+                            tiles: None,
+                        });
+                    }
+                });
+            });
+
+        self.import_rooms_window(ui);
+        self.layout_warnings_window(ui);
+        self.search_window(ui);
+        self.name_labels(response, ui);
+        self.track_history(ui);
+
+        EditResponse {
+            used_dragged,
+            hovered_id: hover_details.map(|h| h.id),
+            snap_line_x,
+            snap_line_y,
+        }
+    }
+
+    /// Arrow keys move the selected objects along the screen axes, finer with shift held
+    fn nudge_selected(&mut self, ui: &Ui) {
+        if self.edit_mode.selected_ids.is_empty() || ui.ctx().memory(|m| m.focused().is_some()) {
+            return;
+        }
+        let (screen_direction, fine) = ui.input(|i| {
+            let mut direction = Vec2::ZERO;
+            for (key, step) in [
+                (Key::ArrowLeft, vec2(-1.0, 0.0)),
+                (Key::ArrowRight, vec2(1.0, 0.0)),
+                (Key::ArrowUp, vec2(0.0, -1.0)),
+                (Key::ArrowDown, vec2(0.0, 1.0)),
+            ] {
+                if i.key_pressed(key) {
+                    direction += step;
+                }
+            }
+            (direction, i.modifiers.shift)
+        });
+        if screen_direction == Vec2::ZERO {
+            return;
+        }
+        // Follow the view rotation so up is always up on screen
+        let world_direction = (self.screen_to_world(self.canvas_center + screen_direction)
+            - self.screen_to_world(self.canvas_center))
+        .normalize_or_zero();
+        let delta = world_direction * if fine { NUDGE_STEP_FINE } else { NUDGE_STEP };
+
+        let selected_ids = &self.edit_mode.selected_ids;
+        for room in &mut self.layout.rooms {
+            if selected_ids.contains(&room.id) {
+                if !room.locked {
+                    room.pos += delta;
+                }
+                continue;
+            }
+            for operation in &mut room.operations {
+                if selected_ids.contains(&operation.id) {
+                    operation.pos += delta;
+                }
+            }
+            for zone in &mut room.zones {
+                if selected_ids.contains(&zone.id) {
+                    zone.pos += delta;
+                }
+            }
+            for opening in &mut room.openings {
+                if selected_ids.contains(&opening.id) {
+                    opening.pos += delta;
+                }
+            }
+            for light in &mut room.lights {
+                if selected_ids.contains(&light.id) {
+                    light.pos += delta;
+                }
+            }
+            // Stacked furniture is stored relative to what it sits on
+            let parent_rotations: Vec<i32> = room
+                .furniture
+                .iter()
+                .map(|f| {
+                    room.stack_parent(f)
+                        .map_or(0, |p| room.furniture_placement(p).1)
+                })
+                .collect();
+            for (furniture, parent_rotation) in room.furniture.iter_mut().zip(parent_rotations) {
+                if selected_ids.contains(&furniture.id) && !furniture.locked {
+                    furniture.pos += rotate_point_i32(delta, parent_rotation);
+                }
+            }
+        }
+    }
+
+    /// Copy the material of the top furniture or room under a click onto the selected room,
+    /// escape cancels
+    fn pick_material(&mut self, response: &egui::Response, ui: &Ui) {
+        ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+        let [selected_id] = *self.edit_mode.selected_ids.as_slice() else {
+            self.edit_mode.eyedropper = false;
+            return;
+        };
+        if ui.input(|i| i.key_pressed(Key::Escape)) {
+            self.edit_mode.eyedropper = false;
+            return;
+        }
+        if !response.clicked() {
+            let window_pos = self.world_to_screen(self.mouse_pos_world) + vec2(0.0, -40.0);
+            Window::new("Eyedropper")
+                .fixed_pos(vec2_to_egui_pos(window_pos))
+                .pivot(Align2::CENTER_CENTER)
+                .title_bar(false)
+                .resizable(false)
+                .interactable(false)
+                .show(ui.ctx(), |ui| {
+                    ui.label("Click to copy a material, escape to cancel");
+                });
+            return;
+        }
+        self.edit_mode.eyedropper = false;
+        let mouse_pos = self.mouse_pos_world;
+        let picked = self.layout.rooms.iter().rev().find_map(|room| {
+            room.furniture
+                .iter()
+                .rev()
+                .find(|f| f.has_material() && room.furniture_contains(f, mouse_pos))
+                .map(|f| f.material.clone())
+                .or_else(|| room.contains(mouse_pos).then(|| room.material.clone()))
+        });
+        let Some(material) = picked else {
+            return;
+        };
+        if let Some(room) = self.layout.rooms.iter_mut().find(|r| r.id == selected_id) {
+            self.toasts
+                .lock()
+                .info(format!("Set {} to {material}", room.name))
+                .duration(Some(Duration::from_secs(2)));
+            room.material = material;
+        }
+    }
+
+    /// Clicks chain ruler points together, showing each segment and the running total
+    fn measure(&mut self, response: &egui::Response, ui: &Ui) {
+        let (units, precision) = (self.device.units, self.device.coordinate_precision);
+        let (cancel, clear) =
+            ui.input(|i| (i.key_pressed(Key::Escape), i.key_pressed(Key::Backspace)));
+        let window_pos = self.world_to_screen(self.mouse_pos_world) + vec2(0.0, -60.0);
+        let Some(points) = &mut self.edit_mode.ruler else {
+            return;
+        };
+        if cancel {
+            self.edit_mode.ruler = None;
+            return;
+        }
+        if clear {
+            points.clear();
+        }
+        if response.clicked() {
+            points.push(self.mouse_pos_world);
+        }
+        ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+
+        let total = points
+            .iter()
+            .chain(std::iter::once(&self.mouse_pos_world))
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|pair| pair[0].distance(*pair[1]))
+            .sum::<f64>();
+        Window::new("Ruler")
+            .fixed_pos(vec2_to_egui_pos(window_pos))
+            .fixed_size([200.0, 0.0])
+            .pivot(Align2::CENTER_CENTER)
+            .title_bar(false)
+            .resizable(false)
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                if points.is_empty() {
+                    ui.label("Click to start measuring");
+                } else {
+                    ui.label(format!("Total: {}", format_length(total, units, precision)));
+                }
+                ui.label("Backspace clears, M or escape to exit");
+            });
+    }
+
+    /// Issues from the last layout check, clicking one selects and zooms to the room holding it
+    fn layout_warnings_window(&mut self, ui: &Ui) {
+        let Some(warnings) = &self.edit_mode.layout_warnings else {
+            return;
+        };
+        let mut open = true;
+        let mut recheck = false;
+        let mut clicked = None;
+        Window::new("Layout Check")
+            .default_pos(vec2_to_egui_pos(vec2(
+                self.canvas_center.x * 2.0 - 20.0,
+                20.0,
+            )))
+            .pivot(Align2::RIGHT_TOP)
+            .resizable(false)
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                if warnings.is_empty() {
+                    ui.label("No issues found");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for warning in warnings {
+                            let label = ui.add_enabled(
+                                warning.room_id.is_some(),
+                                Button::new(&warning.message).frame(false),
+                            );
+                            if label.on_hover_text(warning.id.to_string()).clicked() {
+                                clicked = warning.room_id;
+                            }
+                        }
+                    });
+                recheck = ui.button("Recheck").clicked();
+            });
+        if !open {
+            self.edit_mode.layout_warnings = None;
+        } else if recheck {
+            self.edit_mode.layout_warnings = Some(self.layout.validate());
+        }
+        if let Some(room) = clicked.and_then(|id| self.layout.rooms.iter().find(|r| r.id == id)) {
+            let (min, max) = room.bounds();
+            self.edit_mode.selected_ids = vec![room.id];
+            self.edit_mode.selected_type = Some(ObjectType::Room);
+            self.zoom_to(min, max);
+        }
+    }
+
+    /// Objects whose name or entity id contains the query, with the room holding them, their own
+    /// id and the world bounds to frame. The object being renamed in the find window stays listed
+    fn search_layout(&self, query: &str) -> Vec<(String, Uuid, Uuid, Vec2, Vec2)> {
+        const LIGHT_EXTENT: f64 = 0.25;
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let matches = |fields: &[&str]| {
+            fields
+                .iter()
+                .any(|field| field.to_lowercase().contains(&query))
+        };
+        let renaming = |id: Uuid| {
+            self.edit_mode
+                .renaming
+                .as_ref()
+                .is_some_and(|r| r.in_find && r.id == id)
+        };
+        let mut results = Vec::new();
+        for room in &self.layout.rooms {
+            if matches(&[&room.name]) || renaming(room.id) {
+                let (min, max) = room.bounds();
+                results.push((room.name.clone(), room.id, room.id, min, max));
+            }
+            for furniture in &room.furniture {
+                if matches(&[
+                    &furniture.name,
+                    &furniture.power_draw_entity,
+                    &furniture.state_entity,
+                ]) || renaming(furniture.id)
+                {
+                    let pos = room.furniture_placement(furniture).0;
+                    let extent = Vec2::splat(furniture.size.length() / 2.0);
+                    let name = if furniture.name.is_empty() {
+                        furniture.furniture_type.to_string()
+                    } else {
+                        furniture.name.clone()
+                    };
+                    results.push((
+                        format!("{name} in {}", room.name),
+                        room.id,
+                        furniture.id,
+                        pos - extent,
+                        pos + extent,
+                    ));
+                }
+            }
+            for light in &room.lights {
+                if matches(&[&light.name, &light.entity_id]) {
+                    let pos = room.pos + light.pos;
+                    results.push((
+                        format!("{} in {}", light.name, room.name),
+                        room.id,
+                        light.id,
+                        pos - LIGHT_EXTENT,
+                        pos + LIGHT_EXTENT,
+                    ));
+                }
+            }
+            for sensor in &room.sensors {
+                if matches(&[&sensor.display_name, &sensor.entity_id]) {
+                    let pos = room.pos + room.sensors_offset;
+                    results.push((
+                        format!("{} in {}", sensor.display_name, room.name),
+                        room.id,
+                        sensor.id,
+                        pos - LIGHT_EXTENT,
+                        pos + LIGHT_EXTENT,
+                    ));
+                }
+            }
+        }
+        results
+    }
+
+    /// Show the find window with its search box focused
+    fn open_search(&mut self, ctx: &egui::Context) {
+        self.edit_mode.search.get_or_insert_with(String::new);
+        ctx.memory_mut(|m| m.request_focus(egui::Id::new(SEARCH_FIELD_ID)));
+    }
+
+    /// Search box over names and entity ids, picking a result selects its room and centers on it.
+    /// Double clicking a room or furniture result renames it in place
+    fn search_window(&mut self, ui: &Ui) {
+        if ui.input_mut(|i| i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::F)))
+        {
+            self.open_search(ui.ctx());
+        }
+        let Some(query) = &self.edit_mode.search else {
+            return;
+        };
+        let mut query = query.clone();
+        let results = self.search_layout(&query);
+        let mut open = true;
+        let mut clicked = None;
+        let mut start_renaming = None;
+        let mut stop_renaming = false;
+        Window::new("Find")
+            .default_pos(vec2_to_egui_pos(vec2(
+                self.canvas_center.x * 2.0 - 20.0,
+                20.0,
+            )))
+            .pivot(Align2::RIGHT_TOP)
+            .resizable(false)
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                TextEdit::singleline(&mut query)
+                    .id(egui::Id::new(SEARCH_FIELD_ID))
+                    .hint_text("Name or entity id")
+                    .show(ui);
+                if !query.trim().is_empty() && results.is_empty() {
+                    ui.label("No matches");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for (index, (label, _, id, ..)) in results.iter().enumerate() {
+                            match &self.edit_mode.renaming {
+                                Some(renaming) if renaming.in_find && renaming.id == *id => {
+                                    if let Some(name) = object_name_mut(&mut self.layout, *id) {
+                                        stop_renaming =
+                                            rename_field(ui, name, &renaming.original_name);
+                                    }
+                                }
+                                _ => {
+                                    let response = ui.add(Button::new(label).frame(false));
+                                    if response.double_clicked() {
+                                        start_renaming = Some(*id);
+                                    } else if response.clicked() {
+                                        clicked = Some(index);
+                                    }
+                                }
+                            }
+                        }
+                    });
+            });
+        self.edit_mode.search = open.then_some(query);
+        if stop_renaming || !open {
+            self.edit_mode.renaming.take_if(|r| r.in_find);
+        }
+        if let Some(id) = start_renaming {
+            self.start_renaming(id, true);
+        }
+        if let Some(&(_, room_id, _, min, max)) = clicked.and_then(|index| results.get(index)) {
+            self.edit_mode.selected_ids = vec![room_id];
+            self.edit_mode.selected_type = Some(ObjectType::Room);
+            self.zoom_to(min, max);
+        }
+    }
+
+    /// Window to paste a room schedule into, imported rooms are appended and selected
+    fn import_rooms_window(&mut self, ui: &Ui) {
+        let Some(text) = &mut self.edit_mode.import_text else {
+            return;
+        };
+        let mut open = true;
+        let mut import = false;
+        Window::new("Import Rooms")
+            .default_pos(vec2_to_egui_pos(self.canvas_center))
+            .pivot(Align2::CENTER_CENTER)
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ui.ctx(), |ui| {
+                ui.label("CSV rows of name, width, height, x, y or a JSON array with those keys");
+                ui.label(
+                    "Positions are room centers, rooms without one are placed beside the layout",
+                );
+                ui.add(
+                    TextEdit::multiline(text)
+                        .code_editor()
+                        .desired_rows(8)
+                        .hint_text(
+                            "name, width, height, x, y\nKitchen, 4, 3.5\nHall, 1.2, 6, 2, 0",
+                        ),
+                );
+                import = ui.button("Import").clicked();
+            });
+        if !open {
+            self.edit_mode.import_text = None;
+        }
+        if !import {
+            return;
+        }
+
+        let origin = if self.layout.rooms.is_empty() {
+            Vec2::ZERO
+        } else {
+            let (min, max) = self.layout.bounds();
+            vec2(max.x + 1.0, min.y)
+        };
+        let material = self
+            .layout
+            .materials
+            .first()
+            .map(|m| m.name.clone())
+            .unwrap_or_default();
+        let text = self.edit_mode.import_text.as_deref().unwrap_or_default();
+        match parse_room_schedule(text, &material, origin) {
+            Ok(rooms) => {
+                self.toasts
+                    .lock()
+                    .success(format!("Imported {} rooms", rooms.len()))
+                    .duration(Some(Duration::from_secs(3)));
+                self.edit_mode.selected_ids = rooms.iter().map(|r| r.id).collect();
+                self.edit_mode.selected_type = Some(ObjectType::Room);
+                self.layout.rooms.extend(rooms);
+                self.edit_mode.import_text = None;
+            }
+            Err(e) => {
+                self.toasts
+                    .lock()
+                    .error(format!("Import failed: {e}"))
+                    .duration(Some(Duration::from_secs(5)));
+            }
+        }
+    }
+
+    /// Click to place vertices, double click, enter or clicking the first vertex closes the room,
+    /// returns true when the room was closed so the click isn't used again
+    fn draw_room(&mut self, response: &egui::Response, ui: &Ui) -> bool {
+        let (snap, finish, cancel, undo) = ui.input(|i| {
+            (
+                !i.modifiers.shift,
+                i.key_pressed(Key::Enter),
+                i.key_pressed(Key::Escape),
+                i.key_pressed(Key::Backspace),
+            )
+        });
+        let first_distance = self
+            .edit_mode
+            .drawing_room
+            .as_ref()
+            .and_then(|points| points.first())
+            .map(|&first| self.mouse_pos.distance(self.world_to_screen(first)));
+        let Some(points) = &mut self.edit_mode.drawing_room else {
+            return false;
+        };
+        if cancel {
+            self.edit_mode.drawing_room = None;
+            return false;
+        }
+        if undo {
+            points.pop();
+        }
+
+        let mut closing = finish || response.double_clicked();
+        if response.clicked() && !closing {
+            let mut point = self.mouse_pos_world;
+            if snap {
+                point = vec2(point.x.round_factor(10.0), point.y.round_factor(10.0));
+            }
+            let near_first = first_distance.is_some_and(|d| d < 10.0);
+            if near_first && points.len() >= 3 {
+                closing = true;
+            } else if points.last() != Some(&point) {
+                points.push(point);
+            }
+        }
+        if !closing {
+            return false;
+        }
+
+        let points = self.edit_mode.drawing_room.take().unwrap_or_default();
+        if let Some(mut room) = Room::from_freeform("New Room", &points, "") {
+            room.walls = self.device.new_room_walls;
+            room.walls.remove(room.shared_walls(&self.layout.rooms));
+            self.edit_mode.selected_ids = vec![room.id];
+            self.edit_mode.selected_type = Some(ObjectType::Room);
+            self.layout.rooms.push(room);
+        } else {
+            self.toasts
+                .lock()
+                .error("Room needs at least 3 points and can't cross itself")
+                .duration(Some(Duration::from_secs(3)));
+        }
+        true
+    }
+
+    /// Ctrl+C copies the hovered furniture or the selected rooms, Ctrl+V pastes them at the cursor
+    fn handle_clipboard(&mut self, ui: &Ui, hover_details: Option<&HoverDetails>) {
+        if ui.ctx().memory(|m| m.focused().is_some()) {
+            return;
+        }
+        let (copy, pasted) = ui.input(|i| {
+            let copy = i.events.iter().any(|e| matches!(e, egui::Event::Copy));
+            let pasted = i.events.iter().find_map(|e| match e {
+                egui::Event::Paste(text) => Some(text.clone()),
+                _ => None,
+            });
+            (copy, pasted)
+        });
+
+        if copy {
+            let hovered_furniture = hover_details
+                .filter(|h| h.object_type == ObjectType::Furniture)
+                .and_then(|h| {
+                    self.layout
+                        .rooms
+                        .iter()
+                        .flat_map(|r| &r.furniture)
+                        .find(|f| f.id == h.id)
+                });
+            let contents = if let Some(furniture) = hovered_furniture {
+                Some(ClipboardContents::Furniture(Box::new(furniture.clone())))
+            } else if self.edit_mode.selected_ids.is_empty() {
+                None
+            } else {
+                Some(ClipboardContents::Rooms(
+                    self.layout
+                        .rooms
+                        .iter()
+                        .filter(|r| self.edit_mode.selected_ids.contains(&r.id))
+                        .cloned()
+                        .collect(),
+                ))
+            };
+            if let Some(text) = contents.and_then(|c| ron::to_string(&c).ok()) {
+                ui.ctx().copy_text(text);
+            }
+        }
+
+        let Some(contents) = pasted.and_then(|text| ron::from_str(&text).ok()) else {
+            return;
+        };
+        let mouse_pos = self.mouse_pos_world;
+        match contents {
+            ClipboardContents::Rooms(mut rooms) => {
+                // Keep the rooms arrangement, centered on the cursor
+                let center = rooms.iter().map(|r| r.pos).sum::<Vec2>() / rooms.len().max(1) as f64;
+                self.edit_mode.selected_ids.clear();
+                for room in &mut rooms {
+                    room.regenerate_ids();
+                    room.pos = room.pos - center + mouse_pos;
+                    self.edit_mode.selected_ids.push(room.id);
+                }
+                self.edit_mode.selected_type = Some(ObjectType::Room);
+                self.layout.rooms.extend(rooms);
+            }
+            ClipboardContents::Furniture(mut furniture) => {
+                let Some(room) = self
+                    .layout
+                    .rooms
+                    .iter_mut()
+                    .rev()
+                    .find(|r| r.contains(mouse_pos))
+                else {
+                    self.toasts
+                        .lock()
+                        .info("Paste furniture over a room")
+                        .duration(Some(Duration::from_secs(2)));
+                    return;
+                };
+                furniture.id = Uuid::new_v4();
+                furniture.pos = mouse_pos - room.pos;
+                furniture.stack_on = None;
+                room.furniture.push(*furniture);
+            }
+        }
+    }
+
+    /// Shift drag on empty space to add the rooms touching a box to the selection
+    fn box_select(&mut self, response: &egui::Response, ui: &Ui, nothing_hovered: bool) -> bool {
+        let shift_down = ui.input(|i| i.modifiers.shift);
+        if response.drag_started_by(PointerButton::Primary)
+            && shift_down
+            && nothing_hovered
+            && self.edit_mode.drag_data.is_none()
+        {
+            self.edit_mode.box_select = Some(self.mouse_pos_world);
+        }
+        let Some(start) = self.edit_mode.box_select else {
+            return false;
+        };
+        if response.dragged_by(PointerButton::Primary) {
+            return true;
+        }
+
+        let (box_min, box_max) = (
+            start.min(self.mouse_pos_world),
+            start.max(self.mouse_pos_world),
+        );
+        for room in &self.layout.rooms {
+            let (min, max) = room.bounds();
+            let overlaps =
+                min.x < box_max.x && max.x > box_min.x && min.y < box_max.y && max.y > box_min.y;
+            if overlaps && !self.edit_mode.selected_ids.contains(&room.id) {
+                self.edit_mode.selected_ids.push(room.id);
+            }
+        }
+        if !self.edit_mode.selected_ids.is_empty() {
+            self.edit_mode.selected_type = Some(ObjectType::Room);
+        }
+        self.edit_mode.box_select = None;
+        true
+    }
+
+    /// Compact listing shown instead of the editor while several rooms are selected
+    fn selection_summary(&mut self, ui: &mut Ui) {
+        ui.label(format!(
+            "{} rooms selected, drag any of them to move together",
+            self.edit_mode.selected_ids.len()
+        ));
+        let mut deselect = None;
+        for room in &self.layout.rooms {
+            if !self.edit_mode.selected_ids.contains(&room.id) {
+                continue;
+            }
+            ui.horizontal(|ui| {
+                ui.label(&room.name);
+                if ui.small_button("Deselect").clicked() {
+                    deselect = Some(room.id);
+                }
+            });
+        }
+        if let Some(id) = deselect {
+            self.edit_mode
+                .selected_ids
+                .retain(|&selected| selected != id);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Align");
+            for (label, alignment) in [
+                ("Left", Alignment::Left),
+                ("Center", Alignment::Center),
+                ("Right", Alignment::Right),
+                ("Top", Alignment::Top),
+                ("Middle", Alignment::Middle),
+                ("Bottom", Alignment::Bottom),
+            ] {
+                if ui.button(label).clicked() {
+                    self.align_selected(alignment);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Distribute");
+            let enabled = self.edit_mode.selected_ids.len() > 2;
+            for (label, axis) in [("Horizontally", 0), ("Vertically", 1)] {
+                if ui
+                    .add_enabled(enabled, Button::new(label))
+                    .on_disabled_hover_text("Select at least three rooms")
+                    .on_hover_text("Space evenly between the outermost rooms")
+                    .clicked()
+                {
+                    self.distribute_selected(axis);
+                }
+            }
+        });
+        if ui.button("Delete Selected").clicked() {
+            let selected_ids = std::mem::take(&mut self.edit_mode.selected_ids);
+            self.layout.rooms.retain(|r| !selected_ids.contains(&r.id));
+            self.edit_mode.selected_type = None;
+        }
+    }
+
+    /// Bounds of each selected room with its index
+    fn selected_room_bounds(&self) -> Vec<(usize, Vec2, Vec2)> {
+        self.layout
+            .rooms
+            .iter()
+            .enumerate()
+            .filter(|(_, room)| self.edit_mode.selected_ids.contains(&room.id))
+            .map(|(index, room)| {
+                let (min, max) = room.bounds();
+                (index, min, max)
+            })
+            .collect()
+    }
+
+    /// Line up the selected rooms bounds on the outermost edge, or on the center of them all
+    fn align_selected(&mut self, alignment: Alignment) {
+        let bounds = self.selected_room_bounds();
+        let (all_min, all_max) = bounds.iter().fold(
+            (Vec2::INFINITY, Vec2::NEG_INFINITY),
+            |(a, b), (_, min, max)| (a.min(*min), b.max(*max)),
+        );
+        let center = (all_min + all_max) / 2.0;
+        for (index, min, max) in bounds {
+            let room_center = (min + max) / 2.0;
+            let delta = match alignment {
+                Alignment::Left => vec2(all_min.x - min.x, 0.0),
+                Alignment::Center => vec2(center.x - room_center.x, 0.0),
+                Alignment::Right => vec2(all_max.x - max.x, 0.0),
+                Alignment::Top => vec2(0.0, all_max.y - max.y),
+                Alignment::Middle => vec2(0.0, center.y - room_center.y),
+                Alignment::Bottom => vec2(0.0, all_min.y - min.y),
+            };
+            let room = &mut self.layout.rooms[index];
+            if !room.locked {
+                room.pos += delta;
+            }
+        }
+    }
+
+    /// Keep the rooms reaching furthest each way in place and lay the rest out between them in
+    /// order of their centers, with equal gaps between bounds along the axis, 0 for x and 1 for y.
+    /// When they don't fit they're packed against the first room
+    fn distribute_selected(&mut self, axis: usize) {
+        let mut bounds = self.selected_room_bounds();
+        if bounds.len() < 3 {
+            return;
         }
+        let first = bounds.remove(
+            (0..bounds.len())
+                .min_by(|&a, &b| bounds[a].1[axis].total_cmp(&bounds[b].1[axis]))
+                .unwrap_or_default(),
+        );
+        let last = bounds.remove(
+            (0..bounds.len())
+                .max_by(|&a, &b| bounds[a].2[axis].total_cmp(&bounds[b].2[axis]))
+                .unwrap_or_default(),
+        );
+        bounds.sort_by(|a, b| (a.1[axis] + a.2[axis]).total_cmp(&(b.1[axis] + b.2[axis])));
 
-        if let Some(selected_id) = self.edit_mode.selected_id {
-            let mut window_open: bool = true;
-            Window::new(format!("Edit {selected_id}"))
-                .default_pos(vec2_to_egui_pos(vec2(self.canvas_center.x, 20.0)))
-                .fixed_size([0.0, 0.0])
-                .pivot(Align2::CENTER_TOP)
-                .movable(true)
-                .resizable(false)
-                .collapsible(true)
-                .open(&mut window_open)
-                .show(ui.ctx(), |ui| self.edit_widgets(ui, selected_id));
-            if !window_open {
-                self.edit_mode.selected_id = None;
-                self.edit_mode.selected_type = None;
+        let (start, end) = (first.2[axis], last.1[axis]);
+        let total_size: f64 = bounds
+            .iter()
+            .map(|(_, min, max)| max[axis] - min[axis])
+            .sum();
+        let gap = ((end - start - total_size) / (bounds.len() + 1) as f64).max(0.0);
+        let mut cursor = start + gap;
+        for (index, min, max) in bounds {
+            let mut delta = Vec2::ZERO;
+            delta[axis] = cursor - min[axis];
+            let room = &mut self.layout.rooms[index];
+            if !room.locked {
+                room.pos += delta;
             }
+            cursor += max[axis] - min[axis] + gap;
         }
+    }
 
-        Window::new("Edit Materials".to_string())
-            .fixed_pos(vec2_to_egui_pos(vec2(
-                self.canvas_center.x,
-                self.canvas_center.y,
-            )))
-            .fixed_size([300.0, 0.0])
-            .pivot(Align2::CENTER_CENTER)
-            .open(&mut self.edit_mode.material_editor_open)
-            .show(ui.ctx(), |ui| {
-                ui.vertical_centered(|ui| {
-                    let num_objects = self.layout.materials.len();
-                    let mut alterations = vec![AlterObject::None; num_objects];
-                    for (index, material) in self.layout.materials.iter_mut().enumerate() {
-                        ui.horizontal(|ui| {
-                            ui.label("Material");
-                            TextEdit::singleline(&mut material.name)
-                                .min_size(egui::vec2(100.0, 0.0))
-                                .desired_width(0.0)
-                                .show(ui);
-                            combo_box_for_enum(
-                                ui,
-                                format!("Material {index}"),
-                                &mut material.material,
-                                "",
-                            );
-                            ui.color_edit_button_srgba_unmultiplied(material.tint.mut_array());
+    /// Push an undo entry whenever the layout settles into a new state, so a drag or typing
+    /// only records once released and no-op drags record nothing
+    fn track_history(&mut self, ui: &Ui) {
+        let settled =
+            !ui.input(|i| i.pointer.any_down()) && ui.ctx().memory(|m| m.focused().is_none());
+        if !settled {
+            return;
+        }
 
-                            edit_option(
-                                ui,
-                                "Tiles",
-                                &mut material.tiles,
-                                TileOptions::default,
-                                |ui, tiles| {
-                                    labelled_widget(ui, "Spacing", |ui| {
-                                        ui.add(
-                                            DragValue::new(&mut tiles.spacing)
-                                                .speed(0.1)
-                                                .range(0.01..=5.0)
-                                                .suffix("m"),
-                                        );
-                                    });
-                                    labelled_widget(ui, "Width", |ui| {
-                                        ui.add(
-                                            DragValue::new(&mut tiles.grout_width)
-                                                .speed(0.1)
-                                                .range(0.01..=5.0)
-                                                .suffix("m"),
-                                        );
-                                    });
-                                    labelled_widget(ui, "", |ui| {
-                                        ui.color_edit_button_srgba_unmultiplied(
-                                            tiles.grout_color.mut_array(),
-                                        );
-                                    });
-                                },
-                            );
+        let (redo, undo) = ui.input_mut(|i| {
+            (
+                i.consume_shortcut(&KeyboardShortcut::new(
+                    Modifiers::COMMAND | Modifiers::SHIFT,
+                    Key::Z,
+                )) || i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Y)),
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Z)),
+            )
+        });
+        if redo {
+            self.redo();
+        } else if undo {
+            self.undo();
+        }
 
-                            if ui.button("Delete").clicked() {
-                                alterations[index] = AlterObject::Delete;
-                            }
-                            if index > 0 && ui.button("^").clicked() {
-                                alterations[index] = AlterObject::MoveUp;
-                            }
-                            if index < num_objects - 1 && ui.button("v").clicked() {
-                                alterations[index] = AlterObject::MoveDown;
+        if let Ok(current) = bincode::serialize(&self.layout) {
+            self.edit_mode.history.record(current);
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some(layout) = self
+            .edit_mode
+            .history
+            .undo()
+            .and_then(|snapshot| bincode::deserialize(snapshot).ok())
+        {
+            self.layout = layout;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(layout) = self
+            .edit_mode
+            .history
+            .redo()
+            .and_then(|snapshot| bincode::deserialize(snapshot).ok())
+        {
+            self.layout = layout;
+        }
+    }
+
+    /// Room name labels on the map, double click to rename in place
+    /// Names of the rooms and selected furniture drawn over the canvas, double clicking one on
+    /// the canvas edits it in place
+    fn name_labels(&mut self, response: &egui::Response, ui: &Ui) {
+        let mut labels = Vec::new();
+        for room in &self.layout.rooms {
+            labels.push((room.id, room.pos, room.name.clone()));
+            for furniture in &room.furniture {
+                if self.edit_mode.selected_ids.contains(&furniture.id) {
+                    let name = if furniture.name.is_empty() {
+                        furniture.furniture_type.to_string()
+                    } else {
+                        furniture.name.clone()
+                    };
+                    labels.push((furniture.id, room.furniture_placement(furniture).0, name));
+                }
+            }
+        }
+
+        let painter = ui
+            .ctx()
+            .layer_painter(egui::LayerId::new(
+                egui::Order::Middle,
+                ui.id().with("name_labels"),
+            ))
+            .with_clip_rect(response.rect);
+        let color = ui.visuals().text_color();
+        let double_click_pos = response
+            .interact_pointer_pos()
+            .filter(|_| response.double_clicked());
+        let mut start_renaming = None;
+        for (id, pos, name) in labels {
+            let pos = self.world_to_screen_pos(pos);
+            match &self.edit_mode.renaming {
+                Some(renaming) if renaming.id == id && !renaming.in_find => {
+                    let original_name = renaming.original_name.clone();
+                    let mut stop_renaming = true;
+                    egui::Area::new(ui.id().with(("rename", id)))
+                        .fixed_pos(pos)
+                        .pivot(Align2::CENTER_CENTER)
+                        .show(ui.ctx(), |ui| {
+                            if let Some(name) = object_name_mut(&mut self.layout, id) {
+                                stop_renaming = rename_field(ui, name, &original_name);
                             }
                         });
+                    if stop_renaming {
+                        self.edit_mode.renaming = None;
                     }
-                    for (index, alteration) in alterations.into_iter().enumerate().rev() {
-                        match alteration {
-                            AlterObject::Delete => {
-                                self.layout.materials.remove(index);
-                            }
-                            AlterObject::MoveUp => {
-                                self.layout.materials.swap(index, index - 1);
-                            }
-                            AlterObject::MoveDown => {
-                                self.layout.materials.swap(index, index + 1);
-                            }
-                            _ => {}
-                        }
+                }
+                _ => {
+                    let galley = painter.layout_no_wrap(name, egui::FontId::default(), color);
+                    let rect = Align2::CENTER_CENTER.anchor_size(pos, galley.size());
+                    painter.galley(rect.min, galley, color);
+                    if double_click_pos.is_some_and(|click| rect.contains(click)) {
+                        start_renaming = Some(id);
                     }
+                }
+            }
+        }
+        if let Some(id) = start_renaming {
+            self.start_renaming(id, false);
+        }
+    }
 
-                    // Add button
-                    if ui.button("Add Material").clicked() {
-                        self.layout.materials.push(GlobalMaterial {
-                            name: "New Material".to_string(),
-                            material: Material::Empty,
-                            tint: Color::WHITE,
-                            tiles: None,
-                        });
-                    }
-                });
+    /// Begin editing a room or furniture name in place, on the canvas or in the find window
+    fn start_renaming(&mut self, id: Uuid, in_find: bool) {
+        if let Some(name) = object_name_mut(&mut self.layout, id) {
+            self.edit_mode.renaming = Some(RenameDetails {
+                id,
+                original_name: name.clone(),
+                in_find,
             });
-
-        EditResponse {
-            used_dragged,
-            hovered_id: hover_details.map(|h| h.id),
-            snap_line_x,
-            snap_line_y,
         }
     }
 
     fn edit_widgets(&mut self, ui: &mut Ui, selected_id: Uuid) {
         if self.edit_mode.selected_type.unwrap() == ObjectType::Room {
+            self.get_areas();
             let room_and_index = self.layout.rooms.iter_mut().enumerate().find_map(|obj| {
                 if obj.1.id == selected_id {
                     Some(obj)
@@ -486,22 +2118,45 @@ impl HomeFlow {
                 }
             });
             if let Some((index, room)) = room_and_index {
-                let alter_type = room_edit_widgets(ui, &self.layout.materials, room);
+                let alter_type = room_edit_widgets(
+                    ui,
+                    &self.layout.materials,
+                    &self.textures,
+                    &mut self.edit_mode.recent_furniture,
+                    self.device.units,
+                    self.device.coordinate_precision,
+                    room,
+                );
+                if room_area_widgets(ui, self.hass_areas.as_deref(), room) {
+                    self.hass_areas = None;
+                }
+                let (room_type, material) = (room.room_type, room.material.clone());
                 match alter_type {
                     AlterObject::Delete => {
                         self.layout.rooms.retain(|r| r.id != selected_id);
-                        self.edit_mode.selected_id = None;
+                        self.edit_mode.selected_ids.clear();
                         self.edit_mode.selected_type = None;
                     }
-                    AlterObject::MoveUp => {
-                        if index < self.layout.rooms.len() - 1 {
-                            self.layout.rooms.swap(index, index + 1);
-                        }
+                    AlterObject::MoveUp if index < self.layout.rooms.len() - 1 => {
+                        self.layout.rooms.swap(index, index + 1);
                     }
-                    AlterObject::MoveDown => {
-                        if index > 0 {
-                            self.layout.rooms.swap(index, index - 1);
-                        }
+                    AlterObject::MoveDown if index > 0 => {
+                        self.layout.rooms.swap(index, index - 1);
+                    }
+                    AlterObject::BringToFront => {
+                        let room = self.layout.rooms.remove(index);
+                        self.layout.rooms.push(room);
+                    }
+                    AlterObject::SendToBack => {
+                        let room = self.layout.rooms.remove(index);
+                        self.layout.rooms.insert(0, room);
+                    }
+                    AlterObject::ApplyMaterialToType => {
+                        let changed = self.layout.apply_material_to_type(room_type, &material);
+                        self.toasts
+                            .lock()
+                            .info(format!("Applied {material} to {changed} {room_type} rooms"))
+                            .duration(Some(Duration::from_secs(2)));
                     }
                     _ => {}
                 }
@@ -510,12 +2165,134 @@ impl HomeFlow {
     }
 }
 
+/// Pick the rooms Home Assistant area and add its lights and sensors that the room doesn't have,
+/// returns true when the areas should be fetched again
+fn room_area_widgets(ui: &mut egui::Ui, areas: Option<&[HAArea]>, room: &mut Room) -> bool {
+    ui.separator();
+    let mut refresh = false;
+    let area = room
+        .ha_area_id
+        .as_ref()
+        .and_then(|id| areas?.iter().find(|area| &area.area_id == id));
+    ui.horizontal(|ui| {
+        labelled_widget(ui, "Home Assistant Area", |ui| {
+            let selected_text = match (&room.ha_area_id, area) {
+                (_, Some(area)) => area.name.clone(),
+                (Some(id), None) => id.clone(),
+                (None, None) => "None".to_string(),
+            };
+            egui::ComboBox::from_id_salt("Room Area")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut room.ha_area_id, None, "None");
+                    for area in areas.unwrap_or_default() {
+                        ui.selectable_value(
+                            &mut room.ha_area_id,
+                            Some(area.area_id.clone()),
+                            &area.name,
+                        );
+                    }
+                });
+        });
+        if areas.is_none() {
+            ui.spinner();
+        } else if ui.button("Refresh").clicked() {
+            refresh = true;
+        }
+    });
+    let Some(area) = area else {
+        return refresh;
+    };
+
+    for light in &area.lights {
+        if room.lights.iter().any(|l| l.entity_id == light.entity_id) {
+            continue;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add Light").clicked() {
+                room.lights.push(Light {
+                    entity_id: light.entity_id.clone(),
+                    ..Light::new(&light.name, Vec2::ZERO)
+                });
+            }
+            ui.label(format!("{} ({})", light.name, light.entity_id));
+        });
+    }
+    for sensor in &area.sensors {
+        if room.sensors.iter().any(|s| s.entity_id == sensor.entity_id) {
+            continue;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add Sensor").clicked() {
+                room.sensors
+                    .push(Sensor::new(&sensor.entity_id, &sensor.name, &sensor.unit));
+            }
+            ui.label(format!("{} ({})", sensor.name, sensor.entity_id));
+        });
+    }
+    refresh
+}
+
+fn push_recent_furniture(recent_furniture: &mut Vec<FurnitureType>, furniture_type: FurnitureType) {
+    recent_furniture.retain(|f| *f != furniture_type);
+    recent_furniture.insert(0, furniture_type);
+    recent_furniture.truncate(MAX_RECENT_FURNITURE);
+}
+
+/// Name of the room or furniture with the id
+fn object_name_mut(layout: &mut Home, id: Uuid) -> Option<&mut String> {
+    layout.rooms.iter_mut().find_map(|room| {
+        if room.id == id {
+            Some(&mut room.name)
+        } else {
+            room.furniture
+                .iter_mut()
+                .find(|f| f.id == id)
+                .map(|furniture| &mut furniture.name)
+        }
+    })
+}
+
+/// Focused text box editing a name, true once it closes. Escape reverts, enter or clicking away
+/// keeps the new name
+fn rename_field(ui: &mut Ui, name: &mut String, original_name: &str) -> bool {
+    let response = TextEdit::singleline(name)
+        .desired_width(120.0)
+        .show(ui)
+        .response;
+    if !response.has_focus() && !response.lost_focus() {
+        response.request_focus();
+    }
+    if response.lost_focus() {
+        if ui.input(|i| i.key_pressed(Key::Escape)) {
+            original_name.clone_into(name);
+        }
+        return true;
+    }
+    false
+}
+
 fn room_edit_widgets(
     ui: &mut egui::Ui,
     materials: &[GlobalMaterial],
+    textures: &AHashMap<String, TextureHandle>,
+    recent_furniture: &mut Vec<FurnitureType>,
+    units: Units,
+    precision: usize,
     room: &mut Room,
 ) -> AlterObject {
     let mut alter_type = AlterObject::None;
+    let walls_hash = room.walls_hash();
+    if room
+        .rendered_data
+        .as_ref()
+        .is_some_and(|r| !polygons_are_valid(&r.polygons))
+    {
+        ui.colored_label(
+            Color32::from_rgb(255, 90, 70),
+            "Operations leave this room with an invalid shape that could not be repaired",
+        );
+    }
     ui.horizontal(|ui| {
         ui.label("Room");
         TextEdit::singleline(&mut room.name)
@@ -524,12 +2301,34 @@ fn room_edit_widgets(
         if ui.add(Button::new("Delete")).clicked() {
             alter_type = AlterObject::Delete;
         }
-        if ui.add(Button::new("^")).clicked() {
+        if ui
+            .add(Button::new("^"))
+            .on_hover_text("Move forward")
+            .clicked()
+        {
             alter_type = AlterObject::MoveUp;
         }
-        if ui.add(Button::new("v")).clicked() {
+        if ui
+            .add(Button::new("v"))
+            .on_hover_text("Move backward")
+            .clicked()
+        {
             alter_type = AlterObject::MoveDown;
         }
+        if ui
+            .add(Button::new("Front"))
+            .on_hover_text("Bring to front")
+            .clicked()
+        {
+            alter_type = AlterObject::BringToFront;
+        }
+        if ui
+            .add(Button::new("Back"))
+            .on_hover_text("Send to back")
+            .clicked()
+        {
+            alter_type = AlterObject::SendToBack;
+        }
     });
     ui.separator();
 
@@ -538,8 +2337,14 @@ fn room_edit_widgets(
         .spacing([20.0, 4.0])
         .striped(true)
         .show(ui, |ui| {
-            edit_vec2(ui, "Pos", &mut room.pos, 0.1);
-            edit_vec2(ui, "Size", &mut room.size, 0.1);
+            edit_vec2(ui, "Pos", &mut room.pos, 0.1, precision);
+            edit_vec2(ui, "Size", &mut room.size, 0.1, precision);
+            ui.end_row();
+
+            ui.label("Area");
+            ui.label(format_area(room.area(), units, precision));
+            ui.label("Perimeter");
+            ui.label(format_length(room.perimeter(), units, precision));
             ui.end_row();
 
             // Wall selection
@@ -565,7 +2370,43 @@ fn room_edit_widgets(
             }
             ui.end_row();
 
-            combo_box_for_materials(ui, &room.id.to_string(), materials, &mut room.material);
+            combo_box_for_enum(ui, "Room Type", &mut room.room_type, "Type");
+            if room.freeform.is_none() {
+                combo_box_for_enum(ui, "Room Shape", &mut room.base_shape, "Shape");
+            } else if ui
+                .button("Use Base Shape")
+                .on_hover_text("Replace the drawn outline with the base shape")
+                .clicked()
+            {
+                room.freeform = None;
+            }
+            combo_box_for_enum(ui, "Room Phase", &mut room.phase, "Phase");
+            combo_box_for_materials(
+                ui,
+                &room.id.to_string(),
+                materials,
+                textures,
+                &mut room.material,
+            );
+            if ui
+                .add(Button::new(format!("Apply to all {}", room.room_type)))
+                .on_hover_text("Set this material on every room of the same type")
+                .clicked()
+            {
+                alter_type = AlterObject::ApplyMaterialToType;
+            }
+            ui.end_row();
+
+            labelled_widget(ui, "Always Lit", |ui| {
+                ui.checkbox(&mut room.always_lit, "")
+                    .on_hover_text("Draw this room fully lit regardless of its lights");
+            });
+            edit_tags(ui, format!("{} Tags", room.id), &mut room.tags);
+            labelled_widget(ui, "Locked", |ui| {
+                ui.checkbox(&mut room.locked, "")
+                    .on_hover_text("Stop the room being dragged or nudged");
+            });
+            ui.end_row();
 
             edit_option(
                 ui,
@@ -578,12 +2419,14 @@ fn room_edit_widgets(
                             DragValue::new(&mut outline.thickness)
                                 .speed(0.1)
                                 .range(0.01..=5.0)
-                                .suffix("m"),
+                                .suffix("m")
+                                .fixed_decimals(precision),
                         );
                     });
                     labelled_widget(ui, "Color", |ui| {
                         ui.color_edit_button_srgba_unmultiplied(outline.color.mut_array());
                     });
+                    combo_box_for_enum(ui, "Outline Style", &mut outline.style, "Style");
                 },
             );
         });
@@ -610,6 +2453,7 @@ fn room_edit_widgets(
                     Action::Subtract => Color32::from_rgb(200, 50, 50),
                     Action::AddWall => Color32::from_rgb(50, 100, 50),
                     Action::SubtractWall => Color32::from_rgb(160, 90, 50),
+                    Action::Wall => Color32::from_rgb(90, 110, 200),
                 }
                 .gamma_multiply(0.15);
                 egui::Frame::fill(egui::Frame::central_panel(ui.style()), color).show(ui, |ui| {
@@ -620,7 +2464,14 @@ fn room_edit_widgets(
                             &mut operation.action,
                             "",
                         );
-                        combo_box_for_enum(ui, format!("Shape {index}"), &mut operation.shape, "");
+                        if operation.action != Action::Wall {
+                            combo_box_for_enum(
+                                ui,
+                                format!("Shape {index}"),
+                                &mut operation.shape,
+                                "",
+                            );
+                        }
 
                         if ui.button("Delete").clicked() {
                             alterations[index] = AlterObject::Delete;
@@ -634,8 +2485,19 @@ fn room_edit_widgets(
                     });
 
                     ui.horizontal(|ui| {
-                        edit_vec2(ui, "Pos", &mut operation.pos, 0.1);
-                        edit_vec2(ui, "Size", &mut operation.size, 0.1);
+                        edit_vec2(ui, "Pos", &mut operation.pos, 0.1, precision);
+                        if operation.action == Action::Wall {
+                            labelled_widget(ui, "Length", |ui| {
+                                ui.add(
+                                    DragValue::new(&mut operation.size.x)
+                                        .speed(0.1)
+                                        .fixed_decimals(precision)
+                                        .range(0.0..=f64::INFINITY),
+                                );
+                            });
+                        } else {
+                            edit_vec2(ui, "Size", &mut operation.size, 0.1, precision);
+                        }
                         edit_rotation(ui, &mut operation.rotation);
                     });
 
@@ -651,6 +2513,7 @@ fn room_edit_widgets(
                                         ui,
                                         &operation.id.to_string(),
                                         materials,
+                                        textures,
                                         content,
                                     );
                                 },
@@ -713,8 +2576,8 @@ fn room_edit_widgets(
                     });
 
                     ui.horizontal(|ui| {
-                        edit_vec2(ui, "Pos", &mut zone.pos, 0.1);
-                        edit_vec2(ui, "Size", &mut zone.size, 0.1);
+                        edit_vec2(ui, "Pos", &mut zone.pos, 0.1, precision);
+                        edit_vec2(ui, "Size", &mut zone.size, 0.1, precision);
                         edit_rotation(ui, &mut zone.rotation);
                     });
                 });
@@ -745,6 +2608,7 @@ fn room_edit_widgets(
             labelled_widget(ui, "Openings", |ui| {
                 if ui.add(Button::new("Add")).clicked() {
                     room.openings.push(Opening::default());
+                    room.snap_opening(room.openings.len() - 1, f64::INFINITY);
                 }
             });
         });
@@ -760,21 +2624,39 @@ fn room_edit_widgets(
                     &mut opening.opening_type,
                     "",
                 );
-                edit_vec2(ui, "Pos", &mut opening.pos, 0.1);
+                edit_vec2(ui, "Pos", &mut opening.pos, 0.1, precision);
                 edit_rotation(ui, &mut opening.rotation);
                 labelled_widget(ui, "Width", |ui| {
                     ui.add(
                         DragValue::new(&mut opening.width)
                             .speed(0.1)
                             .range(0.1..=5.0)
-                            .suffix("m"),
+                            .suffix("m")
+                            .fixed_decimals(precision),
                     );
                 });
-                if opening.opening_type == OpeningType::Door {
+                if opening.opening_type == OpeningType::Window {
+                    combo_box_for_enum(
+                        ui,
+                        format!("Window Shape {}", opening.id),
+                        &mut opening.window_shape,
+                        "",
+                    );
+                }
+                if matches!(
+                    opening.opening_type,
+                    OpeningType::Door | OpeningType::SlidingDoor
+                ) {
                     labelled_widget(ui, "Flipped", |ui| {
                         ui.checkbox(&mut opening.flipped, "");
                     });
                 }
+                if opening.opening_type.is_door() {
+                    labelled_widget(ui, "Left Open", |ui| {
+                        ui.checkbox(&mut opening.open, "")
+                            .on_hover_text("Let light through the doorway");
+                    });
+                }
                 if ui.button("Delete").clicked() {
                     alterations[index] = AlterObject::Delete;
                 }
@@ -829,7 +2711,7 @@ fn room_edit_widgets(
                     TextEdit::singleline(&mut light.name)
                         .min_size(egui::vec2(100.0, 0.0))
                         .show(ui);
-                    edit_vec2(ui, "Pos", &mut light.pos, 0.1);
+                    edit_vec2(ui, "Pos", &mut light.pos, 0.1, precision);
                     if ui.button("Delete").clicked() {
                         alterations[index] = AlterObject::Delete;
                     }
@@ -854,16 +2736,48 @@ fn room_edit_widgets(
                             DragValue::new(&mut light.radius)
                                 .speed(0.01)
                                 .range(0.01..=0.5)
-                                .suffix("m"),
+                                .suffix("m")
+                                .fixed_decimals(precision),
                         );
                     });
+                    labelled_widget(ui, "Color", |ui| {
+                        ui.color_edit_button_srgba_unmultiplied(light.color.mut_array());
+                    });
+                });
+                ui.horizontal(|ui| {
+                    combo_box_for_enum(ui, format!("Light Kind {index}"), &mut light.kind, "");
+                    if light.kind == LightKind::Spot {
+                        edit_rotation(ui, &mut light.direction);
+                        labelled_widget(ui, "Cone", |ui| {
+                            ui.add(
+                                DragValue::new(&mut light.cone_angle)
+                                    .speed(1)
+                                    .range(1.0..=180.0)
+                                    .suffix("°"),
+                            );
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
                     edit_option(
                         ui,
                         "Multi",
                         &mut light.multi,
                         MultiLight::default,
                         |ui, content| {
-                            edit_vec2(ui, "Room Padding", &mut content.room_padding, 0.1);
+                            combo_box_for_enum(
+                                ui,
+                                format!("Light Arrangement {index}"),
+                                &mut content.arrangement,
+                                "",
+                            );
+                            edit_vec2(
+                                ui,
+                                "Room Padding",
+                                &mut content.room_padding,
+                                0.1,
+                                precision,
+                            );
                             labelled_widget(ui, "Rows", |ui| {
                                 ui.add(DragValue::new(&mut content.rows).range(1..=20));
                             });
@@ -873,6 +2787,33 @@ fn room_edit_widgets(
                         },
                     );
                 });
+                ui.horizontal(|ui| {
+                    labelled_widget(ui, "Exclusions", |ui| {
+                        if ui.add(Button::new("Add")).clicked() {
+                            light.exclusions.push(Zone::default());
+                        }
+                    });
+                });
+                let mut delete_exclusion = None;
+                for (exclusion_index, exclusion) in light.exclusions.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        combo_box_for_enum(
+                            ui,
+                            format!("Exclusion Shape {index} {exclusion_index}"),
+                            &mut exclusion.shape,
+                            "",
+                        );
+                        edit_vec2(ui, "Pos", &mut exclusion.pos, 0.1, precision);
+                        edit_vec2(ui, "Size", &mut exclusion.size, 0.1, precision);
+                        edit_rotation(ui, &mut exclusion.rotation);
+                        if ui.button("Delete").clicked() {
+                            delete_exclusion = Some(exclusion_index);
+                        }
+                    });
+                }
+                if let Some(exclusion_index) = delete_exclusion {
+                    light.exclusions.remove(exclusion_index);
+                }
             });
         }
         for (index, alteration) in alterations.into_iter().enumerate().rev() {
@@ -903,11 +2844,34 @@ fn room_edit_widgets(
                     room.furniture.push(Furniture::default());
                 }
             });
+            let mut picked_recent = None;
+            for furniture_type in recent_furniture.iter() {
+                if ui
+                    .add(Button::new(furniture_type.to_string()).small())
+                    .on_hover_text("Add another of this recently used type")
+                    .clicked()
+                {
+                    picked_recent = Some(*furniture_type);
+                }
+            }
+            if let Some(furniture_type) = picked_recent {
+                room.furniture.push(Furniture {
+                    furniture_type,
+                    ..Furniture::default()
+                });
+                push_recent_furniture(recent_furniture, furniture_type);
+            }
         });
     })
     .body(|ui| {
         let num_objects = room.furniture.len();
         let mut alterations = vec![AlterObject::None; num_objects];
+        let stack_targets: Vec<(Uuid, String)> = room
+            .furniture
+            .iter()
+            .map(|f| (f.id, f.name.clone()))
+            .collect();
+        let mut restack = None;
         for (index, furniture) in room.furniture.iter_mut().enumerate() {
             egui::Frame::fill(
                 egui::Frame::central_panel(ui.style()),
@@ -926,18 +2890,24 @@ fn room_edit_widgets(
                                 if matches!(variant, FurnitureType::AnimatedPiece(_)) {
                                     continue;
                                 }
-                                ui.selectable_value(
-                                    &mut furniture.furniture_type,
-                                    variant,
-                                    variant.to_string(),
-                                );
+                                if ui
+                                    .selectable_value(
+                                        &mut furniture.furniture_type,
+                                        variant,
+                                        variant.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    push_recent_furniture(recent_furniture, variant);
+                                }
                             }
                         });
                     match &mut furniture.furniture_type {
                         FurnitureType::Chair(ref mut chair_type) => {
                             combo_box_for_enum(ui, format!("{}-c", furniture.id), chair_type, "");
-                            if let ChairType::Sofa(ref mut color) = chair_type {
+                            if let ChairType::Sofa(ref mut color, ref mut shape) = chair_type {
                                 ui.color_edit_button_srgba_unmultiplied(color.mut_array());
+                                combo_box_for_enum(ui, format!("{}-cs", furniture.id), shape, "");
                             }
                         }
                         FurnitureType::Table(ref mut table_type) => {
@@ -972,6 +2942,9 @@ fn room_edit_widgets(
                         FurnitureType::Storage(ref mut storage_type) => {
                             combo_box_for_enum(ui, format!("{}-s", furniture.id), storage_type, "");
                         }
+                        FurnitureType::Stairs(ref mut stair_type) => {
+                            combo_box_for_enum(ui, format!("{}-st", furniture.id), stair_type, "");
+                        }
                         FurnitureType::Electronic(ref mut electronic_type) => {
                             combo_box_for_enum(
                                 ui,
@@ -988,11 +2961,45 @@ fn room_edit_widgets(
                         &mut furniture.render_order,
                         "Render Order",
                     );
+                    combo_box_for_enum(
+                        ui,
+                        format!("{} Phase", furniture.id),
+                        &mut furniture.phase,
+                        "Phase",
+                    );
+                    edit_tags(ui, format!("{} Tags", furniture.id), &mut furniture.tags);
+                    ui.checkbox(&mut furniture.locked, "Locked");
+                    let stacked_name = stack_targets
+                        .iter()
+                        .find(|(id, _)| Some(*id) == furniture.stack_on)
+                        .map_or("Floor", |(_, name)| name.as_str());
+                    labelled_widget(ui, "Stack On", |ui| {
+                        egui::ComboBox::from_id_salt(format!("{} Stack On", furniture.id))
+                            .selected_text(stacked_name)
+                            .show_ui(ui, |ui| {
+                                if ui
+                                    .selectable_label(furniture.stack_on.is_none(), "Floor")
+                                    .clicked()
+                                {
+                                    restack = Some((index, None));
+                                }
+                                for (id, name) in &stack_targets {
+                                    if *id == furniture.id {
+                                        continue;
+                                    }
+                                    let selected = furniture.stack_on == Some(*id);
+                                    if ui.selectable_label(selected, name).clicked() {
+                                        restack = Some((index, Some(*id)));
+                                    }
+                                }
+                            });
+                    });
                     if furniture.has_material() {
                         combo_box_for_materials(
                             ui,
                             &furniture.id.to_string(),
                             materials,
+                            textures,
                             &mut furniture.material,
                         );
                     }
@@ -1001,6 +3008,7 @@ fn room_edit_widgets(
                             ui,
                             &format!("{} Children", furniture.id),
                             materials,
+                            textures,
                             &mut furniture.material_children,
                         );
                     }
@@ -1020,20 +3028,30 @@ fn room_edit_widgets(
                 });
 
                 ui.horizontal(|ui| {
-                    edit_vec2(ui, "Pos", &mut furniture.pos, 0.1);
-                    edit_vec2(ui, "Size", &mut furniture.size, 0.1);
+                    edit_vec2(ui, "Pos", &mut furniture.pos, 0.1, precision);
+                    edit_vec2(ui, "Size", &mut furniture.size, 0.1, precision);
                     edit_rotation(ui, &mut furniture.rotation);
                     ui.label("Power Entity");
                     TextEdit::singleline(&mut furniture.power_draw_entity)
                         .min_size(egui::vec2(200.0, 0.0))
                         .show(ui);
+                    if furniture.furniture_type == FurnitureType::Fireplace {
+                        ui.label("State Entity");
+                        TextEdit::singleline(&mut furniture.state_entity)
+                            .min_size(egui::vec2(200.0, 0.0))
+                            .hint_text("switch.fireplace")
+                            .show(ui);
+                    }
                 });
             });
         }
+        if let Some((index, stack_on)) = restack {
+            room.restack_furniture(index, stack_on);
+        }
         for (index, alteration) in alterations.into_iter().enumerate().rev() {
             match alteration {
                 AlterObject::Delete => {
-                    room.furniture.remove(index);
+                    room.remove_furniture(index);
                 }
                 AlterObject::MoveUp => {
                     room.furniture.swap(index, index - 1);
@@ -1046,12 +3064,21 @@ fn room_edit_widgets(
                     new_furniture.id = Uuid::new_v4();
                     room.furniture.insert(index + 1, new_furniture);
                 }
-                AlterObject::None => {}
+                AlterObject::None
+                | AlterObject::ApplyMaterialToType
+                | AlterObject::BringToFront
+                | AlterObject::SendToBack => {}
             }
         }
     });
 
-    edit_vec2(ui, "Sensors Offset", &mut room.sensors_offset, 0.1);
+    edit_vec2(
+        ui,
+        "Sensors Offset",
+        &mut room.sensors_offset,
+        0.1,
+        precision,
+    );
     CollapsingState::load_with_default_open(
         ui.ctx(),
         ui.make_persistent_id("sensors_collapsing_header"),
@@ -1107,5 +3134,53 @@ fn room_edit_widgets(
         }
     });
 
+    // Keep openings on walls the size or operations moved
+    if room.walls_hash() != walls_hash {
+        room.snap_openings();
+    }
+
     alter_type
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{layout::Home, template};
+
+    fn serialize(layout: &Home) -> Vec<u8> {
+        bincode::serialize(layout).unwrap()
+    }
+
+    #[test]
+    fn layout_serializes_the_same_after_a_round_trip() {
+        let layout = template::default();
+        let bytes = serialize(&layout);
+        for _ in 0..20 {
+            let restored: Home = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(serialize(&restored), bytes);
+        }
+    }
+
+    #[test]
+    fn undo_then_redo_round_trips() {
+        let mut layout = template::default();
+        let mut history = EditHistory::default();
+        history.record(serialize(&layout));
+        let original = serialize(&layout);
+        layout.rooms[0].pos.x += 1.0;
+        history.record(serialize(&layout));
+        let moved = serialize(&layout);
+
+        let restored: Home = bincode::deserialize(history.undo().unwrap()).unwrap();
+        assert_eq!(serialize(&restored), original);
+        // The next frame sees the restored layout, which mustn't count as a new edit
+        history.record(serialize(&restored));
+        assert_eq!(history.redo.len(), 1);
+
+        let restored: Home = bincode::deserialize(history.redo().unwrap()).unwrap();
+        assert_eq!(serialize(&restored), moved);
+        history.record(serialize(&restored));
+        assert_eq!(history.undo.len(), 1);
+        assert!(history.redo.is_empty());
+    }
+}
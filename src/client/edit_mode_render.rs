@@ -1,14 +1,29 @@
 use crate::{
-    client::{edit_mode::EditResponse, vec2_to_egui_pos, HomeFlow},
+    client::{
+        edit_mode::{EditResponse, ObjectType},
+        edit_mode_utils::format_length,
+        vec2_to_egui_pos, HomeFlow,
+    },
     common::{
         layout::{Action, OpeningType, Room, Shape},
         shape::point_to_vec2,
-        utils::RoundFactor,
+        utils::{rotate_point_i32, RoundFactor},
     },
 };
-use egui::{Align2, Color32, Painter, Shape as EShape, Stroke, Window};
+use egui::{Align2, Color32, FontId, Painter, Shape as EShape, Stroke, Window};
 use glam::{dvec2 as vec2, DVec2 as Vec2};
 
+/// Colour operations are outlined in for their action
+pub const fn operation_color(action: Action) -> Color32 {
+    match action {
+        Action::Add => Color32::from_rgb(50, 200, 50),
+        Action::Subtract => Color32::from_rgb(200, 50, 50),
+        Action::AddWall => Color32::from_rgb(50, 100, 50),
+        Action::SubtractWall => Color32::from_rgb(160, 90, 50),
+        Action::Wall => Color32::from_rgb(90, 110, 200),
+    }
+}
+
 impl HomeFlow {
     pub fn paint_edit_mode(&mut self, painter: &Painter, edit_response: &EditResponse) {
         if let Some(snap_line_x) = edit_response.snap_line_x {
@@ -34,6 +49,92 @@ impl HomeFlow {
             ));
         }
 
+        // Render ruler segments with their length and offsets at the midpoint
+        if let Some(points) = &self.edit_mode.ruler {
+            let (units, precision) = (self.device.units, self.device.coordinate_precision);
+            let stroke = Stroke::new(3.0, Color32::from_rgb(255, 200, 80));
+            let ends: Vec<_> = points
+                .iter()
+                .copied()
+                .chain((!points.is_empty()).then_some(self.mouse_pos_world))
+                .collect();
+            for pair in ends.windows(2) {
+                let (start, end) = (pair[0], pair[1]);
+                painter.extend(EShape::dashed_line(
+                    &[
+                        self.world_to_screen_pos(start),
+                        self.world_to_screen_pos(end),
+                    ],
+                    stroke,
+                    12.0,
+                    6.0,
+                ));
+                let delta = end - start;
+                painter.text(
+                    self.world_to_screen_pos((start + end) / 2.0),
+                    Align2::CENTER_BOTTOM,
+                    format!(
+                        "{}\nΔx {} Δy {}",
+                        format_length(delta.length(), units, precision),
+                        format_length(delta.x.abs(), units, precision),
+                        format_length(delta.y.abs(), units, precision)
+                    ),
+                    FontId::proportional(14.0),
+                    stroke.color,
+                );
+            }
+            for &point in points {
+                painter.circle_filled(self.world_to_screen_pos(point), 4.0, stroke.color);
+            }
+        }
+
+        // Render the freeform room being drawn, with the next edge following the cursor
+        if let Some(points) = &self.edit_mode.drawing_room {
+            let stroke = Stroke::new(3.0, Color32::from_rgb(100, 150, 255));
+            let mut line: Vec<_> = points
+                .iter()
+                .map(|&p| self.world_to_screen_pos(p))
+                .collect();
+            line.push(vec2_to_egui_pos(self.mouse_pos));
+            painter.add(EShape::line(line, stroke));
+            for (index, &point) in points.iter().enumerate() {
+                let radius = if index == 0 { 7.0 } else { 4.0 };
+                painter.circle_filled(self.world_to_screen_pos(point), radius, stroke.color);
+            }
+        }
+
+        // Render box selection
+        if let Some(start) = self.edit_mode.box_select {
+            painter.rect(
+                egui::Rect::from_two_pos(
+                    self.world_to_screen_pos(start),
+                    self.world_to_screen_pos(self.mouse_pos_world),
+                ),
+                0.0,
+                Color32::from_rgba_unmultiplied(100, 150, 255, 30),
+                Stroke::new(2.0, Color32::from_rgb(100, 150, 255)),
+            );
+        }
+
+        // Render outlines of every room in a multiple selection
+        if self.edit_mode.selected_ids.len() > 1 {
+            for room in &self.layout.rooms {
+                if !self.edit_mode.selected_ids.contains(&room.id) {
+                    continue;
+                }
+                for poly in &room.rendered_data.as_ref().unwrap().polygons {
+                    let points: Vec<Vec2> = poly.exterior().points().map(point_to_vec2).collect();
+                    self.closed_dashed_line_with_offset(
+                        painter,
+                        &points,
+                        Stroke::new(4.0, Color32::from_rgba_premultiplied(100, 150, 255, 200)),
+                        60.0,
+                        self.time * 50.0,
+                    );
+                }
+            }
+        }
+
         Window::new("Edit mode instructions".to_string())
             .fixed_pos(vec2_to_egui_pos(vec2(
                 self.canvas_center.x,
@@ -47,22 +148,45 @@ impl HomeFlow {
                 ui.vertical_centered(|ui| {
                     ui.label("Drag to move objects");
                     ui.label("Click to select room, escape to deselect");
+                    ui.label("Shift click or shift drag a box to select several rooms");
+                    ui.label("Ctrl+C and Ctrl+V to copy and paste rooms or furniture");
                     ui.label("Shift to disable snap");
+                    ui.label("Alt drag furniture to make a row of copies");
+                    ui.label("Press R while dragging furniture to rotate it");
+                    ui.label("Press M to measure distances");
+                    if self.edit_mode.drawing_room.is_some() {
+                        ui.label("Click to place corners, shift to disable snap");
+                        ui.label("Double click, enter or click the first corner to finish");
+                        ui.label("Backspace removes the last corner, escape cancels");
+                    } else if ui.button("Draw Room").clicked() {
+                        self.edit_mode.drawing_room = Some(Vec::new());
+                        self.edit_mode.selected_ids.clear();
+                    }
                     if ui.button("Add Room").clicked() {
                         let pos = self.screen_to_world(self.canvas_center);
-                        self.layout.rooms.push(Room {
+                        let mut room = Room {
                             pos: vec2(pos.x.round_factor(10.0), pos.y.round_factor(10.0)),
+                            walls: self.device.new_room_walls,
                             ..Room::default()
-                        });
+                        };
+                        // Neighbours already wall off any shared edge, avoid doubling up
+                        room.walls.remove(room.shared_walls(&self.layout.rooms));
+                        self.layout.rooms.push(room);
+                    }
+                    if ui.button("Import Rooms").clicked() {
+                        self.edit_mode.import_text.get_or_insert_with(String::new);
                     }
                 });
             });
 
         // Get hovered room or selected room if there isn't one
-        if let Some(room) = [edit_response.hovered_id, self.edit_mode.selected_id]
-            .iter()
-            .filter_map(|&id| id)
-            .find_map(|id| self.layout.rooms.iter().find(|r| r.id == id))
+        if let Some(room) = [
+            edit_response.hovered_id,
+            self.edit_mode.selected_ids.first().copied(),
+        ]
+        .iter()
+        .filter_map(|&id| id)
+        .find_map(|id| self.layout.rooms.iter().find(|r| r.id == id))
         {
             let rendered_data = room.rendered_data.as_ref().unwrap();
 
@@ -89,31 +213,12 @@ impl HomeFlow {
             }
 
             // Render original shape
-            let vertices = Shape::Rectangle.vertices(room.pos, room.size, 0);
+            let vertices = room.base_vertices();
             let stroke = Stroke::new(3.0, Color32::from_rgb(50, 200, 50).gamma_multiply(0.6));
             self.closed_dashed_line_with_offset(painter, &vertices, stroke, 35.0, self.time * 50.0);
 
             // Render operations
-            for operation in &room.operations {
-                let vertices = operation.vertices(room.pos);
-                let stroke = Stroke::new(
-                    3.0,
-                    match operation.action {
-                        Action::Add => Color32::from_rgb(50, 200, 50),
-                        Action::Subtract => Color32::from_rgb(200, 50, 50),
-                        Action::AddWall => Color32::from_rgb(50, 100, 50),
-                        Action::SubtractWall => Color32::from_rgb(160, 90, 50),
-                    }
-                    .gamma_multiply(0.6),
-                );
-                self.closed_dashed_line_with_offset(
-                    painter,
-                    &vertices,
-                    stroke,
-                    35.0,
-                    self.time * 50.0,
-                );
-            }
+            self.paint_operations(painter, room, 0.6);
 
             // Render zones
             for zone in &room.zones {
@@ -133,7 +238,9 @@ impl HomeFlow {
                 let selected = edit_response.hovered_id == Some(opening.id);
                 let pos = self.world_to_screen(room.pos + opening.pos);
                 let color = match opening.opening_type {
-                    OpeningType::Door => Color32::from_rgb(255, 100, 0),
+                    OpeningType::Door | OpeningType::DoubleDoor | OpeningType::SlidingDoor => {
+                        Color32::from_rgb(255, 100, 0)
+                    }
                     OpeningType::Window => Color32::from_rgb(0, 70, 230),
                 }
                 .gamma_multiply(0.8);
@@ -151,7 +258,7 @@ impl HomeFlow {
                 let rot_dir = vec2(
                     f64::from(opening.rotation).to_radians().cos(),
                     f64::from(opening.rotation).to_radians().sin(),
-                ) * (opening.width / 2.0 * self.stored.zoom);
+                ) * (opening.width / 2.0 * self.device.zoom);
                 let start = vec2_to_egui_pos(pos - rot_dir);
                 let end = vec2_to_egui_pos(pos + rot_dir);
                 painter.line_segment([start, end], Stroke::new(6.0, color));
@@ -160,6 +267,20 @@ impl HomeFlow {
             // Render lights
             for light in &room.lights {
                 let selected = edit_response.hovered_id == Some(light.id);
+                for exclusion in &light.exclusions {
+                    let vertices = exclusion.vertices(room.pos);
+                    let stroke = Stroke::new(
+                        if selected { 4.0 } else { 2.0 },
+                        Color32::from_rgb(60, 60, 90).gamma_multiply(0.8),
+                    );
+                    self.closed_dashed_line_with_offset(
+                        painter,
+                        &vertices,
+                        stroke,
+                        20.0,
+                        self.time * 50.0,
+                    );
+                }
                 let pos = self.world_to_screen(room.pos + light.pos);
                 let color = Color32::from_rgb(255, 255, 0).gamma_multiply(0.8);
                 painter.add(EShape::circle_filled(
@@ -178,13 +299,10 @@ impl HomeFlow {
             for furniture in &room.furniture {
                 let selected = edit_response.hovered_id == Some(furniture.id)
                     || edit_response.hovered_id == Some(furniture.id);
+                let (pos, rotation) = room.furniture_placement(furniture);
                 self.closed_dashed_line_with_offset(
                     painter,
-                    &Shape::Rectangle.vertices(
-                        room.pos + furniture.pos,
-                        furniture.size,
-                        furniture.rotation,
-                    ),
+                    &Shape::Rectangle.vertices(pos, furniture.size, rotation),
                     Stroke::new(
                         if selected { 6.0 } else { 3.0 },
                         Color32::from_rgb(150, 0, 50).gamma_multiply(if selected {
@@ -198,6 +316,99 @@ impl HomeFlow {
                 );
             }
         }
+
+        self.paint_wall_gaps(painter, edit_response);
+    }
+
+    /// Outline each of the rooms operations in the colour of its action
+    pub fn paint_operations(&self, painter: &Painter, room: &Room, opacity: f32) {
+        for operation in &room.operations {
+            let vertices = operation.vertices(room.pos);
+            let stroke = Stroke::new(
+                3.0,
+                operation_color(operation.action).gamma_multiply(opacity),
+            );
+            self.closed_dashed_line_with_offset(painter, &vertices, stroke, 35.0, self.time * 50.0);
+            // Mark the ends walls are dragged by
+            if operation.action == Action::Wall {
+                let (start, end) = operation.wall_ends(room.pos);
+                for point in [start, end] {
+                    painter.circle_filled(self.world_to_screen_pos(point), 5.0, stroke.color);
+                }
+            }
+        }
+    }
+
+    /// Show the gap from each side of the dragged or hovered furniture to the nearest wall
+    fn paint_wall_gaps(&self, painter: &Painter, edit_response: &EditResponse) {
+        let Some(furniture_id) = self
+            .edit_mode
+            .drag_data
+            .as_ref()
+            .filter(|d| d.object_type == ObjectType::Furniture)
+            .map(|d| d.id)
+            .or(edit_response.hovered_id)
+        else {
+            return;
+        };
+        let Some((room, furniture)) = self.layout.rooms.iter().find_map(|room| {
+            room.furniture
+                .iter()
+                .find(|f| f.id == furniture_id)
+                .map(|f| (room, f))
+        }) else {
+            return;
+        };
+        let Some(rendered_data) = &room.rendered_data else {
+            return;
+        };
+
+        // Room edges are the inner faces of the walls
+        let mut edges = Vec::new();
+        for poly in &rendered_data.polygons {
+            for ring in std::iter::once(poly.exterior()).chain(poly.interiors()) {
+                let points: Vec<Vec2> = ring.points().map(point_to_vec2).collect();
+                edges.extend(points.windows(2).map(|w| (w[0], w[1])));
+            }
+        }
+
+        let (center, rotation) = room.furniture_placement(furniture);
+        let half_size = furniture.size / 2.0;
+        let color = Color32::from_rgb(255, 220, 120);
+        for local_dir in [
+            vec2(1.0, 0.0),
+            vec2(-1.0, 0.0),
+            vec2(0.0, 1.0),
+            vec2(0.0, -1.0),
+        ] {
+            let dir = rotate_point_i32(local_dir, -rotation);
+            let start = center + rotate_point_i32(local_dir * half_size, -rotation);
+            let Some(distance) = edges
+                .iter()
+                .filter_map(|&(a, b)| ray_segment_distance(start, dir, a, b))
+                .min_by(f64::total_cmp)
+            else {
+                continue;
+            };
+            let end = start + dir * distance;
+            let (start_screen, end_screen) = (
+                self.world_to_screen_pos(start),
+                self.world_to_screen_pos(end),
+            );
+            painter.line_segment([start_screen, end_screen], Stroke::new(2.0, color));
+            painter.circle_filled(end_screen, 3.0, color);
+            painter.text(
+                start_screen.lerp(end_screen, 0.5),
+                Align2::CENTER_CENTER,
+                format_length(
+                    distance,
+                    self.device.units,
+                    self.device.coordinate_precision,
+                ),
+                FontId::proportional(14.0),
+                Color32::BLACK,
+            );
+        }
     }
 
     fn closed_dashed_line_with_offset(
@@ -253,3 +464,16 @@ impl HomeFlow {
         ));
     }
 }
+
+/// Distance along a ray from origin in direction dir to the segment a-b, if it is hit
+fn ray_segment_distance(origin: Vec2, dir: Vec2, a: Vec2, b: Vec2) -> Option<f64> {
+    let segment = b - a;
+    let denom = dir.perp_dot(segment);
+    if denom.abs() < f64::EPSILON {
+        return None;
+    }
+    let to_a = a - origin;
+    let t = to_a.perp_dot(segment) / denom;
+    let u = to_a.perp_dot(dir) / denom;
+    (t >= 0.0 && (0.0..=1.0).contains(&u)).then_some(t)
+}
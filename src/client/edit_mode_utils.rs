@@ -1,28 +1,35 @@
 use crate::{
     client::{
         edit_mode::{DragData, HoverDetails, ManipulationType, ObjectType},
-        HomeFlow,
+        vec2_to_egui_pos, HomeFlow,
     },
     common::{
-        layout::GlobalMaterial,
-        shape::point_to_vec2,
+        layout::{Action, GlobalMaterial, Shape},
+        shape::{along_wall, OPENING_SNAP_DISTANCE},
         utils::{rotate_point_i32, rotate_point_pivot_i32, RoundFactor},
     },
 };
-use egui::{ComboBox, DragValue, Key, Ui};
+use ahash::AHashMap;
+use egui::{Align2, ComboBox, DragValue, Key, TextEdit, TextureHandle, Ui, Window};
 use glam::{dvec2 as vec2, DVec2 as Vec2};
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+use uuid::Uuid;
+
+const MAX_ASPECT_RATIO: f64 = 25.0;
+const MATERIAL_THUMBNAIL_SIZE: f32 = 16.0;
 
 impl HomeFlow {
     pub fn hover_select(&mut self, response: &egui::Response, ui: &Ui) -> Option<HoverDetails> {
         // Hover over rooms and furniture
         let mut hovered_data = None;
         for room in self.layout.rooms.iter().rev() {
-            if room.contains(self.mouse_pos_world) {
+            if self.device.tags_visible(&room.tags) && room.contains(self.mouse_pos_world) {
                 hovered_data = Some(HoverDetails {
                     id: room.id,
                     object_type: ObjectType::Room,
-                    can_drag: true,
+                    can_drag: !room.locked,
                     pos: room.pos,
                     size: room.size,
                     rotation: 0,
@@ -32,10 +39,21 @@ impl HomeFlow {
             }
         }
 
-        // Click to select room
+        // Click to select room, shift click to add or remove it from the selection
         if response.clicked() {
-            self.edit_mode.selected_id = hovered_data.as_ref().map(|d| d.id);
-            self.edit_mode.selected_type = hovered_data.as_ref().map(|d| d.object_type);
+            let selected_ids = &mut self.edit_mode.selected_ids;
+            match &hovered_data {
+                Some(data) if ui.input(|i| i.modifiers.shift) => {
+                    if selected_ids.contains(&data.id) {
+                        selected_ids.retain(|&id| id != data.id);
+                    } else {
+                        selected_ids.push(data.id);
+                    }
+                }
+                Some(data) => *selected_ids = vec![data.id],
+                None => selected_ids.clear(),
+            }
+            self.edit_mode.selected_type = (!selected_ids.is_empty()).then_some(ObjectType::Room);
             self.edit_mode.drag_data = None;
         }
 
@@ -47,22 +65,49 @@ impl HomeFlow {
                 can_drag: true,
                 pos: drag_data.start_pos,
                 size: drag_data.start_size,
-                rotation: drag_data.start_rotation,
+                rotation: drag_data.start_rotation + drag_data.rotation_offset,
                 manipulation_type: drag_data.manipulation_type,
             });
         }
 
+        // Several selected rooms can only be moved together as a group
+        if self.edit_mode.selected_ids.len() > 1 {
+            let selected_ids = &self.edit_mode.selected_ids;
+            hovered_data = self
+                .layout
+                .rooms
+                .iter()
+                .rev()
+                .find(|r| {
+                    selected_ids.contains(&r.id)
+                        && self.device.tags_visible(&r.tags)
+                        && r.contains(self.mouse_pos_world)
+                })
+                .map(|room| HoverDetails {
+                    id: room.id,
+                    object_type: ObjectType::Room,
+                    can_drag: !room.locked,
+                    pos: room.pos,
+                    size: room.size,
+                    rotation: 0,
+                    manipulation_type: ManipulationType::Move,
+                });
+        }
         // Selected room limits hover scope
-        if self.edit_mode.selected_type == Some(ObjectType::Room) {
+        else if let [selected_id] = *self.edit_mode.selected_ids.as_slice() {
             hovered_data = None;
-            let selected_id = self.edit_mode.selected_id.unwrap();
-            let room = self.layout.rooms.iter().find(|r| r.id == selected_id);
+            let room = self
+                .layout
+                .rooms
+                .iter()
+                .find(|r| r.id == selected_id)
+                .filter(|r| self.device.tags_visible(&r.tags));
             if let Some(room) = room {
                 if room.contains(self.mouse_pos_world) {
                     hovered_data = Some(HoverDetails {
                         id: room.id,
                         object_type: ObjectType::Room,
-                        can_drag: true,
+                        can_drag: !room.locked,
                         pos: room.pos,
                         size: room.size,
                         rotation: 0,
@@ -126,14 +171,17 @@ impl HomeFlow {
                     }
                 }
                 for obj in room.furniture.iter().rev() {
-                    if obj.contains(room.pos, self.mouse_pos_world) {
+                    if self.device.tags_visible(&obj.tags)
+                        && room.furniture_contains(obj, self.mouse_pos_world)
+                    {
+                        let (pos, rotation) = room.furniture_placement(obj);
                         hovered_data = Some(HoverDetails {
                             id: obj.id,
                             object_type: ObjectType::Furniture,
-                            can_drag: true,
-                            pos: room.pos + obj.pos,
+                            can_drag: !obj.locked,
+                            pos,
                             size: obj.size,
-                            rotation: obj.rotation,
+                            rotation,
                             manipulation_type: ManipulationType::Move,
                         });
                         break;
@@ -144,7 +192,7 @@ impl HomeFlow {
 
         // Escape to deselect object
         if ui.input(|i| i.key_pressed(Key::Escape)) {
-            self.edit_mode.selected_id = None;
+            self.edit_mode.selected_ids.clear();
             self.edit_mode.selected_type = None;
             self.edit_mode.drag_data = None;
         }
@@ -152,6 +200,7 @@ impl HomeFlow {
         // If room/operation/zone/furniture, check if at the edge of bounds to resize
         if let Some(data) = &mut hovered_data {
             if self.edit_mode.resize_enabled
+                && self.edit_mode.selected_ids.len() <= 1
                 && matches!(
                     data.object_type,
                     ObjectType::Room
@@ -170,9 +219,16 @@ impl HomeFlow {
                 // Calculate the rotated direction vectors for the four directions
                 let right_dir = rotate_point_i32(vec2(1.0, 0.0), -data.rotation);
                 let up_dir = rotate_point_i32(vec2(0.0, 1.0), -data.rotation);
-                let screen_size = data.size / 2.0 * self.stored.zoom;
+                let screen_size = data.size / 2.0 * self.device.zoom;
 
                 let threshold = 20.0;
+                // Walls are only resized from their ends
+                let is_wall = data.object_type == ObjectType::Operation
+                    && self.layout.rooms.iter().any(|room| {
+                        room.operations
+                            .iter()
+                            .any(|o| o.id == data.id && o.action == Action::Wall)
+                    });
 
                 if (local_mouse_pos.x + 1.0).abs() * screen_size.x < threshold {
                     data.manipulation_type = ManipulationType::ResizeLeft;
@@ -180,10 +236,10 @@ impl HomeFlow {
                 } else if (local_mouse_pos.x - 1.0).abs() * screen_size.x < threshold {
                     data.manipulation_type = ManipulationType::ResizeRight;
                     data.pos += right_dir * data.size.x / 2.0;
-                } else if (local_mouse_pos.y - 1.0).abs() * screen_size.y < threshold {
+                } else if !is_wall && (local_mouse_pos.y - 1.0).abs() * screen_size.y < threshold {
                     data.manipulation_type = ManipulationType::ResizeTop;
                     data.pos += up_dir * data.size.y / 2.0;
-                } else if (local_mouse_pos.y + 1.0).abs() * screen_size.y < threshold {
+                } else if !is_wall && (local_mouse_pos.y + 1.0).abs() * screen_size.y < threshold {
                     data.manipulation_type = ManipulationType::ResizeBottom;
                     data.pos -= up_dir * data.size.y / 2.0;
                 }
@@ -204,6 +260,9 @@ impl HomeFlow {
         let delta = self.mouse_pos_world - drag_data.mouse_start_pos;
         let mut new_pos = drag_data.start_pos + vec2(delta.x, delta.y);
         let mut new_rotation = 0.0;
+        if drag_data.object_type == ObjectType::Furniture {
+            new_rotation = f64::from(drag_data.start_rotation + drag_data.rotation_offset);
+        }
 
         let snap_amount = match drag_data.object_type {
             ObjectType::Room
@@ -220,46 +279,19 @@ impl HomeFlow {
                 .iter()
                 .find(|r| r.openings.iter().any(|o| o.id == drag_data.id))
             {
-                let mut closest_distance = f64::MAX;
-                let mut closest_point = None;
-                let mut closest_rotation = None;
-
-                for poly in &room.rendered_data.as_ref().unwrap().polygons {
-                    // Iterate over pairs of consecutive points to represent the edges of the polygon
-                    let points: Vec<_> = poly.exterior().points().collect();
-                    for i in 0..points.len() {
-                        let p1 = point_to_vec2(points[i]);
-                        let p2 = point_to_vec2(points[(i + 1) % points.len()]);
-
-                        // Calculate the closest point on the line segment from p1 to p2 to new_pos
-                        let line_vec = p2 - p1;
-                        let t = ((new_pos - p1).dot(line_vec)) / line_vec.length_squared();
-                        let closest_point_on_segment = p1 + line_vec * t.clamp(0.0, 1.0);
-
-                        // Calculate the distance from new_pos to this closest point on the segment
-                        let distance = (closest_point_on_segment - new_pos).length();
-                        if distance < closest_distance {
-                            closest_distance = distance;
-                            closest_point = Some(closest_point_on_segment);
-                            closest_rotation = Some(-line_vec.y.atan2(line_vec.x).to_degrees());
-                        }
-                    }
-                }
-
-                let snap_threshold = 0.25;
-                if closest_distance < snap_threshold {
-                    new_pos = closest_point.unwrap();
-                    new_rotation = closest_rotation.unwrap();
+                let polygons = &room.rendered_data.as_ref().unwrap().polygons;
+                if let Some((point, wall_rotation)) =
+                    room.nearest_wall(polygons, new_pos, OPENING_SNAP_DISTANCE)
+                {
+                    new_pos = point;
+                    new_rotation = f64::from(along_wall(drag_data.start_rotation, wall_rotation));
 
-                    // If rotation is 0, 90, 180 or 270 degrees, snap to grid along the line
+                    // If the wall is horizontal or vertical, snap to grid along the line
                     if snap {
-                        if new_rotation.abs() < f64::EPSILON
-                            || (new_rotation - 180.0).abs() < f64::EPSILON
-                        {
+                        let quarter = new_rotation.round().rem_euclid(180.0);
+                        if quarter == 0.0 {
                             new_pos.x = new_pos.x.round_factor(snap_amount);
-                        } else if (new_rotation - 90.0).abs() < f64::EPSILON
-                            || (new_rotation - 270.0).abs() < f64::EPSILON
-                        {
+                        } else if quarter == 90.0 {
                             new_pos.y = new_pos.y.round_factor(snap_amount);
                         }
                     }
@@ -278,86 +310,93 @@ impl HomeFlow {
             )
         {
             // Snap to other rooms
-            let mut closest_horizontal_snap_line = None;
-            let mut closest_vertical_snap_line: Option<(f64, f64, usize)> = None;
             let bounds = match drag_data.manipulation_type {
                 ManipulationType::Move => vec2(0.5, 0.5),
                 ManipulationType::ResizeLeft | ManipulationType::ResizeRight => vec2(0.0, 0.5),
                 ManipulationType::ResizeTop | ManipulationType::ResizeBottom => vec2(0.5, 0.0),
             };
-            let (bounds_min, bounds_max) = (
-                new_pos - bounds * drag_data.start_size,
-                new_pos + bounds * drag_data.start_size,
-            );
-            let snap_threshold = 0.1;
-
-            for other_room in &self.layout.rooms {
-                if other_room.id == drag_data.id {
-                    continue;
-                }
-                let (other_min, other_max) =
+            let other_bounds = self
+                .layout
+                .rooms
+                .iter()
+                .filter(|other_room| {
+                    other_room.id != drag_data.id
+                        && !drag_data.group.iter().any(|(id, _)| *id == other_room.id)
+                })
+                .map(|other_room| {
                     if other_room.operations.iter().any(|o| o.id == drag_data.id) {
                         other_room.self_bounds()
                     } else {
                         other_room.bounds()
-                    };
-
-                for is_vertical in [false, true] {
-                    let (bounds, other_bounds, closest_snap_line) = if is_vertical {
-                        (
-                            [bounds_min.x, bounds_max.x],
-                            [other_min.x, other_max.x],
-                            &mut closest_vertical_snap_line,
-                        )
-                    } else {
-                        (
-                            [bounds_min.y, bounds_max.y],
-                            [other_min.y, other_max.y],
-                            &mut closest_horizontal_snap_line,
-                        )
-                    };
-
-                    for (index, &room_edge) in bounds.iter().enumerate() {
-                        for &other_edge in &other_bounds {
-                            if is_vertical {
-                                if !(bounds_min.y < other_max.y + snap_threshold
-                                    && bounds_max.y > other_min.y - snap_threshold)
-                                {
-                                    continue;
-                                }
-                            } else if !(bounds_min.x < other_max.x + snap_threshold
-                                && bounds_max.x > other_min.x - snap_threshold)
-                            {
-                                continue;
-                            }
-
-                            let distance = (room_edge - other_edge).abs();
-                            if distance < snap_threshold
-                                && closest_snap_line.map_or(true, |(_, dist, _)| distance < dist)
-                            {
-                                *closest_snap_line = Some((other_edge, distance, index));
-                            }
-                        }
                     }
-                }
+                })
+                .collect::<Vec<_>>();
+            (snap_line_x, snap_line_y) = snap_to_bounds(
+                &mut new_pos,
+                bounds * drag_data.start_size,
+                &other_bounds,
+                snap_amount,
+                0.1,
+            );
+        } else if snap
+            && drag_data.object_type == ObjectType::Furniture
+            && matches!(drag_data.manipulation_type, ManipulationType::Move)
+        {
+            // Snap flush against the edges of other furniture and the faces of walls
+            let mut other_bounds = self
+                .layout
+                .rooms
+                .iter()
+                .flat_map(|room| {
+                    room.furniture
+                        .iter()
+                        .filter(|f| f.id != drag_data.id)
+                        .map(|f| rotated_bounds(room.pos + f.pos, f.size, f.rotation))
+                })
+                .collect::<Vec<_>>();
+            if let Some(rendered_data) = &self.layout.rendered_data {
+                other_bounds.extend(
+                    rendered_data
+                        .wall_lines
+                        .iter()
+                        .filter(|(start, end)| {
+                            (start.x - end.x).abs() < f64::EPSILON
+                                || (start.y - end.y).abs() < f64::EPSILON
+                        })
+                        .map(|(start, end)| (start.min(*end), start.max(*end))),
+                );
             }
-            new_pos.y = if let Some((snap_line, _, edge)) = closest_horizontal_snap_line {
-                snap_line_x = Some(snap_line);
-                snap_line + (bounds_max.y - bounds_min.y) / 2.0 * if edge == 0 { 1.0 } else { -1.0 }
-            } else {
-                new_pos.y.round_factor(snap_amount)
-            };
-            new_pos.x = if let Some((snap_line, _, edge)) = closest_vertical_snap_line {
-                snap_line_y = Some(snap_line);
-                snap_line + (bounds_max.x - bounds_min.x) / 2.0 * if edge == 0 { 1.0 } else { -1.0 }
-            } else {
-                new_pos.x.round_factor(snap_amount)
-            };
+            // Threshold is set in screen space so snapping feels the same at any zoom
+            let snap_threshold = (self.device.wall_snap_distance / self.device.zoom).max(0.1);
+            let (min, max) = rotated_bounds(
+                Vec2::ZERO,
+                drag_data.start_size,
+                drag_data.start_rotation + drag_data.rotation_offset,
+            );
+            (snap_line_x, snap_line_y) = snap_to_bounds(
+                &mut new_pos,
+                (max - min) / 2.0,
+                &other_bounds,
+                snap_amount,
+                snap_threshold,
+            );
         } else {
             new_pos.x = new_pos.x.round_factor(snap_amount);
             new_pos.y = new_pos.y.round_factor(snap_amount);
         }
 
+        // Absolute grid on any axis not already snapped to an edge, openings stay on their wall
+        if let Some(grid_size) = self.edit_mode.grid_size.filter(|_| snap) {
+            if drag_data.object_type != ObjectType::Opening {
+                if snap_line_x.is_none() {
+                    new_pos.y = (new_pos.y / grid_size).round() * grid_size;
+                }
+                if snap_line_y.is_none() {
+                    new_pos.x = (new_pos.x / grid_size).round() * grid_size;
+                }
+            }
+        }
+
         (
             new_pos,
             new_rotation.round() as i32,
@@ -365,6 +404,163 @@ impl HomeFlow {
             snap_line_y,
         )
     }
+
+    /// Fill the dragged distance with evenly spaced copies of the dragged furniture
+    pub fn handle_array_drag(&mut self, ui: &Ui, snap: bool) {
+        let info_pos = self.world_to_screen(self.mouse_pos_world) + vec2(0.0, -60.0);
+        let (units, precision) = (self.device.units, self.device.coordinate_precision);
+        let Some(drag_data) = &mut self.edit_mode.drag_data else {
+            return;
+        };
+        let delta = self.mouse_pos_world - drag_data.mouse_start_pos;
+        let Some(copies) = &mut drag_data.array else {
+            return;
+        };
+        let Some(room) = self
+            .layout
+            .rooms
+            .iter_mut()
+            .find(|r| r.furniture.iter().any(|f| f.id == drag_data.id))
+        else {
+            return;
+        };
+        let source = room
+            .furniture
+            .iter()
+            .find(|f| f.id == drag_data.id)
+            .unwrap()
+            .clone();
+
+        // Lock to the dominant axis unless snapping is disabled
+        let direction = if !snap {
+            delta.normalize_or_zero()
+        } else if delta.x.abs() > delta.y.abs() {
+            vec2(delta.x.signum(), 0.0)
+        } else {
+            vec2(0.0, delta.y.signum())
+        };
+        // Copies sit on the same furniture as the source, so step along the drag in its frame
+        let (_, rotation) = room.furniture_placement(&source);
+        let parent_rotation = rotation - source.rotation;
+        let (min, max) = rotated_bounds(Vec2::ZERO, source.size, rotation);
+        let extent = max - min;
+        let spacing = (direction.x.abs() * extent.x + direction.y.abs() * extent.y).max(0.01);
+        let count = (delta.dot(direction) / spacing).floor().max(0.0) as usize;
+
+        while copies.len() > count {
+            let id = copies.pop().unwrap();
+            room.furniture.retain(|f| f.id != id);
+        }
+        while copies.len() < count {
+            let mut new_furniture = source.clone();
+            new_furniture.id = Uuid::new_v4();
+            copies.push(new_furniture.id);
+            room.furniture.push(new_furniture);
+        }
+        for (index, id) in copies.iter().enumerate() {
+            if let Some(furniture) = room.furniture.iter_mut().find(|f| f.id == *id) {
+                furniture.pos = source.pos
+                    + rotate_point_i32(direction * spacing * (index + 1) as f64, parent_rotation);
+            }
+        }
+
+        Window::new("Dragging Info")
+            .fixed_pos(vec2_to_egui_pos(info_pos))
+            .fixed_size([200.0, 0.0])
+            .pivot(Align2::CENTER_CENTER)
+            .title_bar(false)
+            .resizable(false)
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                ui.label(format!("Copies: {count}"));
+                ui.label(format!(
+                    "Spacing: {}",
+                    format_length(spacing, units, precision)
+                ));
+            });
+    }
+}
+
+/// Snaps the edges of a box centered on `pos` to the nearest edges of `other_bounds`,
+/// falling back to rounding by `snap_amount`, returns the snapped lines if any
+fn snap_to_bounds(
+    pos: &mut Vec2,
+    half_size: Vec2,
+    other_bounds: &[(Vec2, Vec2)],
+    snap_amount: f64,
+    snap_threshold: f64,
+) -> (Option<f64>, Option<f64>) {
+    let mut closest_horizontal_snap_line: Option<(f64, f64, usize)> = None;
+    let mut closest_vertical_snap_line: Option<(f64, f64, usize)> = None;
+    let (bounds_min, bounds_max) = (*pos - half_size, *pos + half_size);
+
+    for &(other_min, other_max) in other_bounds {
+        for is_vertical in [false, true] {
+            let (bounds, other_bounds, closest_snap_line) = if is_vertical {
+                (
+                    [bounds_min.x, bounds_max.x],
+                    [other_min.x, other_max.x],
+                    &mut closest_vertical_snap_line,
+                )
+            } else {
+                (
+                    [bounds_min.y, bounds_max.y],
+                    [other_min.y, other_max.y],
+                    &mut closest_horizontal_snap_line,
+                )
+            };
+
+            for (index, &edge) in bounds.iter().enumerate() {
+                for &other_edge in &other_bounds {
+                    if is_vertical {
+                        if !(bounds_min.y < other_max.y + snap_threshold
+                            && bounds_max.y > other_min.y - snap_threshold)
+                        {
+                            continue;
+                        }
+                    } else if !(bounds_min.x < other_max.x + snap_threshold
+                        && bounds_max.x > other_min.x - snap_threshold)
+                    {
+                        continue;
+                    }
+
+                    let distance = (edge - other_edge).abs();
+                    if distance < snap_threshold
+                        && closest_snap_line.is_none_or(|(_, dist, _)| distance < dist)
+                    {
+                        *closest_snap_line = Some((other_edge, distance, index));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut snap_line_x = None;
+    let mut snap_line_y = None;
+    pos.y = if let Some((snap_line, _, edge)) = closest_horizontal_snap_line {
+        snap_line_x = Some(snap_line);
+        snap_line + (bounds_max.y - bounds_min.y) / 2.0 * if edge == 0 { 1.0 } else { -1.0 }
+    } else {
+        pos.y.round_factor(snap_amount)
+    };
+    pos.x = if let Some((snap_line, _, edge)) = closest_vertical_snap_line {
+        snap_line_y = Some(snap_line);
+        snap_line + (bounds_max.x - bounds_min.x) / 2.0 * if edge == 0 { 1.0 } else { -1.0 }
+    } else {
+        pos.x.round_factor(snap_amount)
+    };
+    (snap_line_x, snap_line_y)
+}
+
+/// Axis aligned bounds of a rotated rectangle
+fn rotated_bounds(pos: Vec2, size: Vec2, rotation: i32) -> (Vec2, Vec2) {
+    Shape::Rectangle
+        .vertices(pos, size, rotation)
+        .into_iter()
+        .fold(
+            (Vec2::splat(f64::INFINITY), Vec2::splat(f64::NEG_INFINITY)),
+            |(min, max), corner| (min.min(corner), max.max(corner)),
+        )
 }
 
 pub fn apply_standard_transform(
@@ -422,28 +618,103 @@ pub fn combo_box_for_enum<T>(
         });
 }
 
+/// Material names with a tinted swatch of their texture, textures are keyed like the render
+/// cache and any not loaded yet just leave a gap
 pub fn combo_box_for_materials(
     ui: &mut egui::Ui,
     id: &str,
     materials: &[GlobalMaterial],
+    textures: &AHashMap<String, TextureHandle>,
     selected: &mut String,
 ) {
     ComboBox::from_id_salt(format!("Materials {id}"))
         .selected_text(selected.clone())
         .show_ui(ui, |ui| {
             for material in materials {
-                ui.selectable_value(selected, material.name.clone(), &material.name);
+                ui.horizontal(|ui| {
+                    let size = egui::vec2(MATERIAL_THUMBNAIL_SIZE, MATERIAL_THUMBNAIL_SIZE);
+                    match textures.get(&material.material.to_string()) {
+                        Some(texture) => {
+                            ui.add(
+                                egui::Image::new((texture.id(), size))
+                                    .tint(material.tint.to_egui()),
+                            );
+                        }
+                        None => {
+                            ui.add_space(size.x + ui.spacing().item_spacing.x);
+                        }
+                    }
+                    ui.selectable_value(selected, material.name.clone(), &material.name);
+                });
             }
         });
 }
 
-pub fn edit_vec2(ui: &mut egui::Ui, label: &str, vec2: &mut Vec2, speed: f32) {
+pub fn edit_vec2(ui: &mut egui::Ui, label: &str, vec2: &mut Vec2, speed: f32, precision: usize) {
     labelled_widget(ui, label, |ui| {
-        ui.add(egui::DragValue::new(&mut vec2.x).speed(speed).prefix("X: "));
-        ui.add(egui::DragValue::new(&mut vec2.y).speed(speed).prefix("Y: "));
+        ui.add(
+            egui::DragValue::new(&mut vec2.x)
+                .speed(speed)
+                .fixed_decimals(precision)
+                .prefix("X: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut vec2.y)
+                .speed(speed)
+                .fixed_decimals(precision)
+                .prefix("Y: "),
+        );
     });
 }
 
+/// Whether a resized shape has become a sliver, generous enough to allow long hallways
+pub fn is_extreme_aspect_ratio(size: Vec2) -> bool {
+    let (short, long) = (size.min_element().abs(), size.max_element().abs());
+    long > 0.0 && (short < 0.01 || long / short > MAX_ASPECT_RATIO)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Default, Debug)]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+/// Format a length in meters for display, imperial lengths show as feet and inches like 8' 6"
+/// with the precision applied to the inches
+pub fn format_length(meters: f64, units: Units, precision: usize) -> String {
+    match units {
+        Units::Metric => format!("{meters:.precision$}m"),
+        Units::Imperial => {
+            let sign = if meters < 0.0 { "-" } else { "" };
+            let total_inches = meters.abs() / 0.0254;
+            let rounding = 10f64.powi(precision as i32);
+            let mut feet = (total_inches / 12.0).floor();
+            let mut inches = ((total_inches - feet * 12.0) * rounding).round() / rounding;
+            if inches >= 12.0 {
+                feet += 1.0;
+                inches -= 12.0;
+            }
+            if feet > 0.0 {
+                format!("{sign}{feet}' {inches:.precision$}\"")
+            } else {
+                format!("{sign}{inches:.precision$}\"")
+            }
+        }
+    }
+}
+
+/// Format an area in square meters for display, imperial areas show in square feet
+pub fn format_area(square_meters: f64, units: Units, precision: usize) -> String {
+    match units {
+        Units::Metric => format!("{square_meters:.precision$}m²"),
+        Units::Imperial => format!(
+            "{:.precision$} sq ft",
+            square_meters / 0.0254_f64.powi(2) / 144.0
+        ),
+    }
+}
+
 pub fn edit_rotation(ui: &mut egui::Ui, rotation: &mut i32) {
     labelled_widget(ui, "Rotation", |ui| {
         let widget = ui.add(DragValue::new(rotation).speed(5).suffix("°"));
@@ -463,6 +734,32 @@ where
     });
 }
 
+/// Comma separated tags, the text is kept while typing and only parsed once focus leaves
+pub fn edit_tags(ui: &mut egui::Ui, id_salt: impl std::hash::Hash, tags: &mut Vec<String>) {
+    let id = egui::Id::new(id_salt);
+    let mut text = ui
+        .data(|data| data.get_temp::<String>(id))
+        .unwrap_or_else(|| tags.join(", "));
+    labelled_widget(ui, "Tags", |ui| {
+        let response = TextEdit::singleline(&mut text)
+            .hint_text("electrical, plumbing")
+            .desired_width(120.0)
+            .show(ui)
+            .response;
+        if response.has_focus() {
+            ui.data_mut(|data| data.insert_temp(id, text.clone()));
+        } else if response.lost_focus() {
+            tags.clear();
+            for tag in text.split(',').map(str::trim) {
+                if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                    tags.push(tag.to_owned());
+                }
+            }
+            ui.data_mut(|data| data.remove::<String>(id));
+        }
+    });
+}
+
 pub fn edit_option<T, F, D>(
     ui: &mut egui::Ui,
     label: &str,
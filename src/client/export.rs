@@ -0,0 +1,654 @@
+use crate::{
+    client::{
+        edit_mode_render::operation_color,
+        render::{
+            phase_style, BAY_MAX_DEPTH, DOOR_COLOR, EXTERIOR_WALL_SHADE, WALL_COLOR, WINDOW_COLOR,
+        },
+        DeviceData, HomeFlow,
+    },
+    common::{
+        color::Color,
+        furniture::Furniture,
+        layout::{Home, Opening, OpeningType, Phase, Room, Triangles, WindowShape},
+        shape::{point_to_vec2, EXTERIOR_WALL_EXTRA, WALL_WIDTH},
+        utils::{rotate_point, rotate_point_i32, rotate_point_pivot, Material},
+    },
+};
+use ahash::{AHashMap, AHashSet};
+use egui::Color32;
+use glam::{dvec2 as vec2, DVec2 as Vec2};
+use image::{Rgba, RgbaImage};
+use std::{path::PathBuf, time::Duration};
+use uuid::Uuid;
+
+const BACKGROUND: Rgba<u8> = Rgba([25, 25, 35, 255]);
+const MAX_DIMENSION: f64 = 8192.0;
+// Width in meters of phase and operation outlines, 3 pixels at the default resolution
+const OUTLINE_WIDTH: f64 = 0.03;
+
+/// What goes into an exported image, taken from the device settings
+pub struct ExportOptions {
+    pub pixels_per_meter: f64,
+    pub include_lighting: bool,
+    pub include_operations: bool,
+    // Meters of background left around the outermost walls
+    pub margin: f64,
+    pub phase_filter: Option<Phase>,
+    pub hidden_tags: AHashSet<String>,
+    // World rectangle to draw in place of the rooms plus the margin
+    pub bounds: Option<(Vec2, Vec2)>,
+}
+
+impl ExportOptions {
+    pub fn from_device(device: &DeviceData) -> Self {
+        Self {
+            pixels_per_meter: device.export_resolution,
+            include_lighting: device.export_lighting,
+            include_operations: device.export_operations,
+            margin: device.export_margin,
+            phase_filter: device.phase_filter,
+            hidden_tags: device.hidden_tags.clone(),
+            bounds: None,
+        }
+    }
+
+    fn phase_visible(&self, phase: Phase) -> bool {
+        self.phase_filter.is_none_or(|p| p == phase)
+    }
+
+    fn tags_visible(&self, tags: &[String]) -> bool {
+        tags.is_empty() || tags.iter().any(|tag| !self.hidden_tags.contains(tag))
+    }
+}
+
+/// Premultiplied colour channels from 0 to 1
+fn color_channels(color: Color32) -> [f32; 4] {
+    color.to_array().map(|c| f32::from(c) / 255.0)
+}
+
+/// Software rasterizer drawing the tessellated layout into an image, world space is mapped
+/// so the home bounds plus a margin fill the image
+struct Canvas {
+    image: RgbaImage,
+    min: Vec2,
+    max: Vec2,
+    pixels_per_meter: f64,
+    textures: AHashMap<Material, RgbaImage>,
+    // World rectangles drawing is kept inside, anywhere when empty
+    clip: Vec<(Vec2, Vec2)>,
+}
+
+impl Canvas {
+    fn new(min: Vec2, max: Vec2, pixels_per_meter: f64) -> Self {
+        let size = ((max - min) * pixels_per_meter).ceil().max(Vec2::ONE);
+        Self {
+            image: RgbaImage::from_pixel(size.x as u32, size.y as u32, BACKGROUND),
+            min,
+            max,
+            pixels_per_meter,
+            textures: AHashMap::new(),
+            clip: Vec::new(),
+        }
+    }
+
+    fn clipped(&self, world: Vec2) -> bool {
+        !self.clip.is_empty()
+            && !self
+                .clip
+                .iter()
+                .any(|&(min, max)| world.cmpge(min).all() && world.cmple(max).all())
+    }
+
+    fn to_pixel(&self, world: Vec2) -> Vec2 {
+        vec2(world.x - self.min.x, self.max.y - world.y) * self.pixels_per_meter
+    }
+
+    fn to_world(&self, pixel: Vec2) -> Vec2 {
+        let offset = pixel / self.pixels_per_meter;
+        vec2(self.min.x + offset.x, self.max.y - offset.y)
+    }
+
+    /// Texture colour at a uv, repeating like the on screen textures do
+    fn sample(&mut self, material: Material, uv: Vec2) -> [f32; 4] {
+        let texture = self.textures.entry(material).or_insert_with(|| {
+            image::load_from_memory(material.get_image())
+                .map(|image| image.into_rgba8())
+                .unwrap_or_else(|_| RgbaImage::from_pixel(1, 1, Rgba([255; 4])))
+        });
+        let (width, height) = texture.dimensions();
+        let x = (uv.x.rem_euclid(1.0) * f64::from(width)) as u32;
+        let y = (uv.y.rem_euclid(1.0) * f64::from(height)) as u32;
+        let [r, g, b, a] = texture.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+        let alpha = f32::from(a) / 255.0;
+        // Premultiply to match the tint colours
+        [
+            f32::from(r) / 255.0 * alpha,
+            f32::from(g) / 255.0 * alpha,
+            f32::from(b) / 255.0 * alpha,
+            alpha,
+        ]
+    }
+
+    /// Blend a premultiplied colour over the pixel
+    fn blend(&mut self, x: u32, y: u32, color: [f32; 4]) {
+        let pixel = self.image.get_pixel_mut(x, y);
+        for channel in 0..3 {
+            let dst = f32::from(pixel.0[channel]) / 255.0;
+            pixel.0[channel] = ((color[channel] + dst * (1.0 - color[3])) * 255.0) as u8;
+        }
+    }
+
+    /// Fill the triangles, shading each covered pixel from its world position
+    fn fill<F>(&mut self, triangles: &Triangles, transform: impl Fn(Vec2) -> Vec2, mut shade: F)
+    where
+        F: FnMut(&mut Self, Vec2, Vec2) -> [f32; 4],
+    {
+        let (width, height) = self.image.dimensions();
+        for corners in triangles.indices.chunks_exact(3) {
+            let local = [0, 1, 2].map(|i| triangles.vertices[corners[i] as usize]);
+            let [a, b, c] = local.map(|v| self.to_pixel(transform(v)));
+            let area = (b - a).perp_dot(c - a);
+            if area.abs() < f64::EPSILON {
+                continue;
+            }
+            let min = a.min(b).min(c).floor().max(Vec2::ZERO);
+            let max = a
+                .max(b)
+                .max(c)
+                .ceil()
+                .min(vec2(f64::from(width), f64::from(height)));
+            for y in min.y as u32..max.y as u32 {
+                for x in min.x as u32..max.x as u32 {
+                    let p = vec2(f64::from(x), f64::from(y)) + 0.5;
+                    let w0 = (c - b).perp_dot(p - b) / area;
+                    let w1 = (a - c).perp_dot(p - c) / area;
+                    let w2 = 1.0 - w0 - w1;
+                    if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                        continue;
+                    }
+                    let local_pos = local[0] * w0 + local[1] * w1 + local[2] * w2;
+                    let world_pos = self.to_world(p);
+                    if self.clipped(world_pos) {
+                        continue;
+                    }
+                    let color = shade(self, local_pos, world_pos);
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn fill_textured(
+        &mut self,
+        triangles: &Triangles,
+        transform: impl Fn(Vec2) -> Vec2,
+        material: Material,
+        tint: Color,
+        uv_scale: f64,
+        uv_rotation: i32,
+    ) {
+        let tint = [tint.r(), tint.g(), tint.b(), tint.a()].map(|c| f32::from(c) / 255.0);
+        self.fill(triangles, transform, |canvas, local, _| {
+            let texel = canvas.sample(material, rotate_point_i32(local, uv_rotation) * uv_scale);
+            [0, 1, 2, 3].map(|i| texel[i] * tint[i])
+        });
+    }
+
+    /// Straight segments between the points, each a quad of the width in meters
+    fn polyline(&mut self, points: &[Vec2], width: f64, color: Color32) {
+        let color = color_channels(color);
+        for segment in points.windows(2) {
+            let side = (segment[1] - segment[0]).normalize_or_zero().perp() * width / 2.0;
+            let quad = Triangles {
+                indices: vec![0, 1, 2, 0, 2, 3],
+                vertices: vec![
+                    segment[0] - side,
+                    segment[1] - side,
+                    segment[1] + side,
+                    segment[0] + side,
+                ],
+            };
+            self.fill(&quad, |v| v, |_, _, _| color);
+        }
+    }
+
+    /// Dashes along the closed outline, the dash and gap lengths in meters
+    fn dashed_outline(&mut self, points: &[Vec2], width: f64, dash: f64, gap: f64, color: Color32) {
+        let Some(&first) = points.first() else {
+            return;
+        };
+        let period = dash + gap;
+        let mut distance = 0.0;
+        for (&start, &end) in points.iter().zip(points.iter().skip(1).chain([&first])) {
+            let length = start.distance(end);
+            let direction = (end - start).normalize_or_zero();
+            // Dashes carry on from the previous edge
+            let mut dash_start = -(distance % period);
+            while dash_start < length {
+                let (from, to) = (dash_start.max(0.0), (dash_start + dash).min(length));
+                if to > from {
+                    self.polyline(
+                        &[start + direction * from, start + direction * to],
+                        width,
+                        color,
+                    );
+                }
+                dash_start += period;
+            }
+            distance += length;
+        }
+    }
+
+    fn circle(&mut self, center: Vec2, radius: f64, color: Color32) {
+        let color = color_channels(color);
+        let fan = Triangles {
+            indices: (1..=16).flat_map(|i| [0, i, i % 16 + 1]).collect(),
+            vertices: std::iter::once(center)
+                .chain((0..16).map(|i| {
+                    let angle = f64::from(i) / 16.0 * std::f64::consts::TAU;
+                    center + Vec2::from_angle(angle) * radius
+                }))
+                .collect(),
+        };
+        self.fill(&fan, |v| v, |_, _, _| color);
+    }
+}
+
+/// Rasterize the home as the options set, with the rooms and furniture hidden by the phase
+/// filter or tags left out. With ids only the rooms among them or holding furniture among them
+/// are drawn, with their walls and furniture
+pub fn render_png(home: &Home, options: &ExportOptions, only: Option<&[Uuid]>) -> RgbaImage {
+    let visible =
+        |room: &Room| options.phase_visible(room.phase) && options.tags_visible(&room.tags);
+    let selected = |room: &Room| {
+        only.is_none_or(|ids| {
+            ids.contains(&room.id) || room.furniture.iter().any(|f| ids.contains(&f.id))
+        })
+    };
+    // Nothing matching the filter exports the whole home
+    let rooms: Vec<&Room> = if home
+        .rooms
+        .iter()
+        .any(|room| visible(room) && selected(room))
+    {
+        home.rooms
+            .iter()
+            .filter(|room| visible(room) && selected(room))
+            .collect()
+    } else {
+        home.rooms.iter().filter(|room| visible(room)).collect()
+    };
+    let filtered = rooms.len() < home.rooms.len();
+
+    let (min, max) = options.bounds.unwrap_or_else(|| {
+        let (min, max) = rooms.iter().map(|room| room.bounds()).fold(
+            (Vec2::splat(f64::INFINITY), Vec2::splat(f64::NEG_INFINITY)),
+            |(min, max), (room_min, room_max)| (min.min(room_min), max.max(room_max)),
+        );
+        let margin = Vec2::splat(options.margin.max(0.0) + WALL_WIDTH);
+        (min - margin, max + margin)
+    });
+    if !min.is_finite() || !max.is_finite() {
+        return RgbaImage::from_pixel(1, 1, BACKGROUND);
+    }
+    let pixels_per_meter = options
+        .pixels_per_meter
+        .min(MAX_DIMENSION / (max - min).max_element());
+    let mut canvas = Canvas::new(min, max, pixels_per_meter);
+
+    // Floors, faded and outlined by their phase
+    for room in &rooms {
+        let Some(rendered_data) = &room.rendered_data else {
+            continue;
+        };
+        let (phase_opacity, phase_outline) = phase_style(room.phase);
+        for (layer, multi_triangles) in &rendered_data.material_triangles {
+            let global_material = home.layer_material(layer);
+            for triangles in multi_triangles {
+                canvas.fill_textured(
+                    triangles,
+                    |v| v,
+                    global_material.material,
+                    global_material.tint.gamma_multiply(phase_opacity),
+                    0.2 / global_material.scale,
+                    global_material.rotation,
+                );
+            }
+        }
+        if let Some(color) = phase_outline {
+            for polygon in &rendered_data.polygons {
+                let points: Vec<Vec2> = polygon.exterior().points().map(point_to_vec2).collect();
+                if room.phase == Phase::ToRemove {
+                    canvas.dashed_outline(&points, OUTLINE_WIDTH, 0.12, 0.08, color);
+                } else {
+                    canvas.polyline(&points, OUTLINE_WIDTH, color);
+                }
+            }
+        }
+    }
+
+    // Furniture and their children in render order, at rest rather than hover animated
+    let mut pieces: Vec<(&Furniture, Vec2, f64, u8, f32)> = Vec::new();
+    for room in &rooms {
+        for furniture in &room.furniture {
+            if !options.phase_visible(furniture.phase) || !options.tags_visible(&furniture.tags) {
+                continue;
+            }
+            let opacity = phase_style(furniture.phase).0 * phase_style(room.phase).0;
+            let (pos, rotation) = room.furniture_placement(furniture);
+            let order = room.furniture_render_order(furniture);
+            pieces.push((furniture, pos, f64::from(rotation), order, opacity));
+            if let Some(rendered_data) = &furniture.rendered_data {
+                for child in &rendered_data.children {
+                    pieces.push((
+                        child,
+                        pos + rotate_point_i32(child.pos, -rotation),
+                        f64::from(rotation + child.rotation),
+                        child.get_render_order() + order - furniture.get_render_order(),
+                        opacity,
+                    ));
+                }
+            }
+        }
+    }
+    pieces.sort_by_key(|&(_, _, _, order, _)| order);
+    for (furniture, pos, rotation, _, opacity) in pieces {
+        let Some(rendered_data) = &furniture.rendered_data else {
+            continue;
+        };
+        for (material, multi_triangles) in &rendered_data.triangles {
+            for triangles in multi_triangles {
+                canvas.fill_textured(
+                    triangles,
+                    |v| rotate_point(v, -rotation) + pos,
+                    material.material,
+                    material.tint.gamma_multiply(opacity),
+                    0.2,
+                    material.rotation,
+                );
+            }
+        }
+    }
+
+    // Doors below the walls, at rest open or shut
+    for room in &rooms {
+        for opening in room.openings.iter().filter(|o| o.opening_type.is_door()) {
+            let depth = WALL_WIDTH * 0.8;
+            let (hinge_pos, end_pos) = opening_ends(room, opening);
+            canvas.polyline(
+                &[hinge_pos, end_pos],
+                depth * 0.75,
+                Color32::from_rgb(80, 80, 80),
+            );
+            let open_amount = if opening.open { 1.0 } else { 0.0 };
+            let swing = open_amount * 40.0 * if opening.flipped { -1.0 } else { 1.0 };
+            let leaves = match opening.opening_type {
+                OpeningType::DoubleDoor => {
+                    let center = (hinge_pos + end_pos) / 2.0;
+                    vec![
+                        (
+                            hinge_pos,
+                            rotate_point_pivot(center, hinge_pos, swing),
+                            true,
+                        ),
+                        (end_pos, rotate_point_pivot(center, end_pos, -swing), true),
+                    ]
+                }
+                OpeningType::SlidingDoor => {
+                    let slide = (hinge_pos - end_pos) * open_amount * 0.9;
+                    vec![(hinge_pos + slide, end_pos + slide, false)]
+                }
+                _ => vec![(
+                    hinge_pos,
+                    rotate_point_pivot(end_pos, hinge_pos, swing),
+                    true,
+                )],
+            };
+            for (start, end, hinged) in leaves {
+                if hinged {
+                    canvas.circle(start, depth * 0.5, DOOR_COLOR);
+                }
+                canvas.polyline(&[start, end], depth, DOOR_COLOR);
+            }
+        }
+    }
+
+    // Walls are shared between rooms, so a filtered export keeps only those around its rooms
+    if filtered {
+        let wall = Vec2::splat(WALL_WIDTH + EXTERIOR_WALL_EXTRA);
+        canvas.clip = rooms
+            .iter()
+            .map(|room| {
+                let (room_min, room_max) = room.bounds();
+                (room_min - wall, room_max + wall)
+            })
+            .collect();
+    }
+    if let Some(rendered_data) = &home.rendered_data {
+        let (material, tint, scale, rotation) = home.wall_material.as_ref().map_or(
+            (
+                Material::Empty,
+                Color::from_rgb(WALL_COLOR.r(), WALL_COLOR.g(), WALL_COLOR.b()),
+                1.0,
+                0,
+            ),
+            |wall_material| {
+                let global_material = home.get_global_material(wall_material);
+                (
+                    global_material.material,
+                    global_material.tint,
+                    global_material.scale,
+                    global_material.rotation,
+                )
+            },
+        );
+        for triangles in &rendered_data.wall_triangles {
+            canvas.fill_textured(triangles, |v| v, material, tint, 0.2 / scale, rotation);
+        }
+        let [r, g, b] =
+            [tint.r(), tint.g(), tint.b()].map(|c| (f32::from(c) * EXTERIOR_WALL_SHADE) as u8);
+        let exterior_tint = Color::from_rgba_premultiplied(r, g, b, tint.a());
+        for triangles in &rendered_data.exterior_wall_triangles {
+            canvas.fill_textured(
+                triangles,
+                |v| v,
+                material,
+                exterior_tint,
+                0.2 / scale,
+                rotation,
+            );
+        }
+    }
+
+    // Windows above the walls, arches bow out a curved head and bays project a three sided frame
+    for room in &rooms {
+        for opening in &room.openings {
+            if opening.opening_type != OpeningType::Window {
+                continue;
+            }
+            let (hinge_pos, end_pos) = opening_ends(room, opening);
+            let along = (end_pos - hinge_pos).normalize_or_zero();
+            let center = (hinge_pos + end_pos) / 2.0;
+            let outward = if room.contains(center + along.perp() * WALL_WIDTH) {
+                -along.perp()
+            } else {
+                along.perp()
+            };
+            match opening.window_shape {
+                WindowShape::Flat => {
+                    canvas.polyline(&[hinge_pos, end_pos], WALL_WIDTH, WINDOW_COLOR);
+                }
+                WindowShape::Arched => {
+                    let rise = opening.width * 0.3;
+                    let arch: Vec<Vec2> = (0..=16)
+                        .map(|step| {
+                            let t = f64::from(step) / 16.0;
+                            hinge_pos.lerp(end_pos, t)
+                                + outward * rise * (t * std::f64::consts::PI).sin()
+                        })
+                        .collect();
+                    canvas.polyline(&[hinge_pos, end_pos], WALL_WIDTH, WINDOW_COLOR);
+                    canvas.polyline(&arch, WALL_WIDTH * 0.4, WINDOW_COLOR);
+                }
+                WindowShape::Bay => {
+                    let bay_depth = (opening.width * 0.25).min(BAY_MAX_DEPTH);
+                    let frame = [
+                        hinge_pos,
+                        hinge_pos + (outward + along) * bay_depth,
+                        end_pos + (outward - along) * bay_depth,
+                        end_pos,
+                    ];
+                    canvas.polyline(&frame, WALL_WIDTH, WINDOW_COLOR);
+                }
+            }
+        }
+    }
+
+    // Lighting is a premultiplied image stretched over its world rectangle
+    if let Some(light_data) = home
+        .light_data
+        .as_ref()
+        .filter(|_| options.include_lighting)
+    {
+        let light_min = light_data.image_center - light_data.image_size / 2.0;
+        let (light_width, light_height) = (light_data.image_width, light_data.image_height);
+        let (width, height) = canvas.image.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                let world = canvas.to_world(vec2(f64::from(x), f64::from(y)) + 0.5);
+                if canvas.clipped(world) {
+                    continue;
+                }
+                let uv = (world - light_min) / light_data.image_size;
+                if !(0.0..1.0).contains(&uv.x) || !(0.0..1.0).contains(&uv.y) {
+                    continue;
+                }
+                let light_x = (uv.x * f64::from(light_width)) as usize;
+                let light_y = ((1.0 - uv.y) * f64::from(light_height)) as usize;
+                let index = (light_y * light_width as usize + light_x) * 4;
+                let Some(texel) = light_data.image.get(index..index + 4) else {
+                    continue;
+                };
+                let color = [0, 1, 2, 3].map(|i| f32::from(texel[i]) / 255.0);
+                canvas.blend(x, y, color);
+            }
+        }
+    }
+
+    // Operations outlined on top, as the view mode overlay shows them
+    if options.include_operations {
+        canvas.clip.clear();
+        for room in &rooms {
+            for operation in &room.operations {
+                canvas.dashed_outline(
+                    &operation.vertices(room.pos),
+                    OUTLINE_WIDTH,
+                    0.35,
+                    0.35,
+                    operation_color(operation.action),
+                );
+            }
+        }
+    }
+
+    canvas.image
+}
+
+/// Hinge and far end of an opening, swapped when it's flipped
+fn opening_ends(room: &Room, opening: &Opening) -> (Vec2, Vec2) {
+    let (hinge_pos, end_pos) = opening.span(room.pos);
+    if opening.flipped {
+        (end_pos, hinge_pos)
+    } else {
+        (hinge_pos, end_pos)
+    }
+}
+
+/// First file name not already taken in the working directory
+fn unused_export_path(resolution: f64) -> PathBuf {
+    let stem = format!("home_flow_{}ppm", resolution.round());
+    let mut path = PathBuf::from(format!("{stem}.png"));
+    let mut index = 2;
+    while path.exists() {
+        path = PathBuf::from(format!("{stem}_{index}.png"));
+        index += 1;
+    }
+    path
+}
+
+impl HomeFlow {
+    /// Save a PNG of the selection, or the whole home, next to the app. It's drawn on a worker
+    /// thread from a copy of the layout, the browser build has nowhere to write it
+    pub fn export_png(&self, ctx: &egui::Context) {
+        let toasts = self.toasts.clone();
+        if cfg!(target_arch = "wasm32") {
+            toasts
+                .lock()
+                .error("PNG export isn't supported in the browser")
+                .duration(Some(Duration::from_secs(3)));
+            return;
+        }
+        let layout = self.layout.clone();
+        let selected_ids = self.edit_mode.selected_ids.clone();
+        let options = ExportOptions::from_device(&self.device);
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let image = render_png(&layout, &options, Some(&selected_ids));
+            let path = unused_export_path(options.pixels_per_meter);
+            match image.save(&path) {
+                Ok(()) => {
+                    toasts
+                        .lock()
+                        .success(format!(
+                            "Exported {}x{} to {}",
+                            image.width(),
+                            image.height(),
+                            path.display()
+                        ))
+                        .duration(Some(Duration::from_secs(3)));
+                }
+                Err(e) => {
+                    log::error!("Failed to export PNG: {:?}", e);
+                    toasts
+                        .lock()
+                        .error("Failed to export PNG")
+                        .duration(Some(Duration::from_secs(3)));
+                }
+            }
+            ctx.request_repaint();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{furniture::DetailLevel, template};
+
+    #[test]
+    fn layout_without_lighting_renders_lit() {
+        let mut home = template::default();
+        home.render(false, DetailLevel::Full);
+        assert!(home.light_data.is_none());
+
+        for include_lighting in [false, true] {
+            let options = ExportOptions {
+                pixels_per_meter: 20.0,
+                include_lighting,
+                margin: 0.0,
+                ..ExportOptions::from_device(&DeviceData::default())
+            };
+            let image = render_png(&home, &options, None);
+            // Mean luminance of the image, the background alone is around 0.1
+            let luminance = image
+                .pixels()
+                .map(|pixel| {
+                    let [r, g, b, _] = pixel.0.map(|c| f64::from(c) / 255.0);
+                    0.2126 * r + 0.7152 * g + 0.0722 * b
+                })
+                .sum::<f64>()
+                / f64::from(image.width() * image.height());
+            assert!(luminance > 0.3, "mean luminance {luminance}");
+        }
+    }
+}
@@ -1,17 +1,33 @@
 use crate::{
-    client::HomeFlow,
+    client::{edit_mode::ObjectType, vec2_to_egui_pos, HomeFlow},
     common::{
-        layout::{DataPoint, LightType},
+        layout::{DataPoint, LightType, Scene},
         utils::Lerp,
         PostActionsData,
     },
 };
 use ahash::AHashMap;
-use egui::{pos2, Color32, Painter, Pos2, Response, Stroke};
+use egui::{pos2, Align2, Button, Color32, Painter, Pos2, Response, Stroke, Window};
+use glam::DVec2 as Vec2;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter};
+use uuid::Uuid;
 
 #[derive(Default)]
 pub struct IState {
     pub light_drag: Option<LightDrag>,
+    // Room clicked outside edit mode, showing a control for all its lights
+    pub selected_room: Option<Uuid>,
+    // Furniture double clicked outside edit mode, showing a control for its entities
+    pub selected_furniture: Option<Uuid>,
+    pub context_menu: Option<ContextMenu>,
+}
+
+/// Object a long press or right click landed on and the screen position its menu opens at
+pub struct ContextMenu {
+    pub room_id: Uuid,
+    pub furniture_id: Option<Uuid>,
+    pub pos: Pos2,
 }
 
 pub struct LightDrag {
@@ -27,9 +43,490 @@ pub struct LightDrag {
     pub animated_state_target: f64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Debug)]
+pub enum DoubleClickAction {
+    Nothing,
+    ZoomTo,
+    Toggle,
+    Edit,
+}
+
+#[derive(Clone, Copy)]
+enum MenuAction {
+    ZoomTo,
+    Edit,
+    Duplicate,
+    Delete,
+    BringToFront,
+    SendToBack,
+    Lock,
+}
+
 const POPUP_FADE_TIME: f64 = 0.1;
+// Space framed around a light when zooming to it
+const LIGHT_ZOOM_MARGIN: Vec2 = Vec2::splat(1.0);
 
 impl HomeFlow {
+    pub fn handle_double_click(&mut self, response: &Response) {
+        if !response.double_clicked() {
+            return;
+        }
+        // The click that finishes a room, ruler or eyedropper isn't also a double click action
+        let double_click_delay = response
+            .ctx
+            .options(|o| o.input_options.max_double_click_delay);
+        if self.edit_mode.enabled && self.time - self.edit_mode.tool_time <= double_click_delay {
+            return;
+        }
+        // Double clicking a name label renames it instead
+        if self.edit_mode.renaming.is_some() {
+            return;
+        }
+        let mouse_pos = self.mouse_pos_world;
+
+        // Find the top most object under the cursor, lights then furniture then rooms
+        let mut target = None;
+        for (room_index, room) in self.layout.rooms.iter().enumerate().rev() {
+            if !self.device.tags_visible(&room.tags) {
+                continue;
+            }
+            if !self.is_mobile {
+                for light in &room.lights {
+                    if light
+                        .get_points(room.pos, room.size)
+                        .iter()
+                        .any(|point| mouse_pos.distance(*point) < 0.3)
+                    {
+                        target = Some((
+                            self.device.double_click_light,
+                            room_index,
+                            ObjectType::Light,
+                            light.id,
+                        ));
+                        break;
+                    }
+                }
+            }
+            if target.is_none() {
+                if let Some(furniture) = room.furniture.iter().rev().find(|f| {
+                    self.device.tags_visible(&f.tags) && room.furniture_contains(f, mouse_pos)
+                }) {
+                    target = Some((
+                        self.device.double_click_furniture,
+                        room_index,
+                        ObjectType::Furniture,
+                        furniture.id,
+                    ));
+                }
+            }
+            if target.is_none() && room.contains(mouse_pos) {
+                target = Some((
+                    self.device.double_click_room,
+                    room_index,
+                    ObjectType::Room,
+                    room.id,
+                ));
+            }
+            if target.is_some() {
+                break;
+            }
+        }
+        let Some((action, room_index, object_type, object_id)) = target else {
+            return;
+        };
+        let room = &self.layout.rooms[room_index];
+        let light = room.lights.iter().find(|l| l.id == object_id);
+        let furniture = room.furniture.iter().find(|f| f.id == object_id);
+
+        match action {
+            DoubleClickAction::Nothing => {}
+            DoubleClickAction::ZoomTo => {
+                let (min, max) = if let Some(light) = light {
+                    // Lights are points, so frame the space around them
+                    light.get_points(room.pos, room.size).iter().fold(
+                        (mouse_pos - LIGHT_ZOOM_MARGIN, mouse_pos + LIGHT_ZOOM_MARGIN),
+                        |(min, max), &point| {
+                            (
+                                min.min(point - LIGHT_ZOOM_MARGIN),
+                                max.max(point + LIGHT_ZOOM_MARGIN),
+                            )
+                        },
+                    )
+                } else if let Some(furniture) = furniture {
+                    let pos = room.furniture_placement(furniture).0;
+                    let extent = Vec2::splat(furniture.size.length() / 2.0);
+                    (pos - extent, pos + extent)
+                } else {
+                    room.bounds()
+                };
+                self.zoom_to(min, max);
+            }
+            DoubleClickAction::Toggle => {
+                // Toggle the clicked light, or every light in the room
+                let entity_ids = light.map_or_else(
+                    || room.lights.iter().map(|l| l.entity_id.clone()).collect(),
+                    |light| vec![light.entity_id.clone()],
+                );
+                let turn_on = !room
+                    .lights
+                    .iter()
+                    .any(|l| entity_ids.contains(&l.entity_id) && l.state > 127);
+                self.switch_lights(&entity_ids, turn_on);
+            }
+            DoubleClickAction::Edit => {
+                if self.edit_mode.enabled {
+                    self.edit_mode.selected_ids = vec![object_id];
+                    self.edit_mode.selected_type = Some(object_type);
+                } else if furniture.is_some() {
+                    // Outside edit mode furniture opens the control for its entities
+                    self.interaction_state.selected_furniture = Some(object_id);
+                    self.interaction_state.selected_room = None;
+                }
+            }
+        }
+    }
+
+    /// Long pressing a room or furniture on a touch screen opens a menu of actions for it,
+    /// egui reports the long press once the touch is held still past the click duration
+    pub fn handle_long_press(&mut self, response: &Response) {
+        if response.long_touched() {
+            self.open_context_menu();
+        } else if response.clicked() {
+            self.interaction_state.context_menu = None;
+        }
+        self.context_menu_window(&response.ctx);
+    }
+
+    /// Menu for the top furniture or room under the pointer, closed when there's neither
+    pub fn open_context_menu(&mut self) {
+        let mouse_pos = self.mouse_pos_world;
+        self.interaction_state.context_menu = self
+            .layout
+            .rooms
+            .iter()
+            .rev()
+            .filter(|room| self.device.tags_visible(&room.tags))
+            .find_map(|room| {
+                let furniture = room.furniture.iter().rev().find(|f| {
+                    self.device.tags_visible(&f.tags) && room.furniture_contains(f, mouse_pos)
+                });
+                (furniture.is_some() || room.contains(mouse_pos)).then(|| ContextMenu {
+                    room_id: room.id,
+                    furniture_id: furniture.map(|f| f.id),
+                    pos: vec2_to_egui_pos(self.mouse_pos),
+                })
+            });
+    }
+
+    fn context_menu_window(&mut self, ctx: &egui::Context) {
+        let Some(menu) = &self.interaction_state.context_menu else {
+            return;
+        };
+        let (room_id, furniture_id) = (menu.room_id, menu.furniture_id);
+        let Some(room_index) = self.layout.rooms.iter().position(|r| r.id == room_id) else {
+            self.interaction_state.context_menu = None;
+            return;
+        };
+        let room = &self.layout.rooms[room_index];
+        let furniture_index =
+            furniture_id.and_then(|id| room.furniture.iter().position(|f| f.id == id));
+        let (title, bounds, locked) = match furniture_index.map(|index| &room.furniture[index]) {
+            Some(furniture) => {
+                let pos = room.furniture_placement(furniture).0;
+                let extent = Vec2::splat(furniture.size.length() / 2.0);
+                let name = if furniture.name.is_empty() {
+                    furniture.furniture_type.to_string()
+                } else {
+                    furniture.name.clone()
+                };
+                (name, (pos - extent, pos + extent), furniture.locked)
+            }
+            None => (room.name.clone(), room.bounds(), room.locked),
+        };
+
+        let editing = self.edit_mode.enabled;
+        let mut action = None;
+        Window::new("Context Menu")
+            .fixed_pos(menu.pos)
+            .title_bar(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong(&title);
+                for (label, menu_action) in
+                    [("Zoom To", MenuAction::ZoomTo), ("Edit", MenuAction::Edit)]
+                {
+                    if ui.button(label).clicked() {
+                        action = Some(menu_action);
+                    }
+                }
+                for (label, menu_action) in [
+                    ("Duplicate", MenuAction::Duplicate),
+                    ("Delete", MenuAction::Delete),
+                    ("Bring to Front", MenuAction::BringToFront),
+                    ("Send to Back", MenuAction::SendToBack),
+                    (if locked { "Unlock" } else { "Lock" }, MenuAction::Lock),
+                ] {
+                    if ui
+                        .add_enabled(editing, Button::new(label))
+                        .on_disabled_hover_text("Only in edit mode, where it can be undone")
+                        .clicked()
+                    {
+                        action = Some(menu_action);
+                    }
+                }
+            });
+        let Some(action) = action else {
+            return;
+        };
+        self.interaction_state.context_menu = None;
+
+        let room = &mut self.layout.rooms[room_index];
+        match (action, furniture_index) {
+            (MenuAction::ZoomTo, _) => self.zoom_to(bounds.0, bounds.1),
+            (MenuAction::Edit, _) => {
+                self.edit_mode.enabled = true;
+                self.edit_mode.selected_ids = vec![room_id];
+                self.edit_mode.selected_type = Some(ObjectType::Room);
+            }
+            (MenuAction::Duplicate, Some(index)) => {
+                let mut new_furniture = room.furniture[index].clone();
+                new_furniture.id = Uuid::new_v4();
+                room.furniture.insert(index + 1, new_furniture);
+            }
+            (MenuAction::Duplicate, None) => {
+                let mut new_room = room.clone();
+                new_room.regenerate_ids();
+                self.edit_mode.selected_ids = vec![new_room.id];
+                self.edit_mode.selected_type = Some(ObjectType::Room);
+                self.layout.rooms.insert(room_index + 1, new_room);
+            }
+            (MenuAction::Delete, Some(index)) => room.remove_furniture(index),
+            (MenuAction::Delete, None) => {
+                self.layout.rooms.remove(room_index);
+                self.edit_mode.selected_ids.retain(|&id| id != room_id);
+                if self.edit_mode.selected_ids.is_empty() {
+                    self.edit_mode.selected_type = None;
+                }
+            }
+            (MenuAction::BringToFront, Some(index)) => {
+                let furniture = room.furniture.remove(index);
+                room.furniture.push(furniture);
+            }
+            (MenuAction::BringToFront, None) => {
+                let room = self.layout.rooms.remove(room_index);
+                self.layout.rooms.push(room);
+            }
+            (MenuAction::SendToBack, Some(index)) => {
+                let furniture = room.furniture.remove(index);
+                room.furniture.insert(0, furniture);
+            }
+            (MenuAction::SendToBack, None) => {
+                let room = self.layout.rooms.remove(room_index);
+                self.layout.rooms.insert(0, room);
+            }
+            (MenuAction::Lock, Some(index)) => room.furniture[index].locked = !locked,
+            (MenuAction::Lock, None) => room.locked = !locked,
+        }
+    }
+
+    /// Turn every light with one of the entity ids on or off, queueing a single action per entity
+    fn switch_lights(&mut self, entity_ids: &[String], turn_on: bool) {
+        let target_state = if turn_on { 255 } else { 0 };
+        for room in &mut self.layout.rooms {
+            for light in &mut room.lights {
+                if !entity_ids.contains(&light.entity_id) {
+                    continue;
+                }
+                light.state = target_state;
+                light.last_manual = self.time;
+
+                // Remove existing post packets for this light, and add a new one
+                let entity_id = format!("light.{}", light.entity_id);
+                self.post_queue.retain(|x| x.entity_id != entity_id);
+                self.post_queue.push(PostActionsData {
+                    entity_id,
+                    domain: "light".to_string(),
+                    action: if turn_on { "turn_on" } else { "turn_off" }.to_string(),
+                    additional_data: AHashMap::new(),
+                });
+            }
+        }
+    }
+
+    /// Capture every lights current state as a scene
+    pub fn capture_scene(&self, name: &str) -> Scene {
+        Scene {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            lights: self
+                .layout
+                .rooms
+                .iter()
+                .flat_map(|room| &room.lights)
+                .map(|light| (light.entity_id.clone(), light.state))
+                .collect(),
+        }
+    }
+
+    /// Set each light in the scene to its state, queueing the actions to send them
+    pub fn activate_scene(&mut self, scene_id: Uuid) {
+        let Some(scene) = self.layout.scenes.iter().find(|s| s.id == scene_id) else {
+            return;
+        };
+        let mut posted = Vec::new();
+        for room in &mut self.layout.rooms {
+            for light in &mut room.lights {
+                let Some(&target_state) = scene.lights.get(&light.entity_id) else {
+                    continue;
+                };
+                light.state = target_state;
+                light.last_manual = self.time;
+                if posted.contains(&light.entity_id) {
+                    continue;
+                }
+                posted.push(light.entity_id.clone());
+
+                // Remove existing post packets for this light, and add a new one
+                let entity_id = format!("light.{}", light.entity_id);
+                self.post_queue.retain(|x| x.entity_id != entity_id);
+                let mut additional_data = AHashMap::new();
+                if matches!(light.light_type, LightType::Dimmable) && target_state > 0 {
+                    additional_data.insert("brightness".to_string(), DataPoint::Int(target_state));
+                }
+                self.post_queue.push(PostActionsData {
+                    entity_id,
+                    domain: "light".to_string(),
+                    action: if target_state > 0 {
+                        "turn_on"
+                    } else {
+                        "turn_off"
+                    }
+                    .to_string(),
+                    additional_data,
+                });
+            }
+        }
+    }
+
+    /// Select the room under a click and show a button switching all of its lights at once
+    fn room_light_control(&mut self, response: &Response, painter: &Painter, light_hovered: bool) {
+        if response.clicked() && !light_hovered {
+            self.interaction_state.selected_room = self
+                .layout
+                .rooms
+                .iter()
+                .rev()
+                .find(|room| {
+                    self.device.tags_visible(&room.tags) && room.contains(self.mouse_pos_world)
+                })
+                .filter(|room| !room.lights.is_empty())
+                .map(|room| room.id);
+        }
+        let Some(room) = self
+            .interaction_state
+            .selected_room
+            .and_then(|id| self.layout.rooms.iter().find(|room| room.id == id))
+        else {
+            self.interaction_state.selected_room = None;
+            return;
+        };
+
+        // Lights sharing an entity are one group, count each entity once
+        let mut entity_ids: Vec<String> = Vec::new();
+        let mut lights_on = 0;
+        for light in &room.lights {
+            if !entity_ids.contains(&light.entity_id) {
+                entity_ids.push(light.entity_id.clone());
+                if light.state > 0 {
+                    lights_on += 1;
+                }
+            }
+        }
+        let any_on = lights_on > 0;
+        let label = format!(
+            "{} {} ({lights_on}/{} on)",
+            if any_on { "Turn off" } else { "Turn on" },
+            room.name,
+            entity_ids.len()
+        );
+        let (min, max) = room.bounds();
+        let pos = self.world_to_screen_pos((min + max) / 2.0);
+
+        let mut clicked = false;
+        Window::new("Room Lights")
+            .fixed_pos(pos)
+            .pivot(Align2::CENTER_CENTER)
+            .title_bar(false)
+            .resizable(false)
+            .constrain(false)
+            .show(painter.ctx(), |ui| {
+                clicked = ui.button(label).clicked();
+            });
+        if clicked {
+            self.switch_lights(&entity_ids, !any_on);
+        }
+    }
+
+    /// Control for a double clicked furniture's power and state entities
+    fn furniture_control(&mut self, response: &Response, painter: &Painter) {
+        if response.clicked() && !response.double_clicked() {
+            self.interaction_state.selected_furniture = None;
+        }
+        let Some((room, furniture)) = self.interaction_state.selected_furniture.and_then(|id| {
+            self.layout.rooms.iter().find_map(|room| {
+                room.furniture
+                    .iter()
+                    .find(|f| f.id == id)
+                    .map(|furniture| (room, furniture))
+            })
+        }) else {
+            self.interaction_state.selected_furniture = None;
+            return;
+        };
+        if furniture.power_draw_entity.is_empty() && furniture.state_entity.is_empty() {
+            self.interaction_state.selected_furniture = None;
+            return;
+        }
+
+        let pos = self.world_to_screen_pos(room.furniture_placement(furniture).0);
+        let state_entity = furniture.state_entity.clone();
+        let is_on = furniture.is_on();
+        let mut clicked = false;
+        Window::new("Furniture Control")
+            .fixed_pos(pos)
+            .pivot(Align2::CENTER_CENTER)
+            .title_bar(false)
+            .resizable(false)
+            .constrain(false)
+            .show(painter.ctx(), |ui| {
+                ui.label(&furniture.name);
+                if !furniture.power_draw_entity.is_empty() {
+                    ui.label(furniture.power_draw().map_or_else(
+                        || "Power draw unavailable".to_string(),
+                        |watts| format!("{watts:.0}W"),
+                    ));
+                }
+                if !state_entity.is_empty() {
+                    clicked = ui
+                        .button(if is_on { "Turn off" } else { "Turn on" })
+                        .clicked();
+                }
+            });
+        if clicked {
+            let domain = state_entity
+                .split_once('.')
+                .map_or("homeassistant", |(domain, _)| domain)
+                .to_string();
+            self.post_queue.retain(|x| x.entity_id != state_entity);
+            self.post_queue.push(PostActionsData {
+                entity_id: state_entity,
+                domain,
+                action: if is_on { "turn_off" } else { "turn_on" }.to_string(),
+                additional_data: AHashMap::new(),
+            });
+        }
+    }
+
     pub fn interact_with_layout(&mut self, response: &Response, painter: &Painter) {
         let interaction_button = if self.is_mobile {
             egui::PointerButton::Primary
@@ -39,6 +536,9 @@ impl HomeFlow {
 
         let mut light_hovered = None;
         for room in &self.layout.rooms {
+            if !self.device.tags_visible(&room.tags) {
+                continue;
+            }
             for light in &room.lights {
                 let points = light.get_points(room.pos, room.size);
                 for point in points {
@@ -51,6 +551,9 @@ impl HomeFlow {
                 }
             }
         }
+        self.room_light_control(response, painter, light_hovered.is_some());
+        self.furniture_control(response, painter);
+
         // Toggle light with a right click
         if response.clicked_by(interaction_button) {
             if let Some(light_hovered) = &light_hovered {
@@ -142,25 +645,36 @@ impl HomeFlow {
                     light_drag.animated_state_target = f64::from(new_percent);
                 }
 
-                // Set lights to the new state
-                let target_state = (new_percent * 255.0).round() as u8;
+                // Set lights to the new state, binary lights snap so they match what the server reports
+                let is_binary_light = matches!(light_drag.light_type, LightType::Binary);
+                let target_state = if is_binary_light {
+                    if new_percent > 0.5 {
+                        255
+                    } else {
+                        0
+                    }
+                } else {
+                    (new_percent * 255.0).round() as u8
+                };
                 for room in &mut self.layout.rooms {
                     for light in &mut room.lights {
                         if light.entity_id == light_drag.group_id {
-                            light.state = target_state;
+                            // Show the new brightness straight away rather than fading to it
+                            light.lerped_state = f64::from(target_state) / 255.0;
                             light.last_manual = self.time;
+                            if light.state == target_state {
+                                continue;
+                            }
+                            light.state = target_state;
 
-                            // Remove existing post packets for this light, and add a new one
+                            // Remove existing post packets for this light, and add a new one,
+                            // posting is throttled so only the latest state while dragging is sent
                             let entity_id = format!("light.{}", light.entity_id);
                             self.post_queue.retain(|x| x.entity_id != entity_id);
-                            let is_binary_light =
-                                matches!(light_drag.light_type, LightType::Binary);
                             let mut additional_data = AHashMap::new();
                             if !is_binary_light && target_state > 0 {
-                                additional_data.insert(
-                                    "brightness_pct".to_string(),
-                                    DataPoint::Int((f64::from(target_state) / 255.0 * 100.0) as u8),
-                                );
+                                additional_data
+                                    .insert("brightness".to_string(), DataPoint::Int(target_state));
                             }
                             self.post_queue.push(PostActionsData {
                                 entity_id,
@@ -1,25 +1,216 @@
-use crate::common::{
-    layout::{Light, LightData, LightsData, Room},
-    shape::Line,
-    utils::hash_vec2,
+use crate::{
+    client::HomeFlow,
+    common::{
+        color::Color,
+        furniture::{Furniture, FurnitureType},
+        layout::{Home, Light, LightData, LightsData, Location, OpeningType, Room, SunConfig},
+        shape::{Line, WALL_WIDTH},
+        sun::{bearing_to_direction, solar_position},
+        utils::hash_vec2,
+    },
 };
 use ahash::AHashMap;
 use glam::{dvec2 as vec2, DVec2 as Vec2};
 use std::{
     f64::consts::PI,
     hash::{DefaultHasher, Hash, Hasher},
+    sync::mpsc,
 };
 use uuid::Uuid;
 
 const PIXELS_PER_METER: f64 = 30.0;
 const LIGHT_SAMPLES: u8 = 12; // Number of samples within the light's radius for anti-aliasing
 const MAX_LIGHTS_PER_FRAME: u32 = 4;
+const FIREPLACE_INTENSITY: f64 = 1.5;
+const FIREPLACE_GLOW: [f64; 3] = [255.0, 120.0, 30.0];
+// How strongly a lights colour shows in the area it lights
+const LIGHT_TINT_STRENGTH: f64 = 0.3;
+// Height of the top of a window, the sun reaches further in the lower it is
+const WINDOW_HEAD_HEIGHT: f64 = 2.1;
+const MAX_SUN_REACH: f64 = 6.0;
 
-pub fn combine_lighting(
+/// Patch of sunlight falling through a window, cast away from the sun into the room
+pub struct SunWindow<'a> {
+    room: &'a Room,
+    span: Line,
+    toward_sun: Vec2,
+    reach: f64,
+    strength: f64,
+}
+
+/// Lighting bake running on a worker thread, the previous image stays on screen until it's done
+pub struct LightingJob {
+    receiver: mpsc::Receiver<LightingResult>,
+}
+
+/// Everything a bake needs, owned so it can be moved to another thread
+struct LightingInput {
+    hash: u64,
+    bounds_min: Vec2,
+    bounds_max: Vec2,
+    rooms: Vec<Room>,
+    walls: Vec<Line>,
+    location: Location,
+    sun: Option<SunConfig>,
+    sun_time: (u32, f64),
+}
+
+struct LightingResult {
+    light_images: Vec<(Uuid, LightsData)>,
+    light_data: LightData,
+}
+
+impl LightingInput {
+    /// Snapshot the home, the lights cached images move into the snapshot and come back with the result
+    fn new(home: &mut Home, hash: u64, sun_time: (u32, f64)) -> Option<Self> {
+        let walls = home.rendered_data.as_ref()?.wall_lines.clone();
+        let (bounds_min, bounds_max) = home.bounds();
+        Some(Self {
+            hash,
+            bounds_min,
+            bounds_max,
+            rooms: home.rooms.iter_mut().map(lighting_snapshot).collect(),
+            walls,
+            location: home.location.clone(),
+            sun: home.sun.clone(),
+            sun_time,
+        })
+    }
+
+    fn run(mut self, max_changed: u32) -> LightingResult {
+        let (bounds_min, bounds_max) = (self.bounds_min, self.bounds_max);
+        let (complete, mut new_images) = render_lighting(
+            bounds_min,
+            bounds_max,
+            &self.rooms,
+            &self.walls,
+            max_changed,
+        );
+        for light in self.rooms.iter_mut().flat_map(|room| &mut room.lights) {
+            if let Some(image) = new_images.remove(&light.id) {
+                light.light_data = Some(image);
+            }
+        }
+        let sun_windows = sun_windows(
+            &self.rooms,
+            &self.location,
+            self.sun.as_ref(),
+            self.sun_time,
+        );
+        let light_data = combine_lighting(
+            bounds_min,
+            bounds_max,
+            &self.rooms,
+            &sun_windows,
+            self.hash,
+            complete,
+        );
+        let light_images = self
+            .rooms
+            .into_iter()
+            .flat_map(|room| room.lights)
+            .filter_map(|light| Some((light.id, light.light_data?)))
+            .collect();
+        LightingResult {
+            light_images,
+            light_data,
+        }
+    }
+}
+
+/// Copy of a room with just what lighting reads, leaving out the rendered geometry
+fn lighting_snapshot(room: &mut Room) -> Room {
+    let rendered_data = room.rendered_data.take();
+    let furniture = std::mem::take(&mut room.furniture);
+    let light_images: Vec<Option<LightsData>> = room
+        .lights
+        .iter_mut()
+        .map(|light| light.light_data.take())
+        .collect();
+    let mut snapshot = room.clone();
+    snapshot.furniture = furniture
+        .iter()
+        .filter(|f| f.furniture_type == FurnitureType::Fireplace)
+        .map(|f| Furniture {
+            rendered_data: None,
+            ..f.clone()
+        })
+        .collect();
+    for (light, image) in snapshot.lights.iter_mut().zip(light_images) {
+        light.light_data = image;
+    }
+    room.rendered_data = rendered_data;
+    room.furniture = furniture;
+    snapshot
+}
+
+impl HomeFlow {
+    /// Pick up a finished lighting bake and start a new one if the lighting is out of date.
+    /// The browser has no threads so there it runs in place, a few lights each frame
+    pub fn update_lighting(&mut self, ctx: &egui::Context) {
+        if let Some(job) = &self.lighting_job {
+            match job.receiver.try_recv() {
+                Ok(result) => {
+                    self.lighting_job = None;
+                    self.apply_lighting(result);
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => self.lighting_job = None,
+            }
+        }
+
+        let sun_time = self.sun_time();
+        let hash = self.layout.lighting_hash(sun_time);
+        if self
+            .layout
+            .light_data
+            .as_ref()
+            .is_some_and(|light_data| light_data.hash == hash && light_data.complete)
+        {
+            return;
+        }
+        let Some(input) = LightingInput::new(&mut self.layout, hash, sun_time) else {
+            return;
+        };
+        if cfg!(target_arch = "wasm32") {
+            let result = input.run(MAX_LIGHTS_PER_FRAME);
+            self.apply_lighting(result);
+        } else {
+            let (sender, receiver) = mpsc::channel();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                if sender.send(input.run(u32::MAX)).is_ok() {
+                    ctx.request_repaint();
+                }
+            });
+            self.lighting_job = Some(LightingJob { receiver });
+        }
+    }
+
+    fn apply_lighting(&mut self, result: LightingResult) {
+        let mut light_images: AHashMap<Uuid, LightsData> =
+            result.light_images.into_iter().collect();
+        for light in self
+            .layout
+            .rooms
+            .iter_mut()
+            .flat_map(|room| &mut room.lights)
+        {
+            if let Some(image) = light_images.remove(&light.id) {
+                light.light_data = Some(image);
+            }
+        }
+        self.layout.light_data = Some(result.light_data);
+    }
+}
+
+fn combine_lighting(
     bounds_min: Vec2,
     bounds_max: Vec2,
     rooms: &Vec<Room>,
+    sun_windows: &[SunWindow],
     hash: u64,
+    complete: bool,
 ) -> LightData {
     // Calculate the size of the image based on the home size and resolution factor
     let new_center = (bounds_min + bounds_max) / 2.0;
@@ -46,12 +237,24 @@ pub fn combine_lighting(
                         light.intensity * light.lerped_state,
                         light.get_points(room.pos, room.size),
                         light_data,
+                        light_tint(light.color),
                     ));
                 }
             }
         }
     }
 
+    // Lit fireplaces glow through the room they're in, unshadowed as they sit against a wall
+    let fireplaces: Vec<(&Room, Vec2)> = rooms
+        .iter()
+        .flat_map(|room| {
+            room.furniture
+                .iter()
+                .filter(|f| f.furniture_type == FurnitureType::Fireplace && f.is_on())
+                .map(move |f| (room, room.pos + f.pos))
+        })
+        .collect();
+
     // For each light, add its image to the buffer
     data_buffer
         .chunks_mut(4)
@@ -65,13 +268,18 @@ pub fn combine_lighting(
             if !rooms.iter().any(|r| r.contains(world)) {
                 return;
             }
+            if rooms.iter().any(|r| r.always_lit && r.contains(world)) {
+                return;
+            }
 
             let mut total_light_intensity: f64 = 0.0;
-            for (light_intensity, light_points, light_image) in &lights_data {
+            let mut light_tint = [0.0; 3];
+            for (light_intensity, light_points, light_image, tint) in &lights_data {
                 let light_pixel = f64::from(light_image[i]);
                 if light_pixel == 0.0 {
                     continue;
                 }
+                let intensity_before = total_light_intensity;
                 for light_pos in light_points {
                     let distance = world.distance(*light_pos) * 2.0 / light_intensity;
                     total_light_intensity += light_pixel / distance.powf(2.0);
@@ -80,15 +288,47 @@ pub fn combine_lighting(
                         break;
                     }
                 }
+                let contribution = (total_light_intensity - intensity_before) / 255.0;
+                for (channel, tint) in light_tint.iter_mut().zip(tint) {
+                    *channel += tint * contribution;
+                }
                 if total_light_intensity >= 255.0 {
                     break;
                 }
             }
+
+            let mut fire_intensity: f64 = 0.0;
+            for (room, fire_pos) in &fireplaces {
+                if room.contains(world) {
+                    let distance = world.distance(*fire_pos) * 2.0 / FIREPLACE_INTENSITY;
+                    fire_intensity += 255.0 / distance.powf(2.0).max(1.0);
+                }
+            }
+            let fire_intensity = fire_intensity.min(255.0);
+            total_light_intensity = (total_light_intensity + fire_intensity).min(255.0);
+
+            for window in sun_windows {
+                if !window.room.contains(world) {
+                    continue;
+                }
+                if let Some(distance) = window.distance_through(world) {
+                    total_light_intensity +=
+                        255.0 * window.strength * (1.0 - distance / window.reach);
+                }
+            }
+            total_light_intensity = total_light_intensity.min(255.0);
+
+            // Premultiplied fire glow and light colours, added on top of whatever darkness remains
+            let warmth = fire_intensity / 255.0 * 0.25;
+            for ((channel, glow), tint) in chunk.iter_mut().zip(FIREPLACE_GLOW).zip(light_tint) {
+                *channel = (glow * warmth + tint * LIGHT_TINT_STRENGTH).min(255.0) as u8;
+            }
             chunk[3] = ((255.0 - total_light_intensity) * 0.8) as u8;
         });
 
     LightData {
         hash,
+        complete,
         image: data_buffer,
         image_center: new_center,
         image_size: new_size,
@@ -97,42 +337,114 @@ pub fn combine_lighting(
     }
 }
 
-pub fn render_lighting(
+/// Colour a light adds over what it lights, white lights add nothing so only the hue shows
+fn light_tint(color: Color) -> [f64; 3] {
+    let channels = [color.r(), color.g(), color.b()].map(f64::from);
+    let white = channels.iter().copied().fold(f64::INFINITY, f64::min);
+    channels.map(|channel| channel - white)
+}
+
+/// Windows the sun shines in through, strength falls off the more glancing the sun is to the
+/// window and the patch reaches further in the lower the sun is
+pub fn sun_windows<'a>(
+    rooms: &'a [Room],
+    location: &Location,
+    sun: Option<&SunConfig>,
+    (day, hour): (u32, f64),
+) -> Vec<SunWindow<'a>> {
+    let Some(sun) = sun else {
+        return Vec::new();
+    };
+    let position = solar_position(location.latitude, day, hour);
+    if position.elevation <= 0.0 {
+        return Vec::new();
+    }
+    let toward_sun = bearing_to_direction(position.azimuth, location.north_rotation);
+    let reach = (WINDOW_HEAD_HEIGHT / position.elevation.to_radians().tan()).min(MAX_SUN_REACH);
+
+    let mut windows = Vec::new();
+    for room in rooms {
+        for opening in &room.openings {
+            if opening.opening_type != OpeningType::Window {
+                continue;
+            }
+            let (start, end) = opening.span(room.pos);
+            let center = (start + end) / 2.0;
+            let normal = (end - start).normalize_or_zero().perp();
+            // Only windows with the room on one side and outside on the other
+            let outward = if room.contains(center - normal * WALL_WIDTH) {
+                normal
+            } else if room.contains(center + normal * WALL_WIDTH) {
+                -normal
+            } else {
+                continue;
+            };
+            if rooms
+                .iter()
+                .any(|r| r.contains(center + outward * WALL_WIDTH))
+            {
+                continue;
+            }
+            let incidence = toward_sun.dot(outward);
+            if incidence <= 0.0 {
+                continue;
+            }
+            windows.push(SunWindow {
+                room,
+                span: (start, end),
+                toward_sun,
+                reach,
+                strength: sun.intensity * incidence,
+            });
+        }
+    }
+    windows
+}
+
+impl SunWindow<'_> {
+    /// How far a point is from the window looking toward the sun, None if that misses the window
+    fn distance_through(&self, point: Vec2) -> Option<f64> {
+        let (start, end) = self.span;
+        let edge = end - start;
+        let denominator = self.toward_sun.perp_dot(edge);
+        if denominator.abs() < f64::EPSILON {
+            return None;
+        }
+        let offset = start - point;
+        let distance = offset.perp_dot(edge) / denominator;
+        let along = offset.perp_dot(self.toward_sun) / denominator;
+        ((0.0..=1.0).contains(&along) && distance > 0.0 && distance < self.reach)
+            .then_some(distance)
+    }
+}
+
+/// Render the lights whose cached image is out of date, stopping after `max_changed` of them,
+/// returns whether every light is now up to date
+fn render_lighting(
     bounds_min: Vec2,
     bounds_max: Vec2,
-    rooms: &Vec<Room>,
+    rooms: &[Room],
     all_walls: &[Line],
+    max_changed: u32,
 ) -> (bool, AHashMap<Uuid, LightsData>) {
+    let gaps = light_gaps(rooms);
+    let occluders = subtract_gaps(all_walls, &gaps);
+    let connections = gap_connections(rooms, &gaps);
+
     let mut cur_changed = 0;
     let mut new_light_data = AHashMap::new();
     for room in rooms {
         for light in &room.lights {
-            let mut hasher = DefaultHasher::new();
-            hash_vec2(light.pos, &mut hasher);
-            light.multi.hash(&mut hasher);
-            light.intensity.to_bits().hash(&mut hasher);
-            light.radius.to_bits().hash(&mut hasher);
-            for room in rooms {
-                hash_vec2(room.pos, &mut hasher);
-                hash_vec2(room.size, &mut hasher);
-                room.operations.hash(&mut hasher);
-                room.walls.hash(&mut hasher);
-            }
-            let hash = hasher.finish();
+            // Keyed on what this light can see, so other lights and far away walls don't invalidate it
+            let view = LightView::new(rooms, &occluders, &connections, light, room);
+            let hash = view.hash(bounds_min, bounds_max, light, room);
 
             if light.light_data.is_none() || light.light_data.as_ref().unwrap().0 != hash {
-                let light_data = render_light(
-                    bounds_min,
-                    bounds_max,
-                    rooms,
-                    all_walls,
-                    light,
-                    &light.get_points(room.pos, room.size),
-                );
+                let light_data = render_light(bounds_min, bounds_max, &view, light, room);
                 new_light_data.insert(light.id, (hash, light_data));
                 cur_changed += 1;
             }
-            if cur_changed >= MAX_LIGHTS_PER_FRAME {
+            if cur_changed >= max_changed {
                 return (false, new_light_data);
             }
         }
@@ -140,41 +452,112 @@ pub fn render_lighting(
     (true, new_light_data)
 }
 
-fn render_light(
-    bounds_min: Vec2,
-    bounds_max: Vec2,
-    rooms: &[Room],
-    all_walls: &[Line],
-    light: &Light,
-    points: &[Vec2],
-) -> Vec<u16> {
-    // Create a vec of walls that this light can see
-    let mut walls_for_light = Vec::with_capacity(points.len());
-    for point in points {
-        walls_for_light.push(get_visible_walls(*point, all_walls));
-    }
+/// Everything a light's image depends on outside the light itself
+struct LightView<'a> {
+    points: Vec<Vec2>,
+    walls_for_light: Vec<Vec<Line>>,
+    rooms_to_check: Vec<&'a Room>,
+}
 
-    // Calculate the rooms to check against, if lights room is enclosed then only that, if its not then only rooms that arent enclosed
-    let mut rooms_to_check: Vec<&Room> = Vec::new();
-    let mut is_light_contained = true;
-    for point in points {
-        let room = rooms.iter().find(|room| room.contains(*point));
-        if let Some(room) = room {
-            if !rooms_to_check.iter().any(|r| r.id == room.id) {
-                rooms_to_check.push(room);
+impl<'a> LightView<'a> {
+    fn new(
+        rooms: &'a [Room],
+        all_walls: &[Line],
+        connections: &[(Uuid, Uuid)],
+        light: &Light,
+        light_room: &Room,
+    ) -> Self {
+        let points = light.get_points(light_room.pos, light_room.size);
+
+        // Create a vec of walls that this light can see
+        let mut walls_for_light = Vec::with_capacity(points.len());
+        for point in &points {
+            walls_for_light.push(get_visible_walls(*point, all_walls));
+        }
+
+        // Calculate the rooms to check against, if lights room is enclosed then only that, if its not then only rooms that arent enclosed
+        let mut rooms_to_check: Vec<&Room> = Vec::new();
+        let mut is_light_contained = true;
+        for point in &points {
+            let room = rooms.iter().find(|room| room.contains(*point));
+            if let Some(room) = room {
+                if !rooms_to_check.iter().any(|r| r.id == room.id) {
+                    rooms_to_check.push(room);
+                }
+                if !room.walls.is_all() {
+                    is_light_contained = false;
+                }
             }
-            if !room.walls.is_all() {
-                is_light_contained = false;
+        }
+        if !is_light_contained {
+            for room in rooms {
+                if !room.walls.is_all() && !rooms_to_check.iter().any(|r| r.id == room.id) {
+                    rooms_to_check.push(room);
+                }
             }
         }
-    }
-    if !is_light_contained {
+        // Light also spills through windows and open doors into the rooms on the other side
+        let lit_ids: Vec<Uuid> = rooms_to_check.iter().map(|r| r.id).collect();
         for room in rooms {
-            if !room.walls.is_all() && !rooms_to_check.iter().any(|r| r.id == room.id) {
+            let through_gap = connections.iter().any(|&(a, b)| {
+                (a == room.id && lit_ids.contains(&b)) || (b == room.id && lit_ids.contains(&a))
+            });
+            if through_gap && !rooms_to_check.iter().any(|r| r.id == room.id) {
                 rooms_to_check.push(room);
             }
         }
+
+        Self {
+            points,
+            walls_for_light,
+            rooms_to_check,
+        }
+    }
+
+    fn hash(&self, bounds_min: Vec2, bounds_max: Vec2, light: &Light, light_room: &Room) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_vec2(bounds_min, &mut hasher);
+        hash_vec2(bounds_max, &mut hasher);
+        light.intensity.to_bits().hash(&mut hasher);
+        light.radius.to_bits().hash(&mut hasher);
+        light.exclusions.hash(&mut hasher);
+        light.kind.hash(&mut hasher);
+        light.direction.hash(&mut hasher);
+        light.cone_angle.to_bits().hash(&mut hasher);
+        hash_vec2(light_room.pos, &mut hasher);
+        for point in &self.points {
+            hash_vec2(*point, &mut hasher);
+        }
+        for &(start, end) in self.walls_for_light.iter().flatten() {
+            hash_vec2(start, &mut hasher);
+            hash_vec2(end, &mut hasher);
+        }
+        // Only the floor shape of the lit rooms matters
+        for room in &self.rooms_to_check {
+            hash_vec2(room.pos, &mut hasher);
+            hash_vec2(room.size, &mut hasher);
+            room.base_shape.hash(&mut hasher);
+            for vertex in room.freeform.iter().flatten() {
+                hash_vec2(*vertex, &mut hasher);
+            }
+            room.operations.hash(&mut hasher);
+        }
+        hasher.finish()
     }
+}
+
+fn render_light(
+    bounds_min: Vec2,
+    bounds_max: Vec2,
+    view: &LightView,
+    light: &Light,
+    light_room: &Room,
+) -> Vec<u16> {
+    let LightView {
+        points,
+        walls_for_light,
+        rooms_to_check,
+    } = view;
 
     // Calculate the size of the image based on the home size and resolution factor
     let new_size = bounds_max - bounds_min;
@@ -195,10 +578,22 @@ fn render_light(
         if !rooms_to_check.iter().any(|r| r.contains(world)) {
             return;
         }
+        // Exclusion zones are fully occluded for this light
+        if light
+            .exclusions
+            .iter()
+            .any(|z| z.contains(light_room.pos, world))
+        {
+            return;
+        }
 
         let mut total_light_intensity = 0.0;
 
         for (light_index, light_pos) in points.iter().enumerate() {
+            // Spotlights leave everything outside their cone dark
+            if !light.in_cone(*light_pos, world) {
+                continue;
+            }
             // Do more samples the closer we are to the light
             let dynamic_samples = ((f64::from(LIGHT_SAMPLES)
                 * (1.0 - world.distance(*light_pos) / (light.intensity * 10.0)))
@@ -246,6 +641,72 @@ fn render_light(
     data_buffer
 }
 
+/// Spans of windows and open doors, light passes through these gaps in the walls
+fn light_gaps(rooms: &[Room]) -> Vec<Line> {
+    rooms
+        .iter()
+        .flat_map(|room| {
+            room.openings
+                .iter()
+                .filter(|opening| opening.lets_light_through())
+                .map(|opening| opening.span(room.pos))
+        })
+        .collect()
+}
+
+/// Cut the gaps out of wall faces running alongside them, within a walls thickness
+fn subtract_gaps(walls: &[Line], gaps: &[Line]) -> Vec<Line> {
+    let mut pieces = walls.to_vec();
+    for &(gap_start, gap_end) in gaps {
+        pieces = pieces
+            .into_iter()
+            .flat_map(|(start, end)| {
+                let length = start.distance(end);
+                let direction = (end - start) / length;
+                let gap_direction = (gap_end - gap_start).normalize_or_zero();
+                let alongside = length > f64::EPSILON
+                    && direction.perp_dot(gap_direction).abs() < 0.01
+                    && (gap_start - start).perp_dot(direction).abs() <= WALL_WIDTH;
+                if !alongside {
+                    return vec![(start, end)];
+                }
+                let (a, b) = (
+                    (gap_start - start).dot(direction),
+                    (gap_end - start).dot(direction),
+                );
+                let (cut_start, cut_end) = (a.min(b), a.max(b));
+                if cut_end <= 0.0 || cut_start >= length {
+                    return vec![(start, end)];
+                }
+                let mut kept = Vec::with_capacity(2);
+                if cut_start > 0.0 {
+                    kept.push((start, start + direction * cut_start));
+                }
+                if cut_end < length {
+                    kept.push((start + direction * cut_end, end));
+                }
+                kept
+            })
+            .collect();
+    }
+    pieces
+}
+
+/// Pairs of rooms either side of each gap
+fn gap_connections(rooms: &[Room], gaps: &[Line]) -> Vec<(Uuid, Uuid)> {
+    let room_at = |point: Vec2| rooms.iter().find(|room| room.contains(point)).map(|r| r.id);
+    gaps.iter()
+        .filter_map(|&(start, end)| {
+            let center = (start + end) / 2.0;
+            let normal = (end - start).normalize_or_zero().perp() * WALL_WIDTH;
+            match (room_at(center + normal), room_at(center - normal)) {
+                (Some(a), Some(b)) if a != b => Some((a, b)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 const POINTS_DISTANCE: f64 = 0.1; // Distance between points on the wall to check for visibility
 fn get_visible_walls(light_pos: Vec2, all_walls: &[Line]) -> Vec<Line> {
     let mut visible_walls = Vec::with_capacity(all_walls.len());
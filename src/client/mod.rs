@@ -1,35 +1,54 @@
 mod edit_mode;
 mod edit_mode_render;
 mod edit_mode_utils;
+mod export;
 mod interaction;
 pub mod light_render;
 mod networking;
 mod render;
+mod screenshot;
 
 use crate::{
     client::{
         edit_mode::{EditDetails, EditResponse},
-        interaction::IState,
-        networking::{get_layout, get_states, login, post_actions},
+        edit_mode_utils::Units,
+        interaction::{DoubleClickAction, IState},
+        networking::{get_areas, get_layout, get_states, login, post_actions, StateStream},
+        render::{power_draw_color, Easing, GridVisibility, ShadowOrder},
     },
     common::{
-        layout::Home,
+        furniture::{DetailLevel, Furniture},
+        layout::{Home, Phase, Room, Walls},
         utils::{rotate_point, rotate_point_pivot},
-        HAState, PostActionsData,
+        HAArea, HAState, PostActionsData, PresencePoint,
     },
 };
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use anyhow::Result;
-use egui::{Align2, CentralPanel, Color32, Context, Frame, Sense, TextEdit, TextureHandle, Window};
+use egui::{
+    Align2, CentralPanel, Color32, Context, Frame, Sense, Stroke, TextEdit, TextureHandle, Window,
+};
 use egui_notify::Toasts;
 use glam::{dvec2 as vec2, DVec2 as Vec2};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Duration};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
 static HOME_ASSISTANT_STATE_REFRESH: f64 = 1.0;
+static HOME_ASSISTANT_STATE_SLOW_REFRESH: f64 = 10.0;
+static HOME_ASSISTANT_STATE_MAX_BACKOFF: f64 = 30.0;
+static HOME_ASSISTANT_STREAM_RETRY: f64 = 10.0;
 static HOME_ASSISTANT_STATE_LOCAL_OVERRIDE: f64 = 5.0;
 static HOME_ASSISTANT_STATE_POST_EVERY: f64 = 0.1;
+// Home Assistant rounds brightness, states this close count as confirming a local edit
+static HOME_ASSISTANT_STATE_CONFIRM_TOLERANCE: u8 = 3;
+// Most presence samples kept for the trail, however long its window
+const PRESENCE_TRAIL_MAX_SAMPLES: usize = 4000;
+// Seconds the view takes to glide to a framed target
+const CAMERA_ANIMATION_SECONDS: f64 = 0.3;
+
+const DEVICE_KEY: &str = "device";
+const ACCOUNT_KEY: &str = "account";
 
 nestify::nest! {
     pub struct HomeFlow {
@@ -37,32 +56,93 @@ nestify::nest! {
         frame_time: f64,
 
         canvas_center: Vec2,
+        canvas_rect: egui::Rect,
         mouse_pos: Vec2,
         mouse_pos_world: Vec2,
         is_mobile: bool,
 
         layout_server: Home,
         layout: Home,
+        layout_revision: Option<u64>,
+        layout_outdated: bool,
         textures: AHashMap<String, TextureHandle>,
-        light_data: Option<(u64, TextureHandle)>,
+        light_data: Option<(u64, bool, TextureHandle)>,
+        lighting_job: Option<light_render::LightingJob>,
         bounds: (Vec2, Vec2),
         rotate_key_down: bool,
         rotate_speed: f64,
         rotate_target: f64,
+        // Eased move of the view towards a framed target, dropped as soon as the user pans or zooms
+        camera_animation: Option<struct CameraAnimation {
+            from_translation: Vec2,
+            from_zoom: f64,
+            to_translation: Vec2,
+            to_zoom: f64,
+            progress: f64, // 0-1
+        }>,
         interaction_state: IState,
-        presence_points: Vec<Vec2>,
+        presence_points: Vec<PresencePoint>,
+        // Recent presence positions with the time they were seen, oldest first
+        presence_trail: VecDeque<(f64, Vec2)>,
+        next_slow_states: f64,
+        // Pushed states replace polling while this is open
+        state_stream: Option<StateStream>,
+        next_stream_attempt: f64,
+        // Home Assistant areas for the room editor, None until fetched
+        hass_areas: Option<Vec<HAArea>>,
 
         toasts: Arc<Mutex<Toasts>>,
         edit_mode: EditDetails,
         host: String,
 
+        // View state and preferences local to this device
         #>[derive(Deserialize, Serialize, Debug)]
         #>[serde(default)]
-        stored: pub struct StoredData {
-            auth_token: String,
+        device: pub struct DeviceData {
             translation: Vec2,
             zoom: f64, // Zoom is meter to pixels
             rotation: f64,
+            show_room_power: bool,
+            show_operations: bool,
+            temperature_overlay: bool,
+            stream_states: bool,
+            easing: Easing,
+            hover_speed: f64,
+            hover_strength: f64,
+            hover_in_view_mode: bool,
+            shadow_order: ShadowOrder,
+            units: Units,
+            coordinate_precision: usize,
+            wall_snap_distance: f64, // Screen pixels
+            drag_threshold: f64,     // Screen pixels
+            zoom_min: f64, // Screen pixels per meter
+            zoom_max: f64,
+            // Stop panning once the home would leave the view entirely
+            keep_home_on_screen: bool,
+            new_room_walls: Walls,
+            furniture_detail: DetailLevel,
+            floor_opacity: f32,
+            phase_filter: Option<Phase>,
+            hidden_tags: AHashSet<String>,
+            grid_visibility: GridVisibility,
+            show_sun_path: bool,
+            presence_trail_seconds: f64, // Zero hides the trail
+            sun_day: u32,  // Day of the year
+            sun_hour: f64, // Local solar time
+            export_resolution: f64, // Pixels per meter
+            export_lighting: bool,
+            export_operations: bool,
+            export_margin: f64, // Meters of background around the plan
+            double_click_room: DoubleClickAction,
+            double_click_light: DoubleClickAction,
+            double_click_furniture: DoubleClickAction,
+        },
+
+        // Data tied to the logged in account
+        #>[derive(Deserialize, Serialize, Debug, Default)]
+        #>[serde(default)]
+        account: pub struct AccountData {
+            auth_token: String,
         },
 
         login_form: struct LoginForm {
@@ -85,6 +165,14 @@ nestify::nest! {
                 InProgress,
                 Done(Result<HAState>),
             },
+            hass_areas: enum DownloadAreas {
+                #[default]
+                None,
+                InProgress,
+                Done(Result<Vec<HAArea>>),
+            },
+            // Consecutive failed state fetches, each one doubles the wait before retrying
+            hass_failures: u32,
             hass_post: enum UploadStates {
                 #[default]
                 None,
@@ -103,47 +191,115 @@ nestify::nest! {
     }
 }
 
-impl Default for StoredData {
+impl Default for DeviceData {
     fn default() -> Self {
         Self {
-            auth_token: String::new(),
             translation: Vec2::ZERO,
             zoom: 100.0,
             rotation: 0.0,
+            show_room_power: false,
+            show_operations: false,
+            temperature_overlay: false,
+            stream_states: true,
+            easing: Easing::default(),
+            hover_speed: 10.0,
+            hover_strength: 1.0,
+            hover_in_view_mode: true,
+            shadow_order: ShadowOrder::default(),
+            units: Units::default(),
+            coordinate_precision: 3,
+            wall_snap_distance: 12.0,
+            drag_threshold: 6.0,
+            zoom_min: 40.0,
+            zoom_max: 300.0,
+            keep_home_on_screen: true,
+            new_room_walls: Walls::all(),
+            furniture_detail: DetailLevel::default(),
+            floor_opacity: 1.0,
+            phase_filter: None,
+            hidden_tags: AHashSet::new(),
+            grid_visibility: GridVisibility::default(),
+            show_sun_path: false,
+            presence_trail_seconds: 60.0,
+            sun_day: 172,
+            sun_hour: 12.0,
+            export_resolution: 100.0,
+            export_lighting: true,
+            export_operations: false,
+            export_margin: 0.5,
+            double_click_room: DoubleClickAction::ZoomTo,
+            double_click_light: DoubleClickAction::Toggle,
+            double_click_furniture: DoubleClickAction::Edit,
         }
     }
 }
 
+impl DeviceData {
+    /// Zoom within the configured limits, tolerating a minimum set above the maximum
+    pub fn clamp_zoom(&self, zoom: f64) -> f64 {
+        zoom.clamp(self.zoom_min, self.zoom_max.max(self.zoom_min))
+    }
+
+    /// Untagged objects are always shown, tagged ones only hide once every tag is hidden
+    pub fn tags_visible(&self, tags: &[String]) -> bool {
+        tags.is_empty() || tags.iter().any(|tag| !self.hidden_tags.contains(tag))
+    }
+
+    /// Furniture goes with its room, so hiding either hides it
+    pub fn furniture_visible(&self, room: &Room, furniture: &Furniture) -> bool {
+        self.tags_visible(&room.tags) && self.tags_visible(&furniture.tags)
+    }
+}
+
 impl HomeFlow {
     pub fn new(cc: &eframe::CreationContext) -> Self {
-        let stored = cc.storage.map_or_else(StoredData::default, |storage| {
-            eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
+        // Fall back to the combined blob stored under APP_KEY by older versions
+        let device: DeviceData = cc.storage.map_or_else(DeviceData::default, |storage| {
+            eframe::get_value(storage, DEVICE_KEY)
+                .or_else(|| eframe::get_value(storage, eframe::APP_KEY))
+                .unwrap_or_default()
+        });
+        let account: AccountData = cc.storage.map_or_else(AccountData::default, |storage| {
+            eframe::get_value(storage, ACCOUNT_KEY)
+                .or_else(|| eframe::get_value(storage, eframe::APP_KEY))
+                .unwrap_or_default()
         });
 
-        let rotation = ((stored.rotation / 90.0).round() * 90.0).rem_euclid(360.0);
+        let rotation = ((device.rotation / 90.0).round() * 90.0).rem_euclid(360.0);
         Self {
             time: 0.0,
             frame_time: 0.0,
             canvas_center: Vec2::ZERO,
+            canvas_rect: egui::Rect::NOTHING,
             mouse_pos: Vec2::ZERO,
             mouse_pos_world: Vec2::ZERO,
             is_mobile: false,
 
             layout_server: Home::empty(),
             layout: Home::empty(),
+            layout_revision: None,
+            layout_outdated: false,
             textures: AHashMap::new(),
             light_data: None,
+            lighting_job: None,
             bounds: (Vec2::ZERO, Vec2::ZERO),
             rotate_key_down: false,
             rotate_speed: 0.0,
             rotate_target: rotation,
+            camera_animation: None,
             interaction_state: IState::default(),
             presence_points: Vec::new(),
+            presence_trail: VecDeque::new(),
+            next_slow_states: 0.0,
+            state_stream: None,
+            next_stream_attempt: 0.0,
+            hass_areas: None,
 
             toasts: Arc::new(Mutex::new(Toasts::default())),
             edit_mode: EditDetails::default(),
             host: "localhost:8127".to_string(),
-            stored: StoredData { rotation, ..stored },
+            device: DeviceData { rotation, ..device },
+            account,
             login_form: LoginForm {
                 username: String::new(),
                 password: String::new(),
@@ -154,23 +310,23 @@ impl HomeFlow {
     }
 
     fn screen_to_world(&self, v: Vec2) -> Vec2 {
-        let pivot = vec2(-self.stored.translation.x, self.stored.translation.y);
+        let pivot = vec2(-self.device.translation.x, self.device.translation.y);
         rotate_point_pivot(
             vec2(
-                (v.x - self.canvas_center.x) / self.stored.zoom - self.stored.translation.x,
-                (self.canvas_center.y - v.y) / self.stored.zoom + self.stored.translation.y,
+                (v.x - self.canvas_center.x) / self.device.zoom - self.device.translation.x,
+                (self.canvas_center.y - v.y) / self.device.zoom + self.device.translation.y,
             ),
             pivot,
-            -self.stored.rotation,
+            -self.device.rotation,
         )
     }
 
     fn world_to_screen(&self, v: Vec2) -> Vec2 {
-        let pivot = vec2(-self.stored.translation.x, self.stored.translation.y);
-        let v = rotate_point_pivot(v, pivot, self.stored.rotation);
+        let pivot = vec2(-self.device.translation.x, self.device.translation.y);
+        let v = rotate_point_pivot(v, pivot, self.device.rotation);
         vec2(
-            (v.x + self.stored.translation.x) * self.stored.zoom + self.canvas_center.x,
-            (self.stored.translation.y - v.y) * self.stored.zoom + self.canvas_center.y,
+            (v.x + self.device.translation.x) * self.device.zoom + self.canvas_center.x,
+            (self.device.translation.y - v.y) * self.device.zoom + self.canvas_center.y,
         )
     }
     fn world_to_screen_pos(&self, v: Vec2) -> egui::Pos2 {
@@ -178,6 +334,51 @@ impl HomeFlow {
         egui::pos2(v.x as f32, v.y as f32)
     }
 
+    /// Centers the view on the given world bounds, zooming so they fill most of the canvas
+    fn zoom_to(&mut self, min: Vec2, max: Vec2) {
+        let center = (min + max) / 2.0;
+        let half_size = (max - min).max(Vec2::splat(0.1)) / 2.0;
+        // Screen extent of the bounds once turned by the view rotation
+        let size = [half_size, vec2(half_size.x, -half_size.y)]
+            .map(|corner| rotate_point(corner, self.device.rotation).abs())
+            .into_iter()
+            .fold(Vec2::ZERO, Vec2::max)
+            * 2.0;
+        let canvas_size = self.canvas_center * 2.0;
+        self.camera_animation = Some(CameraAnimation {
+            from_translation: self.device.translation,
+            from_zoom: self.device.zoom,
+            to_translation: vec2(-center.x, center.y),
+            to_zoom: self
+                .device
+                .clamp_zoom((canvas_size / size).min_element() * 0.8),
+            progress: 0.0,
+        });
+    }
+
+    /// Frame the whole home
+    fn zoom_to_fit(&mut self) {
+        let (min, max) = self.layout.bounds();
+        if min.is_finite() && max.is_finite() {
+            self.zoom_to(min, max);
+        }
+    }
+
+    /// Keep some of the home bounds inside the view, the view center may go as far past the
+    /// bounds as half of the visible area
+    fn clamp_translation(&mut self) {
+        let half_view = self.canvas_center / self.device.zoom;
+        // World extent of the view once turned by the view rotation
+        let half_extent = [half_view, vec2(half_view.x, -half_view.y)]
+            .map(|corner| rotate_point(corner, self.device.rotation).abs())
+            .into_iter()
+            .fold(Vec2::ZERO, Vec2::max);
+        let (min, max) = self.bounds;
+        let center = vec2(-self.device.translation.x, self.device.translation.y)
+            .clamp(min - half_extent, max + half_extent);
+        self.device.translation = vec2(-center.x, center.y);
+    }
+
     fn handle_pan_zoom(&mut self, response: &egui::Response, ui: &egui::Ui) {
         if !(self.bounds.0.is_finite()
             && self.bounds.1.is_finite()
@@ -187,6 +388,11 @@ impl HomeFlow {
             return;
         }
 
+        let fit_pressed = ui.input(|i| i.key_pressed(egui::Key::F) && i.modifiers.is_none());
+        if fit_pressed && ui.ctx().memory(|m| m.focused().is_none()) {
+            self.zoom_to_fit();
+        }
+
         // Drag
         let pointer_button = if self.edit_mode.enabled {
             egui::PointerButton::Secondary
@@ -214,18 +420,30 @@ impl HomeFlow {
             translation_delta = egui_to_vec2(multi_touch.translation_delta) * 0.01;
             multi_touch_rotation = f64::from(multi_touch.rotation_delta);
         }
+        if scroll_delta.abs() > 0.0 || translation_delta.length() > 0.0 {
+            self.camera_animation = None;
+        }
+        if let Some(animation) = &mut self.camera_animation {
+            animation.progress += self.frame_time / CAMERA_ANIMATION_SECONDS;
+            let t = self.device.easing.apply(animation.progress);
+            self.device.translation = animation.from_translation.lerp(animation.to_translation, t);
+            self.device.zoom = animation.from_zoom + (animation.to_zoom - animation.from_zoom) * t;
+            if animation.progress >= 1.0 {
+                self.camera_animation = None;
+            }
+        }
         if scroll_delta.abs() > 0.0 {
-            let zoom_amount = scroll_delta * (self.stored.zoom / 100.0);
+            let zoom_amount = scroll_delta * (self.device.zoom / 100.0);
             let mouse_world_before_zoom = self.screen_to_world(self.mouse_pos);
-            self.stored.zoom = (self.stored.zoom + zoom_amount).clamp(40.0, 300.0);
+            self.device.zoom = self.device.clamp_zoom(self.device.zoom + zoom_amount);
             let mouse_world_after_zoom = self.screen_to_world(self.mouse_pos);
             let difference = mouse_world_after_zoom - mouse_world_before_zoom;
-            self.stored.translation += Vec2::new(difference.x, -difference.y);
+            self.device.translation += Vec2::new(difference.x, -difference.y);
         }
 
         if translation_delta.length() > 0.0 {
-            let rotated = rotate_point(translation_delta, self.stored.rotation);
-            self.stored.translation += rotated / (self.stored.zoom / 100.0);
+            let rotated = rotate_point(translation_delta, self.device.rotation);
+            self.device.translation += rotated / (self.device.zoom / 100.0);
         }
 
         let (q_down, e_down) = ui.input(|i| (i.key_down(egui::Key::Q), i.key_down(egui::Key::E)));
@@ -236,7 +454,7 @@ impl HomeFlow {
                 .clamp(-max_speed, max_speed);
             interaction_rotated = true;
         } else if is_multi_touch {
-            self.stored.rotation -= multi_touch_rotation.to_degrees();
+            self.device.rotation -= multi_touch_rotation.to_degrees();
             self.rotate_speed = 0.0;
         }
         if interaction_rotated && !self.rotate_key_down {
@@ -246,34 +464,36 @@ impl HomeFlow {
             self.rotate_key_down = false;
             // Determine the nearest 90 degree snap target based on current rotation
             let inertia = (self.rotate_speed * 0.25).clamp(-max_speed * 0.1, max_speed * 0.1);
-            self.rotate_target = ((self.stored.rotation + inertia) / 90.0).round() * 90.0;
+            self.rotate_target = ((self.device.rotation + inertia) / 90.0).round() * 90.0;
         }
         if !(q_down || e_down || is_multi_touch) {
-            let rotation_diff = self.rotate_target - self.stored.rotation;
+            let rotation_diff = self.rotate_target - self.device.rotation;
 
             // Adjust rotate speed towards the needed speed for snapping, within the max speed limit
             let needed_speed = rotation_diff * self.frame_time * 500.0;
             self.rotate_speed = if rotation_diff.abs() > 0.1 {
                 needed_speed.clamp(-max_speed, max_speed)
             } else {
-                self.stored.rotation = self.rotate_target.rem_euclid(360.0);
-                self.rotate_target = self.stored.rotation;
+                self.device.rotation = self.rotate_target.rem_euclid(360.0);
+                self.rotate_target = self.device.rotation;
                 0.0
             };
         }
 
         // Apply rotation if there's any rotate speed
         if self.rotate_speed.abs() > 0.0 {
-            self.stored.rotation += self.rotate_speed * self.frame_time;
+            self.device.rotation += self.rotate_speed * self.frame_time;
         }
 
-        // Clamp translation to bounds
-        self.stored.translation = self.stored.translation.clamp(self.bounds.0, self.bounds.1);
+        if self.device.keep_home_on_screen {
+            self.clamp_translation();
+        }
     }
 
     fn load_layout(&mut self) {
-        // Load layout from server if needed
-        if !self.layout.version.is_empty() {
+        // Load layout from server if needed, or reload it once another device saved changes
+        let wants_reload = self.layout_outdated && !self.edit_mode.enabled;
+        if !self.layout.version.is_empty() && !wants_reload {
             return;
         }
         let network_store = self.network_data.clone();
@@ -282,7 +502,7 @@ impl HomeFlow {
             DownloadLayout::None => {
                 network_data_guard.layout = DownloadLayout::InProgress;
                 drop(network_data_guard);
-                get_layout(&self.host, &self.stored.auth_token, move |res| {
+                get_layout(&self.host, &self.account.auth_token, move |res| {
                     network_store.lock().layout = DownloadLayout::Done(res);
                 });
             }
@@ -290,13 +510,24 @@ impl HomeFlow {
             DownloadLayout::Done(ref response) => {
                 match response {
                     Ok(layout) => {
-                        self.layout_server = layout.clone();
-                        self.layout = layout.clone();
+                        // Skip replacing an unchanged layout, such as after our own save,
+                        // so the displayed states don't reset
+                        if !layout.same_layout(&self.layout_server) {
+                            self.layout_server = layout.clone();
+                            self.layout = layout.clone();
+                            // The new layout has none of the streamed states yet
+                            if let Some(stream) = &mut self.state_stream {
+                                stream.resync();
+                            }
+                            // Or its room sensors, so ask for them with the next poll
+                            self.next_slow_states = 0.0;
+                        }
+                        self.layout_outdated = false;
                     }
                     Err(e) => {
                         // If unauthorised, clear auth token and show login screen
                         if e.to_string().contains("status code: 401") {
-                            self.stored.auth_token.clear();
+                            self.account.auth_token.clear();
                         }
                         log::error!("Failed to fetch layout: {:?}", e);
                     }
@@ -307,6 +538,9 @@ impl HomeFlow {
     }
 
     fn get_states(&mut self) {
+        if self.poll_state_stream() {
+            return;
+        }
         let network_store = self.network_data.clone();
         let mut network_data_guard = network_store.lock();
         match &network_data_guard.hass_states {
@@ -314,9 +548,19 @@ impl HomeFlow {
                 network_data_guard.hass_states = DownloadStates::InProgress;
                 drop(network_data_guard);
 
-                get_states(&self.host, &self.stored.auth_token, move |res| {
-                    network_store.lock().hass_states = DownloadStates::Done(res);
-                });
+                // Room sensors change slowly so are only requested occasionally
+                let include_slow = self.time >= self.next_slow_states;
+                if include_slow {
+                    self.next_slow_states = self.time + HOME_ASSISTANT_STATE_SLOW_REFRESH;
+                }
+                get_states(
+                    &self.host,
+                    &self.account.auth_token,
+                    include_slow,
+                    move |res| {
+                        network_store.lock().hass_states = DownloadStates::Done(res);
+                    },
+                );
             }
             DownloadStates::Waiting(time) => {
                 if self.time > *time {
@@ -327,55 +571,216 @@ impl HomeFlow {
             DownloadStates::Done(ref response) => {
                 match response {
                     Ok(states) => {
-                        // Update all data with the new state
-                        for room in &mut self.layout.rooms {
-                            for sensor in &room.sensors {
-                                for (packet_id, packet_state) in &states.sensors {
-                                    if &sensor.entity_id == packet_id {
-                                        room.hass_data
-                                            .insert(sensor.entity_id.clone(), packet_state.clone());
-                                    }
-                                }
-                            }
-                            for light in &mut room.lights {
-                                // Update light if it hasn't been locally edited recently
-                                if light.last_manual == 0.0
-                                    || self.time
-                                        > light.last_manual + HOME_ASSISTANT_STATE_LOCAL_OVERRIDE
-                                {
-                                    for (packet_id, packet_state) in &states.lights {
-                                        if &light.entity_id == packet_id {
-                                            light.state = *packet_state;
-                                        }
-                                    }
-                                }
-                            }
-                            for furniture in &mut room.furniture {
-                                for sensor in &furniture.wanted_sensors() {
-                                    for (packet_id, packet_state) in &states.sensors {
-                                        if sensor == packet_id {
-                                            furniture
-                                                .hass_data
-                                                .insert(sensor.clone(), packet_state.clone());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        self.presence_points.clone_from(&states.presence_points);
+                        self.apply_states(states);
+                        self.check_layout_revision(states.layout_revision);
+                        network_data_guard.hass_failures = 0;
                     }
                     Err(e) => {
                         // If unauthorised, clear auth token and show login screen
                         if e.to_string().contains("status code: 401") {
-                            self.stored.auth_token.clear();
+                            self.account.auth_token.clear();
+                        } else if let Some(demo_states) = self.layout.demo_states.clone() {
+                            // Show the demo snapshot while Home Assistant is unreachable
+                            self.apply_states(&demo_states);
                         }
                         log::error!("Failed to fetch states: {:?}", e);
+                        network_data_guard.hass_failures += 1;
+                    }
+                }
+                let backoff = HOME_ASSISTANT_STATE_REFRESH
+                    * 2f64.powi(network_data_guard.hass_failures.min(16) as i32);
+                network_data_guard.hass_states = DownloadStates::Waiting(
+                    self.time + backoff.min(HOME_ASSISTANT_STATE_MAX_BACKOFF),
+                );
+            }
+        }
+    }
+
+    /// Apply states pushed by the server, returns false while polling is needed because the
+    /// stream is turned off, still connecting or has dropped and is waiting to retry
+    fn poll_state_stream(&mut self) -> bool {
+        if !self.device.stream_states || self.account.auth_token.is_empty() {
+            self.state_stream = None;
+            return false;
+        }
+        if self.state_stream.is_none() && self.time >= self.next_stream_attempt {
+            self.next_stream_attempt = self.time + HOME_ASSISTANT_STREAM_RETRY;
+            match StateStream::connect(&self.host, &self.account.auth_token) {
+                Ok(stream) => self.state_stream = Some(stream),
+                Err(e) => log::error!("{:?}", e),
+            }
+        }
+        let Some(stream) = &mut self.state_stream else {
+            return false;
+        };
+        let (packets, open) = match stream.poll() {
+            Ok(packets) => (packets, stream.is_open()),
+            Err(e) => {
+                // Fall back to polling until the next attempt to reconnect
+                log::warn!("{:?}", e);
+                self.state_stream = None;
+                self.next_stream_attempt = self.time + HOME_ASSISTANT_STREAM_RETRY;
+                return false;
+            }
+        };
+        for states in &packets {
+            self.apply_states(states);
+            self.check_layout_revision(states.layout_revision);
+        }
+        if open {
+            self.network_data.lock().hass_failures = 0;
+        }
+        open
+    }
+
+    /// Warning in the top left while state fetches are failing, with the time to the next retry
+    fn connection_status(&self, ctx: &Context, rect: egui::Rect) {
+        let (failures, retry_at) = {
+            let network_data = self.network_data.lock();
+            let retry_at = match network_data.hass_states {
+                DownloadStates::Waiting(time) => Some(time),
+                _ => None,
+            };
+            (network_data.hass_failures, retry_at)
+        };
+        if failures == 0 {
+            return;
+        }
+        let status = retry_at.filter(|time| *time > self.time).map_or_else(
+            || "Disconnected, retrying".to_string(),
+            |time| format!("Disconnected, retrying in {}s", (time - self.time).ceil()),
+        );
+        Window::new("Connection Status")
+            .fixed_pos(egui::pos2(rect.left() + 10.0, rect.top() + 10.0))
+            .pivot(Align2::LEFT_TOP)
+            .title_bar(false)
+            .resizable(false)
+            .constrain(false)
+            .show(ctx, |ui| {
+                ui.colored_label(Color32::from_rgb(255, 90, 70), format!("● {status}"));
+            });
+    }
+
+    /// Fetch the Home Assistant areas if they haven't been, a failed fetch leaves the list empty
+    /// until it's refreshed
+    fn get_areas(&mut self) {
+        let network_store = self.network_data.clone();
+        let mut network_data_guard = network_store.lock();
+        match &network_data_guard.hass_areas {
+            DownloadAreas::None => {
+                if self.hass_areas.is_none() {
+                    network_data_guard.hass_areas = DownloadAreas::InProgress;
+                    drop(network_data_guard);
+                    get_areas(&self.host, &self.account.auth_token, move |res| {
+                        network_store.lock().hass_areas = DownloadAreas::Done(res);
+                    });
+                }
+            }
+            DownloadAreas::InProgress => {}
+            DownloadAreas::Done(ref response) => {
+                match response {
+                    Ok(areas) => self.hass_areas = Some(areas.clone()),
+                    Err(e) => {
+                        log::error!("Failed to fetch areas: {:?}", e);
+                        self.hass_areas = Some(Vec::new());
+                    }
+                }
+                network_data_guard.hass_areas = DownloadAreas::None;
+            }
+        }
+    }
+
+    /// Flag the layout for reloading when another device has saved since it was fetched,
+    /// local edits win until they are saved or discarded
+    fn check_layout_revision(&mut self, revision: u64) {
+        if self.layout_revision.is_some_and(|known| known != revision) {
+            self.layout_outdated = true;
+            if self.edit_mode.enabled {
+                self.toasts
+                    .lock()
+                    .info("Layout was changed on another device, saving will overwrite it")
+                    .duration(Some(Duration::from_secs(5)));
+            }
+        }
+        self.layout_revision = Some(revision);
+    }
+
+    fn apply_states(&mut self, states: &HAState) {
+        // Update all data with the new state
+        for room in &mut self.layout.rooms {
+            for sensor in &room.sensors {
+                for (packet_id, packet_state) in &states.sensors {
+                    if &sensor.entity_id == packet_id {
+                        room.hass_data
+                            .insert(sensor.entity_id.clone(), packet_state.clone());
+                    }
+                }
+            }
+            for light in &mut room.lights {
+                let Some(packet_state) = states.lights.get(&light.entity_id) else {
+                    continue;
+                };
+                // Local edits hold until the server reports the same state, or they time out
+                if light.last_manual != 0.0
+                    && packet_state.abs_diff(light.state) <= HOME_ASSISTANT_STATE_CONFIRM_TOLERANCE
+                {
+                    light.last_manual = 0.0;
+                }
+                if light.last_manual == 0.0
+                    || self.time > light.last_manual + HOME_ASSISTANT_STATE_LOCAL_OVERRIDE
+                {
+                    light.state = *packet_state;
+                }
+            }
+            for furniture in &mut room.furniture {
+                for sensor in &furniture.wanted_sensors() {
+                    for (packet_id, packet_state) in &states.sensors {
+                        if sensor == packet_id {
+                            furniture
+                                .hass_data
+                                .insert(sensor.clone(), packet_state.clone());
+                        }
                     }
                 }
-                network_data_guard.hass_states =
-                    DownloadStates::Waiting(self.time + HOME_ASSISTANT_STATE_REFRESH);
             }
         }
+        self.presence_points.clone_from(&states.presence_points);
+
+        // Add the new points to the trail and age out samples past its window
+        let window = self.device.presence_trail_seconds;
+        self.presence_trail.extend(
+            states
+                .presence_points
+                .iter()
+                .filter(|_| window > 0.0)
+                .map(|point| (self.time, point.pos)),
+        );
+        while self.presence_trail.len() > PRESENCE_TRAIL_MAX_SAMPLES
+            || self
+                .presence_trail
+                .front()
+                .is_some_and(|&(time, _)| self.time - time > window)
+        {
+            self.presence_trail.pop_front();
+        }
+    }
+
+    /// Snapshot the currently displayed states so they can be shown offline
+    fn capture_states(&self) -> HAState {
+        let mut states = HAState {
+            presence_points: self.presence_points.clone(),
+            ..HAState::default()
+        };
+        for room in &self.layout.rooms {
+            states.sensors.extend(room.hass_data.clone());
+            for light in &room.lights {
+                states.lights.insert(light.entity_id.clone(), light.state);
+            }
+            for furniture in &room.furniture {
+                states.sensors.extend(furniture.hass_data.clone());
+            }
+        }
+        states
     }
 
     fn post_states(&mut self) {
@@ -388,10 +793,11 @@ impl HomeFlow {
             UploadStates::None => {
                 network_data_guard.hass_post = UploadStates::InProgress;
                 drop(network_data_guard);
+                dedupe_actions(&mut self.post_queue);
                 let next_post = self.time;
                 post_actions(
                     &self.host,
-                    &self.stored.auth_token,
+                    &self.account.auth_token,
                     &self.post_queue,
                     move |_| {
                         network_store.lock().hass_post =
@@ -413,7 +819,8 @@ impl HomeFlow {
 impl eframe::App for HomeFlow {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, &self.stored);
+        eframe::set_value(storage, DEVICE_KEY, &self.device);
+        eframe::set_value(storage, ACCOUNT_KEY, &self.account);
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
@@ -432,7 +839,7 @@ impl eframe::App for HomeFlow {
         });
 
         // If no auth token, show login screen
-        if self.stored.auth_token.is_empty() {
+        if self.account.auth_token.is_empty() {
             CentralPanel::default()
                 .frame(Frame {
                     fill: Color32::from_rgb(25, 25, 35),
@@ -498,10 +905,10 @@ impl eframe::App for HomeFlow {
                                                         .info(message)
                                                         .duration(Some(Duration::from_secs(3)));
 
-                                                    self.stored.auth_token = token.to_string();
+                                                    self.account.auth_token = token.to_string();
                                                 } else {
                                                     // If no | is found, treat the entire response as the token
-                                                    self.stored.auth_token.clone_from(response);
+                                                    self.account.auth_token.clone_from(response);
                                                 }
                                             }
                                             Err(e) => {
@@ -529,6 +936,7 @@ impl eframe::App for HomeFlow {
         }
         self.get_states();
         self.post_states();
+        self.handle_screenshot(ctx);
 
         CentralPanel::default()
             .frame(Frame {
@@ -542,6 +950,7 @@ impl eframe::App for HomeFlow {
                 let (response, painter) =
                     ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
                 self.canvas_center = egui_pos_to_vec2(response.rect.center());
+                self.canvas_rect = response.rect;
 
                 let mouse_pos = ui
                     .input(|i| i.pointer.interact_pos())
@@ -551,7 +960,8 @@ impl eframe::App for HomeFlow {
 
                 self.is_mobile = ctx.screen_rect().size().x < 550.0;
 
-                let edit_mode_response = if self.is_mobile {
+                // Phones only get edit mode once a long press menu turns it on
+                let edit_mode_response = if self.is_mobile && !self.edit_mode.enabled {
                     EditResponse {
                         used_dragged: false,
                         hovered_id: None,
@@ -568,15 +978,57 @@ impl eframe::App for HomeFlow {
                     self.handle_pan_zoom(&response, ui);
                 }
 
+                if let Some(grid_size) = self
+                    .edit_mode
+                    .grid_size
+                    .filter(|_| self.edit_mode.enabled && self.edit_mode.show_grid)
+                {
+                    let stroke = Stroke::new(1.0, Color32::from_white_alpha(20));
+                    self.paint_grid_lines(&painter, grid_size, stroke);
+                }
                 self.render_layout(&painter);
+                if self.device.show_sun_path {
+                    self.paint_sun_path(&painter);
+                }
 
-                if !self.is_mobile && self.edit_mode.enabled {
+                if !edit_mode_response.used_dragged {
+                    self.handle_double_click(&response);
+                }
+                self.handle_long_press(&response);
+
+                if self.edit_mode.enabled {
                     self.paint_edit_mode(&painter, &edit_mode_response);
                 } else {
+                    if self.device.show_operations {
+                        for room in &self.layout.rooms {
+                            self.paint_operations(&painter, room, 0.3);
+                        }
+                    }
                     self.interact_with_layout(&response, &painter);
                 }
 
-                if !self.is_mobile {
+                if let Some(total_power) = self.layout.total_power_draw() {
+                    Window::new("Top Bar")
+                        .fixed_pos(egui::pos2(
+                            response.rect.center().x,
+                            response.rect.top() + 10.0,
+                        ))
+                        .pivot(Align2::CENTER_TOP)
+                        .title_bar(false)
+                        .resizable(false)
+                        .constrain(false)
+                        .show(ctx, |ui| {
+                            ui.colored_label(
+                                power_draw_color(total_power),
+                                format!("⚡ {} W", total_power.round() as i64),
+                            );
+                        });
+                }
+
+                self.phase_legend(ctx, response.rect);
+                self.connection_status(ctx, response.rect);
+
+                if !self.is_mobile || self.edit_mode.enabled {
                     Window::new("Bottom Right")
                         .fixed_pos(egui::pos2(
                             response.rect.right() - 10.0,
@@ -606,6 +1058,25 @@ impl eframe::App for HomeFlow {
     }
 }
 
+/// Keep only the last of the queued actions with the same entity, domain and action,
+/// the survivors stay in the order they were last queued
+fn dedupe_actions(actions: &mut Vec<PostActionsData>) {
+    let mut seen = AHashSet::new();
+    let mut kept: Vec<PostActionsData> = actions
+        .drain(..)
+        .rev()
+        .filter(|action| {
+            seen.insert((
+                action.entity_id.clone(),
+                action.domain.clone(),
+                action.action.clone(),
+            ))
+        })
+        .collect();
+    kept.reverse();
+    *actions = kept;
+}
+
 pub const fn vec2_to_egui_pos(vec: Vec2) -> egui::Pos2 {
     egui::pos2(vec.x as f32, vec.y as f32)
 }
@@ -617,3 +1088,50 @@ pub const fn egui_to_vec2(vec: egui::Vec2) -> Vec2 {
 pub const fn egui_pos_to_vec2(vec: egui::Pos2) -> Vec2 {
     vec2(vec.x as f64, vec.y as f64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::layout::DataPoint;
+
+    fn action(entity_id: &str, action: &str, brightness: f64) -> PostActionsData {
+        PostActionsData {
+            entity_id: entity_id.to_owned(),
+            domain: "light".to_owned(),
+            action: action.to_owned(),
+            additional_data: [("brightness".to_owned(), DataPoint::Float(brightness))].into(),
+        }
+    }
+
+    #[test]
+    fn dedupe_keeps_the_last_of_each_action_in_order() {
+        let mut actions = vec![
+            action("light.kitchen", "turn_on", 0.2),
+            action("light.hall", "turn_on", 0.5),
+            action("light.kitchen", "turn_off", 0.0),
+            action("light.kitchen", "turn_on", 0.8),
+            action("light.porch", "turn_on", 1.0),
+        ];
+        dedupe_actions(&mut actions);
+
+        let kept: Vec<_> = actions
+            .iter()
+            .map(|a| {
+                let brightness = match a.additional_data["brightness"] {
+                    DataPoint::Float(brightness) => brightness,
+                    _ => unreachable!(),
+                };
+                (a.entity_id.as_str(), a.action.as_str(), brightness)
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("light.hall", "turn_on", 0.5),
+                ("light.kitchen", "turn_off", 0.0),
+                ("light.kitchen", "turn_on", 0.8),
+                ("light.porch", "turn_on", 1.0),
+            ]
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use crate::common::{
-    layout::Home, HAState, LoginPacket, PostActionsData, PostActionsPacket, SaveLayoutPacket,
-    TokenPacket,
+    layout::Home, GetStatesPacket, HAArea, HAState, LoginPacket, PostActionsData,
+    PostActionsPacket, SaveLayoutPacket, TokenPacket,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use ewebsock::{WsEvent, WsMessage, WsReceiver, WsSender};
 
 pub fn get_layout(host: &str, token: &str, on_done: impl 'static + Send + FnOnce(Result<Home>)) {
     ehttp::fetch(
@@ -17,8 +18,13 @@ pub fn get_layout(host: &str, token: &str, on_done: impl 'static + Send + FnOnce
             on_done(match res {
                 Ok(res) => {
                     if res.status == 200 {
-                        bincode::deserialize(&res.bytes)
-                            .map_or_else(|_| Err(anyhow::anyhow!("Failed to load layout")), Ok)
+                        bincode::deserialize(&res.bytes).map_or_else(
+                            |_| Err(anyhow::anyhow!("Failed to load layout")),
+                            |mut home: Home| {
+                                home.migrate();
+                                Ok(home)
+                            },
+                        )
                     } else {
                         Err(anyhow::anyhow!(
                             "Failed to load layout, status code: {}",
@@ -53,12 +59,18 @@ pub fn save_layout(
     );
 }
 
-pub fn get_states(host: &str, token: &str, on_done: impl 'static + Send + FnOnce(Result<HAState>)) {
+pub fn get_states(
+    host: &str,
+    token: &str,
+    include_slow: bool,
+    on_done: impl 'static + Send + FnOnce(Result<HAState>),
+) {
     ehttp::fetch(
         ehttp::Request::post(
             format!("http://{host}/get_states"),
-            bincode::serialize(&TokenPacket {
+            bincode::serialize(&GetStatesPacket {
                 token: token.to_string(),
+                include_slow,
             })
             .unwrap(),
         ),
@@ -81,6 +93,104 @@ pub fn get_states(host: &str, token: &str, on_done: impl 'static + Send + FnOnce
     );
 }
 
+pub fn get_areas(
+    host: &str,
+    token: &str,
+    on_done: impl 'static + Send + FnOnce(Result<Vec<HAArea>>),
+) {
+    ehttp::fetch(
+        ehttp::Request::post(
+            format!("http://{host}/get_areas"),
+            bincode::serialize(&TokenPacket {
+                token: token.to_string(),
+            })
+            .unwrap(),
+        ),
+        Box::new(move |res: std::result::Result<ehttp::Response, String>| {
+            on_done(match res {
+                Ok(res) => {
+                    if res.status == 200 {
+                        bincode::deserialize(&res.bytes)
+                            .map_or_else(|_| Err(anyhow::anyhow!("Failed to load areas")), Ok)
+                    } else {
+                        Err(anyhow::anyhow!(
+                            "Failed to load areas, status code: {}",
+                            res.status
+                        ))
+                    }
+                }
+                Err(e) => Err(anyhow::anyhow!("Network error loading areas: {}", e)),
+            });
+        }),
+    );
+}
+
+/// Connection the server pushes state changes over, the first packet and periodic keyframes hold
+/// every state
+pub struct StateStream {
+    sender: WsSender,
+    receiver: WsReceiver,
+    token: String,
+    open: bool,
+}
+
+impl StateStream {
+    pub fn connect(host: &str, token: &str) -> Result<Self> {
+        let (sender, receiver) = ewebsock::connect(
+            format!("ws://{host}/stream_states"),
+            ewebsock::Options::default(),
+        )
+        .map_err(|e| anyhow!("Failed to open state stream: {}", e))?;
+        Ok(Self {
+            sender,
+            receiver,
+            token: token.to_string(),
+            open: false,
+        })
+    }
+
+    /// Whether the connection is open and authenticated, until then states still need polling
+    pub const fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Ask for every state again rather than only the changes, such as once a new layout has
+    /// replaced the one the earlier states were applied to
+    pub fn resync(&mut self) {
+        if self.open {
+            self.sender.send(WsMessage::Binary(Vec::new()));
+        }
+    }
+
+    /// States received since the last call, an error once the connection has dropped
+    pub fn poll(&mut self) -> Result<Vec<HAState>> {
+        let mut states = Vec::new();
+        while let Some(event) = self.receiver.try_recv() {
+            match event {
+                WsEvent::Opened => {
+                    self.sender.send(WsMessage::Binary(
+                        bincode::serialize(&TokenPacket {
+                            token: self.token.clone(),
+                        })
+                        .unwrap(),
+                    ));
+                }
+                WsEvent::Message(WsMessage::Binary(bytes)) => {
+                    states.push(
+                        bincode::deserialize(&bytes)
+                            .map_err(|_| anyhow!("Failed to read streamed states"))?,
+                    );
+                    self.open = true;
+                }
+                WsEvent::Message(_) => {}
+                WsEvent::Error(e) => return Err(anyhow!("State stream error: {}", e)),
+                WsEvent::Closed => return Err(anyhow!("State stream closed")),
+            }
+        }
+        Ok(states)
+    }
+}
+
 pub fn post_actions(
     host: &str,
     token: &str,
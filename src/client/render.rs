@@ -1,10 +1,14 @@
 use crate::{
-    client::{vec2_to_egui_pos, HomeFlow},
+    client::{
+        edit_mode_utils::{combo_box_for_enum, edit_option},
+        vec2_to_egui_pos, HomeFlow,
+    },
     common::{
         color::Color,
         furniture::{AnimatedPieceType, Furniture, FurnitureType},
-        layout::{OpeningType, Shape},
+        layout::{LineStyle, Location, OpeningType, Phase, Room, Shape, WindowShape},
         shape::{point_to_vec2, WALL_WIDTH},
+        sun::{bearing_to_direction, solar_position, SunPosition},
         utils::{rotate_point, rotate_point_i32, rotate_point_pivot, Lerp, Material},
     },
 };
@@ -15,26 +19,354 @@ use egui::{
     TextureOptions,
 };
 use glam::{dvec2 as vec2, DVec2 as Vec2};
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+pub(crate) const WALL_COLOR: Color32 = Color32::from_rgb(130, 80, 20);
+pub(crate) const DOOR_COLOR: Color32 = Color32::from_rgb(200, 130, 40);
+pub(crate) const WINDOW_COLOR: Color32 = Color32::from_rgb(80, 140, 240);
+// Exterior walls are drawn this much darker than interior ones
+pub(crate) const EXTERIOR_WALL_SHADE: f32 = 0.7;
+// Furthest a bay window projects out from its wall
+pub(crate) const BAY_MAX_DEPTH: f64 = 0.6;
+
+const POWER_DRAW_MEDIUM: f64 = 200.0;
+const POWER_DRAW_HIGH: f64 = 1000.0;
+
+// Presence icons grow and shrink by this fraction at this many radians per second
+const PRESENCE_PULSE_AMOUNT: f64 = 0.08;
+const PRESENCE_PULSE_SPEED: f64 = 2.5;
+// Opacity of the newest presence trail samples, overlapping samples build up like a heatmap
+const PRESENCE_TRAIL_OPACITY: f32 = 0.15;
+
+// Temperatures in celsius shown fully blue and fully red by the temperature overlay
+const TEMPERATURE_COLD: f64 = 15.0;
+const TEMPERATURE_HOT: f64 = 28.0;
+const TEMPERATURE_COLOR_COLD: Color32 = Color32::from_rgb(60, 120, 255);
+const TEMPERATURE_COLOR_HOT: Color32 = Color32::from_rgb(255, 70, 50);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Default, Debug)]
+pub enum Easing {
+    Linear,
+    #[default]
+    EaseInOut,
+    Spring,
+}
+
+impl Easing {
+    /// Maps linear progress 0-1 to eased progress, spring overshoots slightly before settling
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Self::Spring => 1.0 - (-5.0 * t).exp() * (2.5 * std::f64::consts::PI * t).cos(),
+        }
+    }
+}
+
+/// Whether wall shadows are drawn beneath the baked lighting overlay or on top of it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Default, Debug)]
+pub enum ShadowOrder {
+    #[default]
+    BelowLighting,
+    AboveLighting,
+}
+
+/// Opacity and outline color that objects in each renovation phase are drawn with
+pub const fn phase_style(phase: Phase) -> (f32, Option<Color32>) {
+    match phase {
+        Phase::Existing => (1.0, None),
+        Phase::New => (1.0, Some(Color32::from_rgb(80, 200, 120))),
+        Phase::ToRemove => (0.35, Some(Color32::from_rgb(230, 80, 70))),
+    }
+}
+
+/// When the metric background grid is drawn behind the layout
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Default, Debug)]
+pub enum GridVisibility {
+    Never,
+    #[default]
+    EditMode,
+    Always,
+}
+
+pub const fn power_draw_color(power_draw: f64) -> Color32 {
+    if power_draw >= POWER_DRAW_HIGH {
+        Color32::from_rgb(255, 90, 70)
+    } else if power_draw >= POWER_DRAW_MEDIUM {
+        Color32::from_rgb(255, 190, 60)
+    } else {
+        Color32::WHITE
+    }
+}
+
+/// Person icon centered on a point, sitting side on when seated
+fn paint_person(painter: &Painter, center: egui::Pos2, scale: f32, seated: bool) {
+    let fill = Color32::from_rgb(0, 240, 140);
+    let outline = Color32::from_rgb(0, 120, 70);
+    let (head, limbs) = if seated {
+        (
+            evec2(0.0, -0.45),
+            vec![
+                (evec2(0.0, -0.1), evec2(0.0, 0.35)),
+                (evec2(0.0, 0.35), evec2(0.45, 0.35)),
+                (evec2(0.45, 0.35), evec2(0.45, 0.8)),
+            ],
+        )
+    } else {
+        (evec2(0.0, -0.6), vec![(evec2(0.0, -0.25), evec2(0.0, 0.8))])
+    };
+    // Draw the outline then the fill over it, so overlapping limbs join cleanly
+    for (color, grow) in [(outline, 0.15), (fill, 0.0)] {
+        let width = (0.4 + grow) * scale;
+        for (start, end) in &limbs {
+            let (start, end) = (center + *start * scale, center + *end * scale);
+            painter.circle_filled(start, width / 2.0, color);
+            painter.circle_filled(end, width / 2.0, color);
+            painter.line_segment([start, end], Stroke::new(width, color));
+        }
+        painter.circle_filled(center + head * scale, (0.3 + grow / 2.0) * scale, color);
+    }
+}
+
+/// Average of a rooms temperature sensors in celsius, sensors reporting fahrenheit are converted
+fn room_temperature(room: &Room) -> Option<f64> {
+    let temperatures: Vec<f64> = room
+        .sensors
+        .iter()
+        .filter_map(|sensor| {
+            let value = room.hass_data.get(&sensor.entity_id)?.parse::<f64>().ok()?;
+            match sensor.unit.trim() {
+                "°C" | "C" => Some(value),
+                "°F" | "F" => Some((value - 32.0) * 5.0 / 9.0),
+                _ => None,
+            }
+        })
+        .collect();
+    (!temperatures.is_empty()).then(|| temperatures.iter().sum::<f64>() / temperatures.len() as f64)
+}
 
-const WALL_COLOR: Color32 = Color32::from_rgb(130, 80, 20);
-const DOOR_COLOR: Color32 = Color32::from_rgb(200, 130, 40);
-const WINDOW_COLOR: Color32 = Color32::from_rgb(80, 140, 240);
+/// Tint from blue to red for a temperature in celsius
+fn temperature_color(temperature: f64) -> Color32 {
+    let t =
+        ((temperature - TEMPERATURE_COLD) / (TEMPERATURE_HOT - TEMPERATURE_COLD)).clamp(0.0, 1.0);
+    Color32::from_rgb(
+        TEMPERATURE_COLOR_COLD
+            .r()
+            .lerp(TEMPERATURE_COLOR_HOT.r(), t),
+        TEMPERATURE_COLOR_COLD
+            .g()
+            .lerp(TEMPERATURE_COLOR_HOT.g(), t),
+        TEMPERATURE_COLOR_COLD
+            .b()
+            .lerp(TEMPERATURE_COLOR_HOT.b(), t),
+    )
+}
 
 impl HomeFlow {
+    /// Key for the renovation phase styles, only shown once something is marked new or to remove
+    pub fn phase_legend(&mut self, ctx: &egui::Context, rect: egui::Rect) {
+        let phases_used = self.layout.rooms.iter().any(|room| {
+            room.phase != Phase::Existing
+                || room.furniture.iter().any(|f| f.phase != Phase::Existing)
+        });
+        if !phases_used && self.device.phase_filter.is_none() {
+            return;
+        }
+        egui::Window::new("Phase Legend")
+            .fixed_pos(egui::pos2(rect.left() + 10.0, rect.bottom() - 10.0))
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .title_bar(false)
+            .resizable(false)
+            .constrain(false)
+            .show(ctx, |ui| {
+                for phase in Phase::iter() {
+                    let (opacity, outline) = phase_style(phase);
+                    ui.colored_label(
+                        outline.unwrap_or(Color32::WHITE).gamma_multiply(opacity),
+                        format!("■ {phase}"),
+                    );
+                }
+                edit_option(
+                    ui,
+                    "Only Show",
+                    &mut self.device.phase_filter,
+                    || Phase::Existing,
+                    |ui, phase| combo_box_for_enum(ui, "Phase Filter", phase, ""),
+                );
+            });
+    }
+
+    /// Day of the year and hour the sun is shown and daylight is lit at
+    pub const fn sun_time(&self) -> (u32, f64) {
+        (self.device.sun_day, self.device.sun_hour)
+    }
+
+    /// Sun arc for the configured day around the edge of the plan, with the sun at the chosen hour
+    /// and a north marker, higher sun is drawn closer in
+    pub fn paint_sun_path(&self, painter: &Painter) {
+        const SUN_COLOR: Color32 = Color32::from_rgb(255, 200, 60);
+        let Location {
+            latitude,
+            north_rotation,
+        } = self.layout.location;
+        let (day, hour) = self.sun_time();
+        let (min, max) = self.bounds;
+        let center = (min + max) / 2.0;
+        let radius = (max - min).length() / 2.0 + 0.5;
+        let project = |sun: SunPosition| {
+            let distance = radius * (1.0 - sun.elevation / 90.0 * 0.5);
+            self.world_to_screen_pos(
+                center + bearing_to_direction(sun.azimuth, north_rotation) * distance,
+            )
+        };
+
+        let arc: Vec<_> = (0..=96)
+            .map(|step| solar_position(latitude, day, f64::from(step) / 4.0))
+            .filter(|sun| sun.elevation > 0.0)
+            .map(project)
+            .collect();
+        if arc.len() > 1 {
+            painter.add(EShape::line(
+                arc,
+                Stroke::new(2.0, SUN_COLOR.gamma_multiply(0.5)),
+            ));
+        }
+
+        let sun = solar_position(latitude, day, hour);
+        if sun.elevation > 0.0 {
+            let pos = project(sun);
+            painter.line_segment(
+                [self.world_to_screen_pos(center), pos],
+                Stroke::new(1.0, SUN_COLOR.gamma_multiply(0.3)),
+            );
+            painter.circle_filled(pos, 10.0, SUN_COLOR);
+        }
+
+        let north = self.world_to_screen_pos(
+            center + bearing_to_direction(0.0, north_rotation) * (radius + 0.3),
+        );
+        painter.text(
+            north,
+            egui::Align2::CENTER_CENTER,
+            "N",
+            FontId::proportional(18.0),
+            Color32::WHITE,
+        );
+    }
+
+    /// Metric grid every meter, heavier every five, with a marker at the world origin,
+    /// fading out as the view zooms away so it doesn't turn into noise
+    fn render_background_grid(&self, painter: &Painter) {
+        let visible = match self.device.grid_visibility {
+            GridVisibility::Never => false,
+            GridVisibility::EditMode => self.edit_mode.enabled,
+            GridVisibility::Always => true,
+        };
+        if !visible {
+            return;
+        }
+        let fade = ((self.device.zoom - 10.0) / 40.0).clamp(0.0, 1.0) as f32;
+        if fade <= 0.0 {
+            return;
+        }
+        let minor = Color32::from_white_alpha(12).gamma_multiply(fade);
+        let major = Color32::from_white_alpha(30).gamma_multiply(fade);
+        self.paint_grid_lines(painter, 1.0, Stroke::new(1.0, minor));
+        self.paint_grid_lines(painter, 5.0, Stroke::new(2.0, major));
+
+        let origin = self.world_to_screen_pos(Vec2::ZERO);
+        let stroke = Stroke::new(2.0, Color32::from_white_alpha(80).gamma_multiply(fade));
+        painter.line_segment([origin - evec2(8.0, 0.0), origin + evec2(8.0, 0.0)], stroke);
+        painter.line_segment([origin - evec2(0.0, 8.0), origin + evec2(0.0, 8.0)], stroke);
+        painter.circle_stroke(origin, 5.0, stroke);
+    }
+
+    /// Lines every cell across the visible canvas, skipped when they would be closer than a few pixels
+    pub fn paint_grid_lines(&self, painter: &Painter, cell: f64, stroke: Stroke) {
+        if cell * self.device.zoom < 4.0 {
+            return;
+        }
+        let rect = painter.clip_rect();
+        let (min, max) = [
+            rect.left_top(),
+            rect.right_top(),
+            rect.left_bottom(),
+            rect.right_bottom(),
+        ]
+        .into_iter()
+        .map(|corner| self.screen_to_world(vec2(f64::from(corner.x), f64::from(corner.y))))
+        .fold(
+            (Vec2::splat(f64::INFINITY), Vec2::splat(f64::NEG_INFINITY)),
+            |(min, max), corner| (min.min(corner), max.max(corner)),
+        );
+        let (start, end) = ((min / cell).floor(), (max / cell).ceil());
+        for x in start.x as i64..=end.x as i64 {
+            let x = x as f64 * cell;
+            painter.line_segment(
+                [
+                    self.world_to_screen_pos(vec2(x, min.y)),
+                    self.world_to_screen_pos(vec2(x, max.y)),
+                ],
+                stroke,
+            );
+        }
+        for y in start.y as i64..=end.y as i64 {
+            let y = y as f64 * cell;
+            painter.line_segment(
+                [
+                    self.world_to_screen_pos(vec2(min.x, y)),
+                    self.world_to_screen_pos(vec2(max.x, y)),
+                ],
+                stroke,
+            );
+        }
+    }
+
     pub fn load_texture(&self, material: Material) -> TextureId {
         self.textures.get(&material.to_string()).unwrap().id()
     }
 
+    fn render_power_draw(
+        &self,
+        painter: &Painter,
+        pos: Vec2,
+        power_draw: f64,
+        scale: f32,
+        alpha: f32,
+    ) {
+        let power_draw_scale = scale * self.device.zoom as f32;
+        let color = power_draw_color(power_draw);
+
+        let galley = painter.layout_no_wrap(
+            format!("⚡ {} W", power_draw.round() as i64),
+            FontId::proportional(power_draw_scale),
+            color.gamma_multiply(alpha),
+        );
+        let rect =
+            egui::Align2::CENTER_CENTER.anchor_size(self.world_to_screen_pos(pos), galley.size());
+        painter.add(EShape::rect_filled(
+            rect.expand(power_draw_scale * 0.5),
+            power_draw_scale,
+            Color32::from_black_alpha((150.0 * alpha).round() as u8),
+        ));
+        painter.galley(rect.min, galley, color);
+    }
+
     pub fn render_layout(&mut self, painter: &Painter) {
         if self.layout.version.is_empty() {
             return;
         }
-        self.layout.render(self.edit_mode.enabled);
+        self.layout
+            .render(self.edit_mode.enabled, self.device.furniture_detail);
         if self.layout.rendered_data.is_none() {
             return;
         }
         if !self.edit_mode.enabled {
-            self.layout.render_lighting();
+            self.update_lighting(painter.ctx());
         }
         self.bounds = self.layout.bounds();
 
@@ -42,11 +374,16 @@ impl HomeFlow {
         let mut materials_to_ready = Vec::new();
         for room in &self.layout.rooms {
             if let Some(data) = &room.rendered_data {
-                for material in data.material_triangles.keys() {
-                    materials_to_ready.push(self.layout.get_global_material(material).material);
+                for layer in data.material_triangles.keys() {
+                    materials_to_ready.push(self.layout.layer_material(layer).material);
                 }
             }
         }
+        if let Some(wall_material) = &self.layout.wall_material {
+            materials_to_ready.push(self.layout.get_global_material(wall_material).material);
+        }
+        // Every global material is shown as a thumbnail in the material pickers
+        materials_to_ready.extend(self.layout.materials.iter().map(|m| m.material));
         for room in &self.layout.rooms {
             for furniture in &room.furniture {
                 let rendered_data = furniture.rendered_data.as_ref().unwrap();
@@ -80,20 +417,38 @@ impl HomeFlow {
                 });
         }
 
+        self.render_background_grid(painter);
+
         // Render rooms
+        let phase_filter = self.device.phase_filter;
+        let phase_visible = move |phase: Phase| phase_filter.is_none_or(|p| p == phase);
         for room in &self.layout.rooms {
+            if !phase_visible(room.phase) || !self.device.tags_visible(&room.tags) {
+                continue;
+            }
+            let (phase_opacity, phase_outline) = phase_style(room.phase);
             let rendered_data = room.rendered_data.as_ref().unwrap();
-            for (material, multi_triangles) in &rendered_data.material_triangles {
-                let global_material = self.layout.get_global_material(material);
+            // The temperature overlay tints the floor in place of the material colour
+            let temperature_tint = room_temperature(room)
+                .filter(|_| self.device.temperature_overlay)
+                .map(temperature_color);
+            for (layer, multi_triangles) in &rendered_data.material_triangles {
+                let global_material = self.layout.layer_material(layer);
                 let texture_id = self.load_texture(global_material.material);
+                let color = temperature_tint
+                    .unwrap_or_else(|| global_material.tint.to_egui())
+                    .gamma_multiply(self.device.floor_opacity * phase_opacity);
                 for triangles in multi_triangles {
                     let vertices = triangles
                         .vertices
                         .iter()
                         .map(|&v| Vertex {
                             pos: self.world_to_screen_pos(v),
-                            uv: vec2_to_egui_pos(v * 0.2),
-                            color: global_material.tint.to_egui(),
+                            uv: vec2_to_egui_pos(
+                                rotate_point_i32(v, global_material.rotation) * 0.2
+                                    / global_material.scale,
+                            ),
+                            color,
                         })
                         .collect();
                     painter.add(EShape::mesh(Mesh {
@@ -106,19 +461,52 @@ impl HomeFlow {
             // Render outline line around each of the rooms polygons
             if let Some(outline) = &room.outline {
                 let rendered_data = room.rendered_data.as_ref().unwrap();
+                let width = (outline.thickness * self.device.zoom) as f32;
+                let stroke = Stroke::new(width, outline.color.to_egui());
                 for polygon in &rendered_data.polygons {
-                    let vertices = polygon
+                    // Exterior points are closed, the last point matches the first
+                    let vertices: Vec<_> = polygon
                         .exterior()
                         .points()
                         .map(|v| self.world_to_screen_pos(point_to_vec2(v)))
                         .collect();
-                    painter.add(EShape::closed_line(
-                        vertices,
-                        Stroke::new(
-                            (outline.thickness * self.stored.zoom) as f32,
-                            outline.color.to_egui(),
-                        ),
-                    ));
+                    match outline.style {
+                        LineStyle::Solid => {
+                            painter.add(EShape::closed_line(vertices, stroke));
+                        }
+                        LineStyle::Dashed => {
+                            painter.extend(EShape::dashed_line(
+                                &vertices,
+                                stroke,
+                                width * 4.0,
+                                width * 2.0,
+                            ));
+                        }
+                        LineStyle::Dotted => {
+                            painter.extend(EShape::dotted_line(
+                                &vertices,
+                                stroke.color,
+                                width * 2.0,
+                                width * 0.5,
+                            ));
+                        }
+                    }
+                }
+            }
+            // New work is outlined solid, work to remove dashed
+            if let Some(color) = phase_outline {
+                let stroke = Stroke::new(3.0, color);
+                for polygon in &rendered_data.polygons {
+                    let vertices: Vec<_> = polygon
+                        .exterior()
+                        .points()
+                        .map(|v| self.world_to_screen_pos(point_to_vec2(v)))
+                        .collect();
+                    if room.phase == Phase::ToRemove {
+                        painter.extend(EShape::dashed_line(&vertices, stroke, 12.0, 8.0));
+                    } else {
+                        painter.add(EShape::closed_line(vertices, stroke));
+                    }
                 }
             }
         }
@@ -127,12 +515,16 @@ impl HomeFlow {
         let mut furnitures_hovered = Vec::new();
         for room in &self.layout.rooms {
             for furniture in &room.furniture {
+                if !self.device.furniture_visible(room, furniture) {
+                    continue;
+                }
+                let (pos, rotation) = room.furniture_placement(furniture);
                 if furniture.can_hover()
                     && Shape::Rectangle.contains(
                         self.mouse_pos_world,
-                        room.pos + furniture.pos,
+                        pos,
                         furniture.size * 1.2,
-                        furniture.rotation,
+                        rotation,
                     )
                 {
                     furnitures_hovered.push(furniture);
@@ -142,11 +534,9 @@ impl HomeFlow {
                     if child.can_hover()
                         && Shape::Rectangle.contains(
                             self.mouse_pos_world,
-                            room.pos
-                                + furniture.pos
-                                + rotate_point_i32(child.pos, -furniture.rotation),
+                            pos + rotate_point_i32(child.pos, -rotation),
                             child.size * 1.2,
-                            furniture.rotation + child.rotation,
+                            rotation + child.rotation,
                         )
                     {
                         furnitures_hovered.push(child);
@@ -156,15 +546,20 @@ impl HomeFlow {
         }
         let mut furniture_sorted = furnitures_hovered.clone();
         furniture_sorted.sort_by_key(|f| f.get_render_order());
-        let top_hover = furniture_sorted.last().map(|f| f.id);
+        let hover_enabled = self.edit_mode.enabled || self.device.hover_in_view_mode;
+        let top_hover = furniture_sorted
+            .last()
+            .map(|f| f.id)
+            .filter(|_| hover_enabled);
 
+        let hover_step = self.frame_time * self.device.hover_speed;
         for room in &mut self.layout.rooms {
             for furniture in &mut room.furniture {
                 let target = f64::from(Some(furniture.id) == top_hover) * 2.0 - 1.0;
                 let difference = target - furniture.hover_amount;
                 if difference.abs() > f64::EPSILON {
                     furniture.hover_amount = (furniture.hover_amount
-                        + difference.signum() * self.frame_time * 10.0)
+                        + difference.signum() * hover_step)
                         .clamp(-1.0, 1.0);
                 }
                 let rendered_data = furniture.rendered_data.as_mut().unwrap();
@@ -173,7 +568,7 @@ impl HomeFlow {
                     let difference = target - child.hover_amount;
                     if difference.abs() > f64::EPSILON {
                         child.hover_amount = (child.hover_amount
-                            + difference.signum() * self.frame_time * 10.0)
+                            + difference.signum() * hover_step)
                             .clamp(-1.0, 1.0);
                     }
                 }
@@ -183,10 +578,13 @@ impl HomeFlow {
         // Gather furniture and children
         let mut furniture_map = AHashMap::new();
         let mut furniture_locations = AHashMap::new();
+        let mut furniture_opacity = AHashMap::new();
         let mut child_adjustments = AHashMap::new();
 
-        let mut handle_furniture_child = |room_pos: Vec2, obj: &Furniture, child: &Furniture| {
-            let hover = child.hover_amount.max(0.0);
+        let easing = self.device.easing;
+        let hover_strength = self.device.hover_strength;
+        let mut handle_furniture_child = |obj_pos: Vec2, obj_rotation: i32, child: &Furniture| {
+            let hover = easing.apply(child.hover_amount) * hover_strength;
             let (offset, offset_rot) = match child.furniture_type {
                 FurnitureType::Chair(_) => (vec2(hover * 0.15, hover * 0.3), hover * 20.0),
                 FurnitureType::AnimatedPiece(animated_piece_type) => match animated_piece_type {
@@ -214,31 +612,44 @@ impl HomeFlow {
                 _ => (Vec2::ZERO, 0.0), // Handles other FurnitureTypes
             };
 
-            let offset = rotate_point_i32(offset, -(obj.rotation + child.rotation));
+            let offset = rotate_point_i32(offset, -(obj_rotation + child.rotation));
             child_adjustments.insert(
                 child.id,
                 (
-                    room_pos + obj.pos + rotate_point_i32(child.pos, -obj.rotation) + offset,
-                    f64::from(obj.rotation) + f64::from(child.rotation) + offset_rot,
+                    obj_pos + rotate_point_i32(child.pos, -obj_rotation) + offset,
+                    f64::from(obj_rotation) + f64::from(child.rotation) + offset_rot,
                 ),
             );
         };
 
         for room in &self.layout.rooms {
             for furniture in &room.furniture {
+                if !phase_visible(room.phase)
+                    || !phase_visible(furniture.phase)
+                    || !self.device.furniture_visible(room, furniture)
+                {
+                    continue;
+                }
                 let rendered_data = furniture.rendered_data.as_ref().unwrap();
-                furniture_locations.insert(
-                    furniture.id,
-                    (room.pos + furniture.pos, f64::from(furniture.rotation)),
-                );
+                let opacity = phase_style(furniture.phase).0 * phase_style(room.phase).0;
+                furniture_opacity.insert(furniture.id, opacity);
+                for child in &rendered_data.children {
+                    furniture_opacity.insert(child.id, opacity);
+                }
+                let (pos, rotation) = room.furniture_placement(furniture);
+                furniture_locations.insert(furniture.id, (pos, f64::from(rotation)));
+                let order = room.furniture_render_order(furniture);
                 furniture_map
-                    .entry(furniture.get_render_order())
+                    .entry(order)
                     .or_insert_with(Vec::new)
                     .push(furniture);
                 for child in &rendered_data.children {
-                    handle_furniture_child(room.pos, furniture, child);
+                    handle_furniture_child(pos, rotation, child);
+                    // Children keep their place relative to the parent when it is stacked higher
+                    let child_order =
+                        child.get_render_order() + order - furniture.get_render_order();
                     furniture_map
-                        .entry(child.get_render_order())
+                        .entry(child_order)
                         .or_insert_with(Vec::new)
                         .push(child);
                 }
@@ -259,6 +670,7 @@ impl HomeFlow {
                     let &(pos, rot) = furniture_locations
                         .get(&furniture.id)
                         .unwrap_or(&(vec2(0.0, 0.0), 0.0));
+                    let opacity = furniture_opacity.get(&furniture.id).copied().unwrap_or(1.0);
 
                     // Render shadow
                     let shadow_offset = vec2(0.01, -0.02);
@@ -279,7 +691,8 @@ impl HomeFlow {
                                     } else {
                                         Color::TRANSPARENT
                                     }
-                                    .to_egui(),
+                                    .to_egui()
+                                    .gamma_multiply(opacity),
                                 }
                             })
                             .collect();
@@ -295,6 +708,7 @@ impl HomeFlow {
                     let &(pos, rot) = furniture_locations
                         .get(&furniture.id)
                         .unwrap_or(&(vec2(0.0, 0.0), 0.0));
+                    let opacity = furniture_opacity.get(&furniture.id).copied().unwrap_or(1.0);
 
                     for (material, multi_triangles) in &rendered_data.triangles {
                         let texture_id = self.load_texture(material.material);
@@ -306,8 +720,10 @@ impl HomeFlow {
                                     let adjusted_v = rotate_point(v, -rot) + pos;
                                     Vertex {
                                         pos: self.world_to_screen_pos(adjusted_v),
-                                        uv: vec2_to_egui_pos(v * 0.2),
-                                        color: material.tint.to_egui(),
+                                        uv: vec2_to_egui_pos(
+                                            rotate_point_i32(v, material.rotation) * 0.2,
+                                        ),
+                                        color: material.tint.to_egui().gamma_multiply(opacity),
                                     }
                                 })
                                 .collect();
@@ -322,102 +738,33 @@ impl HomeFlow {
             }
         }
 
-        // Render wall shadows
-        let rendered_data = self.layout.rendered_data.as_ref().unwrap();
-        let shadow_offset = vec2(0.01, -0.02);
-        let (shadow_color, shadow_triangles) = &rendered_data.wall_shadows.1;
-        for triangles in shadow_triangles {
-            if triangles.vertices.is_empty() {
-                continue;
-            }
-            let vertices = triangles
-                .vertices
-                .iter()
-                .enumerate()
-                .map(|(i, &v)| {
-                    let is_interior = *triangles.inners.get(i).unwrap_or(&false);
-                    Vertex {
-                        pos: self.world_to_screen_pos(v + shadow_offset),
-                        uv: egui::Pos2::ZERO,
-                        color: if is_interior {
-                            *shadow_color
-                        } else {
-                            Color::TRANSPARENT
-                        }
-                        .to_egui(),
-                    }
-                })
-                .collect();
-            painter.add(EShape::mesh(Mesh {
-                indices: triangles.indices.clone(),
-                vertices,
-                texture_id: TextureId::Managed(0),
-            }));
+        if self.device.shadow_order == ShadowOrder::BelowLighting {
+            self.render_wall_shadows(painter);
         }
 
-        // Render lighting
+        // Render lighting, the overlay only darkens so without light data rooms stay fully lit
         if !self.edit_mode.enabled {
-            if let Some(light_data) = &self.layout.light_data {
-                // Check if the light data has changed and needs to be reloaded.
-                let needs_reload = self
-                    .light_data
-                    .as_ref()
-                    .map_or(true, |(hash, _)| *hash != light_data.hash);
-
-                if needs_reload {
-                    let texture = painter.ctx().load_texture(
-                        "lighting".to_string(),
-                        ColorImage::from_rgba_premultiplied(
-                            [
-                                light_data.image_width as usize,
-                                light_data.image_height as usize,
-                            ],
-                            &light_data.image,
-                        ),
-                        TextureOptions::LINEAR,
-                    );
-                    self.light_data = Some((light_data.hash, texture));
-                }
+            self.render_light_overlay(painter);
+        }
 
-                // Render the texture.
-                if let Some((_, texture_handle)) = &self.light_data {
-                    let vertices = [
-                        vec2(-0.5, -0.5),
-                        vec2(0.5, -0.5),
-                        vec2(0.5, 0.5),
-                        vec2(-0.5, 0.5),
-                    ]
-                    .iter()
-                    .map(|&v| Vertex {
-                        pos: self.world_to_screen_pos(
-                            light_data.image_center + v * light_data.image_size,
-                        ),
-                        uv: egui::pos2(v.x as f32 + 0.5, 1.0 - (v.y as f32 + 0.5)),
-                        color: Color::WHITE.to_egui(),
-                    })
-                    .collect();
-                    painter.add(EShape::mesh(Mesh {
-                        indices: vec![0, 1, 2, 0, 2, 3],
-                        vertices,
-                        texture_id: texture_handle.id(),
-                    }));
-                }
-            }
+        if self.device.shadow_order == ShadowOrder::AboveLighting {
+            self.render_wall_shadows(painter);
         }
 
         // Open the door if mouse is nearby
         for room in &mut self.layout.rooms {
             for opening in &mut room.openings {
-                if opening.opening_type != OpeningType::Door {
+                if !opening.opening_type.is_door() {
                     continue;
                 }
                 let mouse_distance = self.mouse_pos_world.distance(room.pos + opening.pos);
-                let target = f64::from(mouse_distance < opening.width / 2.0) * 2.0 - 1.0;
+                let target =
+                    f64::from(opening.open || mouse_distance < opening.width / 2.0) * 2.0 - 1.0;
                 let difference = target - opening.open_amount;
                 if difference.abs() > f64::EPSILON {
-                    // Linearly interpolate open_amount towards the target value.
+                    // Step open_amount linearly, easing is applied when rendering
                     opening.open_amount = (opening.open_amount
-                        + (target - opening.open_amount) * self.frame_time * 8.0)
+                        + difference.signum() * self.frame_time * 4.0)
                         .clamp(-1.0, 1.0);
                 }
             }
@@ -425,21 +772,17 @@ impl HomeFlow {
         // Render openings
         let mut window_meshes = Vec::new();
         for room in &self.layout.rooms {
+            if !self.device.tags_visible(&room.tags) {
+                continue;
+            }
             for opening in &room.openings {
-                let color = match opening.opening_type {
-                    OpeningType::Door => DOOR_COLOR,
-                    OpeningType::Window => WINDOW_COLOR,
+                let (color, depth) = if opening.opening_type.is_door() {
+                    (DOOR_COLOR, WALL_WIDTH * 0.8)
+                } else {
+                    (WINDOW_COLOR, WALL_WIDTH)
                 };
-                let depth = (match opening.opening_type {
-                    OpeningType::Door => WALL_WIDTH * 0.8,
-                    OpeningType::Window => WALL_WIDTH,
-                } * self.stored.zoom) as f32;
-                let rot_dir = vec2(
-                    f64::from(-opening.rotation).to_radians().cos(),
-                    f64::from(-opening.rotation).to_radians().sin(),
-                );
-                let hinge_pos_initial = room.pos + opening.pos + rot_dir * opening.width / 2.0;
-                let end_pos_initial = room.pos + opening.pos - rot_dir * opening.width / 2.0;
+                let depth = (depth * self.device.zoom) as f32;
+                let (hinge_pos_initial, end_pos_initial) = opening.span(room.pos);
                 let (hinge_pos, end_pos) = if opening.flipped {
                     (end_pos_initial, hinge_pos_initial)
                 } else {
@@ -452,40 +795,131 @@ impl HomeFlow {
 
                 let stroke = PathStroke::new(depth, color);
                 if opening.opening_type == OpeningType::Window {
-                    window_meshes.push(EShape::LineSegment { points, stroke });
+                    // Arches bow out a curved head beyond the glass, bays project a three sided frame
+                    let along = (end_pos - hinge_pos).normalize_or_zero();
+                    let center = (hinge_pos + end_pos) / 2.0;
+                    let outward = if room.contains(center + along.perp() * WALL_WIDTH) {
+                        -along.perp()
+                    } else {
+                        along.perp()
+                    };
+                    match opening.window_shape {
+                        WindowShape::Flat => {
+                            window_meshes.push(EShape::LineSegment { points, stroke });
+                        }
+                        WindowShape::Arched => {
+                            let rise = opening.width * 0.3;
+                            let arch = (0..=16)
+                                .map(|step| {
+                                    let t = f64::from(step) / 16.0;
+                                    let pos = hinge_pos.lerp(end_pos, t)
+                                        + outward * rise * (t * std::f64::consts::PI).sin();
+                                    self.world_to_screen_pos(pos)
+                                })
+                                .collect();
+                            window_meshes.push(EShape::LineSegment { points, stroke });
+                            window_meshes
+                                .push(EShape::line(arch, PathStroke::new(depth * 0.4, color)));
+                        }
+                        WindowShape::Bay => {
+                            let bay_depth = (opening.width * 0.25).min(BAY_MAX_DEPTH);
+                            let frame = [
+                                hinge_pos,
+                                hinge_pos + (outward + along) * bay_depth,
+                                end_pos + (outward - along) * bay_depth,
+                                end_pos,
+                            ]
+                            .map(|pos| self.world_to_screen_pos(pos))
+                            .to_vec();
+                            window_meshes.push(EShape::line(frame, stroke));
+                        }
+                    }
                 } else {
                     //Render a line filing the gap between the door and the wall
                     painter.add(EShape::LineSegment {
                         points,
                         stroke: PathStroke::new(depth * 0.75, Color32::from_rgb(80, 80, 80)),
                     });
-                    // Render the door
-                    let open_amount = opening.open_amount.max(0.0)
-                        * 40.0
-                        * if opening.flipped { -1.0 } else { 1.0 };
-                    let end_pos_door = rotate_point_pivot(end_pos, hinge_pos, open_amount);
-                    let points = [points[0], self.world_to_screen_pos(end_pos_door)];
-                    painter.circle_filled(points[0], depth * 0.5, color);
-                    painter.add(EShape::LineSegment { points, stroke });
+                    // Render the door, swinging leaves pivot on their hinges and sliding ones move along the wall
+                    let open_amount = self.device.easing.apply(opening.open_amount);
+                    let swing = open_amount * 40.0 * if opening.flipped { -1.0 } else { 1.0 };
+                    let leaves = match opening.opening_type {
+                        OpeningType::DoubleDoor => {
+                            let center = (hinge_pos + end_pos) / 2.0;
+                            vec![
+                                (
+                                    hinge_pos,
+                                    rotate_point_pivot(center, hinge_pos, swing),
+                                    true,
+                                ),
+                                (end_pos, rotate_point_pivot(center, end_pos, -swing), true),
+                            ]
+                        }
+                        OpeningType::SlidingDoor => {
+                            let slide = (hinge_pos - end_pos) * open_amount * 0.9;
+                            vec![(hinge_pos + slide, end_pos + slide, false)]
+                        }
+                        _ => vec![(
+                            hinge_pos,
+                            rotate_point_pivot(end_pos, hinge_pos, swing),
+                            true,
+                        )],
+                    };
+                    for (start, end, hinged) in leaves {
+                        let points = [
+                            self.world_to_screen_pos(start),
+                            self.world_to_screen_pos(end),
+                        ];
+                        if hinged {
+                            painter.circle_filled(points[0], depth * 0.5, color);
+                        }
+                        painter.add(EShape::LineSegment {
+                            points,
+                            stroke: stroke.clone(),
+                        });
+                    }
                 }
             }
         }
 
-        // Render walls
-        for wall in &rendered_data.wall_triangles {
+        // Render walls, textured with the wall material if one is set
+        let rendered_data = self.layout.rendered_data.as_ref().unwrap();
+        let (wall_texture, wall_color, wall_scale, wall_rotation) =
+            self.layout.wall_material.as_ref().map_or(
+                (TextureId::Managed(0), WALL_COLOR, 1.0, 0),
+                |wall_material| {
+                    let global_material = self.layout.get_global_material(wall_material);
+                    (
+                        self.load_texture(global_material.material),
+                        global_material.tint.to_egui(),
+                        global_material.scale,
+                        global_material.rotation,
+                    )
+                },
+            );
+        let [r, g, b, _] = wall_color
+            .to_array()
+            .map(|c| f32::from(c) * EXTERIOR_WALL_SHADE);
+        let exterior_color = Color32::from_rgb(r as u8, g as u8, b as u8);
+        let walls = (rendered_data
+            .wall_triangles
+            .iter()
+            .map(|wall| (wall, wall_color)))
+        .chain((rendered_data.exterior_wall_triangles.iter()).map(|wall| (wall, exterior_color)));
+        for (wall, color) in walls {
             let vertices = wall
                 .vertices
                 .iter()
-                .map(|v| Vertex {
-                    pos: self.world_to_screen_pos(*v),
-                    uv: egui::Pos2::ZERO,
-                    color: WALL_COLOR,
+                .map(|&v| Vertex {
+                    pos: self.world_to_screen_pos(v),
+                    uv: vec2_to_egui_pos(rotate_point_i32(v, wall_rotation) * 0.2 / wall_scale),
+                    color,
                 })
                 .collect();
             painter.add(EShape::mesh(Mesh {
                 indices: wall.indices.clone(),
                 vertices,
-                texture_id: TextureId::Managed(0),
+                texture_id: wall_texture,
             }));
         }
 
@@ -497,6 +931,9 @@ impl HomeFlow {
         // Render lights
         let mut lights_data = Vec::new();
         for room in &mut self.layout.rooms {
+            if !self.device.tags_visible(&room.tags) {
+                continue;
+            }
             for light in &mut room.lights {
                 let points = light.get_points(room.pos, room.size);
                 for point in points {
@@ -505,11 +942,19 @@ impl HomeFlow {
                         let diff = (statef - light.lerped_state).signum() * self.frame_time;
                         light.lerped_state = (light.lerped_state + diff).clamp(0.0, 1.0);
                     }
-                    lights_data.push((point, light.lerped_state));
+                    // Ease the remaining distance as if transitioning across the full range
+                    let remaining = light.lerped_state - statef;
+                    let eased_remaining =
+                        (1.0 - self.device.easing.apply(1.0 - remaining.abs())).clamp(0.0, 1.0);
+                    lights_data.push((
+                        point,
+                        statef + remaining.signum() * eased_remaining,
+                        light.color,
+                    ));
                 }
             }
         }
-        for (light_pos, light_state) in lights_data {
+        for (light_pos, light_state, light_color) in lights_data {
             let (min_opacity, max_opacity) = (0.25, 0.75);
             let (min_distance, max_distance) = (0.5, 2.0);
             let big_distance = 0.5;
@@ -522,7 +967,7 @@ impl HomeFlow {
 
             // Calculate the opacity based on the normalized distance
             let alpha = min_opacity + (max_opacity - min_opacity) * (1.0 - norm_dist);
-            let radius = ((0.05 + 0.05 * norm_dist_big) * self.stored.zoom as f32).max(5.0);
+            let radius = ((0.05 + 0.05 * norm_dist_big) * self.device.zoom as f32).max(5.0);
 
             let mut shape = CircleShape {
                 center: self.world_to_screen_pos(light_pos),
@@ -551,7 +996,7 @@ impl HomeFlow {
                 Color32::from_rgb(100, 100, 100)
             } else {
                 let color_off = Color32::from_rgb(200, 200, 200);
-                let color_on = Color32::from_rgb(255, 255, 50);
+                let color_on = light_color.to_egui();
                 Color32::from_rgb(
                     color_off.r().lerp(color_on.r(), light_state),
                     color_off.g().lerp(color_on.g(), light_state),
@@ -568,6 +1013,17 @@ impl HomeFlow {
             painter.add(shape);
         }
 
+        // Render the presence trail under the current points, fading out with age
+        let trail_window = self.device.presence_trail_seconds;
+        for &(time, pos) in self.presence_trail.iter().filter(|_| trail_window > 0.0) {
+            let recency = (1.0 - (self.time - time) / trail_window).clamp(0.0, 1.0) as f32;
+            painter.circle_filled(
+                self.world_to_screen_pos(pos),
+                0.15 * self.device.zoom as f32,
+                Color32::from_rgb(0, 240, 140).gamma_multiply(PRESENCE_TRAIL_OPACITY * recency),
+            );
+        }
+
         // Render presence points
         let mut presence_points = self.presence_points.clone();
 
@@ -575,46 +1031,74 @@ impl HomeFlow {
         let mut chair_positions = Vec::new();
         for room in &self.layout.rooms {
             for furniture in &room.furniture {
+                let (furniture_pos, rotation) = room.furniture_placement(furniture);
                 if matches!(furniture.furniture_type, FurnitureType::Chair(_)) {
-                    chair_positions.push(room.pos + furniture.pos);
+                    chair_positions.push(furniture_pos);
                 }
                 let rendered_data = furniture.rendered_data.as_ref().unwrap();
                 for child in &rendered_data.children {
                     if matches!(child.furniture_type, FurnitureType::Chair(_)) {
-                        let hover = child.hover_amount.max(0.0);
-                        let pos = room.pos
-                            + furniture.pos
-                            + rotate_point_i32(child.pos, -furniture.rotation)
+                        let hover = self.device.easing.apply(child.hover_amount) * hover_strength;
+                        let pos = furniture_pos
+                            + rotate_point_i32(child.pos, -rotation)
                             + rotate_point_i32(
                                 vec2(hover * 0.15, hover * 0.3),
-                                -(furniture.rotation + child.rotation),
+                                -(rotation + child.rotation),
                             );
                         chair_positions.push(pos);
                     }
                 }
             }
         }
-        for point in &mut presence_points {
+        let mut seated = vec![false; presence_points.len()];
+        for (point, seated) in presence_points.iter_mut().zip(&mut seated) {
             for chair_pos in &chair_positions {
-                if (*point - *chair_pos).length() < 0.4 {
-                    *point = *chair_pos;
+                if (point.pos - *chair_pos).length() < 0.4 {
+                    point.pos = *chair_pos;
+                    *seated = true;
                 }
             }
         }
-        for point in presence_points {
-            painter.circle(
-                self.world_to_screen_pos(point),
-                0.1 * self.stored.zoom as f32,
-                Color32::from_rgb(0, 240, 140).gamma_multiply(0.5),
-                Stroke::new(
-                    0.02 * self.stored.zoom as f32,
-                    Color32::from_rgb(0, 200, 100).gamma_multiply(0.7),
-                ),
+        for (index, (point, seated)) in presence_points.into_iter().zip(seated).enumerate() {
+            let pos = self.world_to_screen_pos(point.pos);
+            // Offset each persons pulse by their name, or their order when unnamed
+            let mut hasher = DefaultHasher::new();
+            match &point.label {
+                Some(label) => label.hash(&mut hasher),
+                None => index.hash(&mut hasher),
+            }
+            let phase = (hasher.finish() % 1000) as f64 / 1000.0 * std::f64::consts::TAU;
+            let pulse =
+                1.0 + PRESENCE_PULSE_AMOUNT * (self.time * PRESENCE_PULSE_SPEED + phase).sin();
+            paint_person(
+                painter,
+                pos,
+                0.1 * self.device.zoom as f32 * pulse as f32,
+                seated,
             );
+            if let Some(label) = point.label {
+                let label_scale = 0.1 * self.device.zoom as f32;
+                let galley = painter.layout_no_wrap(
+                    label,
+                    FontId::proportional(label_scale),
+                    Color32::WHITE,
+                );
+                let rect = egui::Align2::CENTER_TOP
+                    .anchor_size(pos + evec2(0.0, label_scale * 1.5), galley.size());
+                painter.add(EShape::rect_filled(
+                    rect.expand(label_scale * 0.3),
+                    label_scale * 0.5,
+                    Color32::from_rgb(0, 120, 70).gamma_multiply(0.8),
+                ));
+                painter.galley(rect.min, galley, Color32::WHITE);
+            }
         }
 
         // Render sensors
         for room in &self.layout.rooms {
+            if !self.device.tags_visible(&room.tags) {
+                continue;
+            }
             // Render circles for rooms sensors at room center
             let mut sensors = Vec::new();
             for sensor in &room.sensors {
@@ -626,7 +1110,7 @@ impl HomeFlow {
                 }
             }
             for (index, (sensor, value)) in sensors.iter().enumerate() {
-                let sensor_draw_scale = 0.2 * self.stored.zoom as f32;
+                let sensor_draw_scale = 0.2 * self.device.zoom as f32;
 
                 let pos = room.pos
                     + room.sensors_offset
@@ -641,7 +1125,7 @@ impl HomeFlow {
                     Stroke::new(sensor_draw_scale * 0.1, Color32::WHITE),
                 );
                 painter.text(
-                    self.world_to_screen_pos(pos) - (evec2(0.0, 0.1) * self.stored.zoom as f32),
+                    self.world_to_screen_pos(pos) - (evec2(0.0, 0.1) * self.device.zoom as f32),
                     egui::Align2::CENTER_CENTER,
                     sensor.display_name.to_string(),
                     FontId::proportional(sensor_draw_scale * 0.35),
@@ -657,7 +1141,7 @@ impl HomeFlow {
                     Color32::BLACK,
                 );
                 painter.text(
-                    self.world_to_screen_pos(pos) + (evec2(0.0, 0.1) * self.stored.zoom as f32),
+                    self.world_to_screen_pos(pos) + (evec2(0.0, 0.1) * self.device.zoom as f32),
                     egui::Align2::CENTER_CENTER,
                     sensor.unit.to_string(),
                     FontId::proportional(sensor_draw_scale * 0.35),
@@ -667,11 +1151,14 @@ impl HomeFlow {
 
             // Render furniture sensors
             for furniture in &room.furniture {
+                if !self.device.tags_visible(&furniture.tags) {
+                    continue;
+                }
                 let (min_opacity, max_opacity) = (0.05, 0.75);
                 let (min_distance, max_distance) = (0.2, 1.0);
 
                 // Normalize the distance within the range of min_distance to max_distance for opacity
-                let pos = room.pos + furniture.pos;
+                let pos = room.furniture_placement(furniture).0;
                 let mouse_dist = self.mouse_pos_world.distance(pos) as f32;
                 let norm_dist =
                     ((mouse_dist - min_distance) / (max_distance - min_distance)).clamp(0.0, 1.0);
@@ -679,28 +1166,100 @@ impl HomeFlow {
 
                 // Render power draw
                 if !furniture.power_draw_entity.is_empty() {
-                    let power_draw = furniture
-                        .hass_data
-                        .get(&furniture.power_draw_entity)
-                        .and_then(|value| value.parse::<f64>().ok())
-                        .unwrap_or(0.0);
-                    let power_draw_scale = 0.1 * self.stored.zoom as f32;
-
-                    let galley = painter.layout_no_wrap(
-                        format!("⚡ {} W", power_draw.round() as i64).to_string(),
-                        FontId::proportional(power_draw_scale),
-                        Color32::WHITE.gamma_multiply(alpha),
-                    );
-                    let rect = egui::Align2::CENTER_CENTER
-                        .anchor_size(self.world_to_screen_pos(pos), galley.size());
-                    painter.add(EShape::rect_filled(
-                        rect.expand(power_draw_scale * 0.5),
-                        power_draw_scale,
-                        Color32::from_black_alpha((150.0 * alpha).round() as u8),
-                    ));
-                    painter.galley(rect.min, galley, Color32::WHITE);
+                    let power_draw = furniture.power_draw().unwrap_or(0.0);
+                    self.render_power_draw(painter, pos, power_draw, 0.1, alpha);
+                }
+            }
+
+            // Render room power draw subtotal
+            if self.device.show_room_power {
+                if let Some(power_draw) = room.power_draw() {
+                    let pos = room.pos + room.sensors_offset + vec2(0.0, -0.4);
+                    self.render_power_draw(painter, pos, power_draw, 0.15, 0.9);
                 }
             }
         }
     }
+
+    fn render_wall_shadows(&self, painter: &Painter) {
+        let rendered_data = self.layout.rendered_data.as_ref().unwrap();
+        let shadow_offset = vec2(0.01, -0.02);
+
+        let (shadow_color, shadow_triangles) = &rendered_data.wall_shadows.1;
+        for triangles in shadow_triangles {
+            if triangles.vertices.is_empty() {
+                continue;
+            }
+            let vertices = triangles
+                .vertices
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| {
+                    let is_interior = *triangles.inners.get(i).unwrap_or(&false);
+                    Vertex {
+                        pos: self.world_to_screen_pos(v + shadow_offset),
+                        uv: egui::Pos2::ZERO,
+                        color: if is_interior {
+                            *shadow_color
+                        } else {
+                            Color::TRANSPARENT
+                        }
+                        .to_egui(),
+                    }
+                })
+                .collect();
+            painter.add(EShape::mesh(Mesh {
+                indices: triangles.indices.clone(),
+                vertices,
+                texture_id: TextureId::Managed(0),
+            }));
+        }
+    }
+
+    fn render_light_overlay(&mut self, painter: &Painter) {
+        if let Some(light_data) = &self.layout.light_data {
+            // Check if the light data has changed and needs to be reloaded.
+            let needs_reload = self.light_data.as_ref().is_none_or(|&(hash, complete, _)| {
+                (hash, complete) != (light_data.hash, light_data.complete)
+            });
+
+            if needs_reload {
+                let texture = painter.ctx().load_texture(
+                    "lighting".to_string(),
+                    ColorImage::from_rgba_premultiplied(
+                        [
+                            light_data.image_width as usize,
+                            light_data.image_height as usize,
+                        ],
+                        &light_data.image,
+                    ),
+                    TextureOptions::LINEAR,
+                );
+                self.light_data = Some((light_data.hash, light_data.complete, texture));
+            }
+
+            // Render the texture.
+            if let Some((_, _, texture_handle)) = &self.light_data {
+                let vertices = [
+                    vec2(-0.5, -0.5),
+                    vec2(0.5, -0.5),
+                    vec2(0.5, 0.5),
+                    vec2(-0.5, 0.5),
+                ]
+                .iter()
+                .map(|&v| Vertex {
+                    pos: self
+                        .world_to_screen_pos(light_data.image_center + v * light_data.image_size),
+                    uv: egui::pos2(v.x as f32 + 0.5, 1.0 - (v.y as f32 + 0.5)),
+                    color: Color::WHITE.to_egui(),
+                })
+                .collect();
+                painter.add(EShape::mesh(Mesh {
+                    indices: vec![0, 1, 2, 0, 2, 3],
+                    vertices,
+                    texture_id: texture_handle.id(),
+                }));
+            }
+        }
+    }
 }
@@ -0,0 +1,149 @@
+use super::HomeFlow;
+use egui::Context;
+use std::time::Duration;
+
+impl HomeFlow {
+    /// Ask the backend for a capture of the next frame, picked up by `handle_screenshot`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn copy_view_to_clipboard(&self, ctx: &Context) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
+    }
+
+    /// eframe can't capture the canvas on the web, so draw the current view with the exporter
+    /// and hand the browser clipboard a PNG of it
+    #[cfg(target_arch = "wasm32")]
+    pub fn copy_view_to_clipboard(&self, ctx: &Context) {
+        use super::export::{render_png, ExportOptions};
+        use glam::dvec2 as vec2;
+
+        // The exporter draws unrotated, so frame everything the rotated view shows
+        let rect = self.canvas_rect;
+        let corners = [
+            rect.left_top(),
+            rect.right_top(),
+            rect.right_bottom(),
+            rect.left_bottom(),
+        ]
+        .map(|corner| self.screen_to_world(vec2(f64::from(corner.x), f64::from(corner.y))));
+        let min = corners
+            .iter()
+            .fold(corners[0], |min, &corner| min.min(corner));
+        let max = corners
+            .iter()
+            .fold(corners[0], |max, &corner| max.max(corner));
+        let options = ExportOptions {
+            pixels_per_meter: self.device.zoom * f64::from(ctx.pixels_per_point()),
+            include_lighting: !self.edit_mode.enabled,
+            include_operations: self.device.show_operations && !self.edit_mode.enabled,
+            bounds: Some((min, max)),
+            ..ExportOptions::from_device(&self.device)
+        };
+        let image = render_png(&self.layout, &options, None);
+
+        let toasts = self.toasts.clone();
+        let ctx = ctx.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match write_web_clipboard(&image).await {
+                Ok(()) => {
+                    toasts
+                        .lock()
+                        .success("View copied to clipboard")
+                        .duration(Some(Duration::from_secs(2)));
+                }
+                Err(e) => {
+                    log::error!("Failed to copy view: {:?}", e);
+                    toasts
+                        .lock()
+                        .error("Clipboard unavailable, couldn't copy the view")
+                        .duration(Some(Duration::from_secs(3)));
+                }
+            }
+            ctx.request_repaint();
+        });
+    }
+
+    /// Put any captured frame onto the system clipboard as an image, cropped to the canvas
+    pub fn handle_screenshot(&self, ctx: &Context) {
+        let image = ctx.input(|i| {
+            i.events.iter().find_map(|e| match e {
+                egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                _ => None,
+            })
+        });
+        let Some(image) = image else {
+            return;
+        };
+        let image = if self.canvas_rect.is_positive() {
+            image.region(&self.canvas_rect, Some(ctx.pixels_per_point()))
+        } else {
+            (*image).clone()
+        };
+        match write_clipboard_image(&image) {
+            Ok(()) => {
+                self.toasts
+                    .lock()
+                    .success("View copied to clipboard")
+                    .duration(Some(Duration::from_secs(2)));
+            }
+            Err(e) => {
+                log::error!("Failed to copy view: {:?}", e);
+                self.toasts
+                    .lock()
+                    .error("Clipboard unavailable, couldn't copy the view")
+                    .duration(Some(Duration::from_secs(3)));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_clipboard_image(image: &egui::ColorImage) -> anyhow::Result<()> {
+    let bytes = image
+        .pixels
+        .iter()
+        .flat_map(egui::Color32::to_srgba_unmultiplied)
+        .collect::<Vec<_>>();
+    arboard::Clipboard::new()?.set_image(arboard::ImageData {
+        width: image.size[0],
+        height: image.size[1],
+        bytes: bytes.into(),
+    })?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_clipboard_image(_image: &egui::ColorImage) -> anyhow::Result<()> {
+    anyhow::bail!("Screenshots aren't captured on the web")
+}
+
+/// Write the image to the browser clipboard as a PNG `ClipboardItem`
+#[cfg(target_arch = "wasm32")]
+async fn write_web_clipboard(image: &image::RgbaImage) -> anyhow::Result<()> {
+    use eframe::wasm_bindgen::JsValue;
+
+    let js_error = |e: JsValue| anyhow::anyhow!("{e:?}");
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+
+    let bytes = js_sys::Uint8Array::from(png.as_slice());
+    let blob_options = web_sys::BlobPropertyBag::new();
+    blob_options.set_type("image/png");
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(
+        &js_sys::Array::of1(&bytes),
+        &blob_options,
+    )
+    .map_err(js_error)?;
+    let record = js_sys::Object::new();
+    js_sys::Reflect::set(&record, &JsValue::from_str("image/png"), &blob).map_err(js_error)?;
+    let item = web_sys::ClipboardItem::new_with_record_from_str_to_blob_promise(&record)
+        .map_err(js_error)?;
+
+    let clipboard = web_sys::window()
+        .ok_or_else(|| anyhow::anyhow!("No window"))?
+        .navigator()
+        .clipboard();
+    wasm_bindgen_futures::JsFuture::from(clipboard.write(&js_sys::Array::of1(&item)))
+        .await
+        .map_err(js_error)?;
+    Ok(())
+}
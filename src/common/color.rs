@@ -30,6 +30,7 @@ impl Color {
     pub const GREEN: Self = Self::from_rgb(0, 255, 0);
     pub const BLUE: Self = Self::from_rgb(0, 0, 255);
     pub const YELLOW: Self = Self::from_rgb(255, 255, 0);
+    pub const WARM_WHITE: Self = Self::from_rgb(255, 214, 170);
 
     #[inline]
     pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
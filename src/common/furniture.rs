@@ -1,14 +1,17 @@
 use crate::common::{
     color::Color,
-    layout::{DataPoint, GlobalMaterial, Shape, Triangles},
-    shape::{polygons_to_shadows, triangulate_polygon, ShadowsData},
-    utils::{hash_vec2, Material},
+    layout::{DataPoint, GlobalMaterial, Phase, Shape, Triangles},
+    shape::{create_polygons, polygons_to_shadows, triangulate_polygon, Line, ShadowsData},
+    utils::{hash_vec2, rotate_point_i32, segment_distance, Material},
 };
 use ahash::AHashMap;
 use geo_types::MultiPolygon;
 use glam::{dvec2 as vec2, DVec2 as Vec2};
 use serde::{Deserialize, Serialize};
-use std::hash::{Hash, Hasher};
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
 use strum_macros::{Display, EnumIter};
 use uuid::Uuid;
 
@@ -24,7 +27,12 @@ nestify::nest! {
                 #[default]
                 Dining,
                 Office,
-                Sofa(Color),
+                Sofa(Color, #[serde(default)] pub enum SofaShape {
+                    #[default]
+                    Straight,
+                    LShape,
+                    UShape,
+                }),
             }),
             Table(pub enum TableType {
                 #[default]
@@ -63,6 +71,12 @@ nestify::nest! {
                 PresenceBoolean, // If the boolean is true, a presence point is added
             }),
             Radiator,
+            Fireplace,
+            Stairs(pub enum StairType {
+                #[default]
+                Straight,
+                Landing,
+            }),
             #[default]
             Misc,
             AnimatedPiece(
@@ -89,10 +103,25 @@ nestify::nest! {
         pub pos: Vec2,
         pub size: Vec2,
         pub rotation: i32,
+        #[serde(default)]
+        pub phase: Phase,
+        // Furniture in the same room this sits on, pos and rotation are then relative to it
+        #[serde(default)]
+        pub stack_on: Option<Uuid>,
+        // Layers this belongs to, hidden when all of them are hidden
+        #[serde(default)]
+        pub tags: Vec<String>,
+        // Can't be dragged or nudged in edit mode
+        #[serde(default)]
+        pub locked: bool,
 
         pub power_draw_entity: String,
+        // Entity reporting whether a fireplace is lit, such as switch.fireplace
+        #[serde(default)]
+        pub state_entity: String,
         pub misc_sensors: Vec<String>,
-        pub misc_data: AHashMap<String, DataPoint>,
+        // Ordered so the serialised layout is the same every time, undo compares the bytes
+        pub misc_data: BTreeMap<String, DataPoint>,
 
         #[serde(skip)]
         pub hover_amount: f64,
@@ -103,10 +132,25 @@ nestify::nest! {
     }
 }
 
+/// How much internal detail furniture draws, simple hides small parts like pillows and taps
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumIter, Default, Hash, Debug,
+)]
+pub enum DetailLevel {
+    Simple,
+    #[default]
+    Full,
+}
+
 const WOOD: FurnMaterial = FurnMaterial::new(Material::Wood, Color::from_rgb(190, 120, 80));
 const CERAMIC: FurnMaterial = FurnMaterial::new(Material::Empty, Color::from_rgb(230, 220, 200));
 const METAL_DARK: FurnMaterial = FurnMaterial::new(Material::Empty, Color::from_rgb(80, 80, 80));
 
+// Depth of each step going up a flight of stairs
+const TREAD_DEPTH: f64 = 0.25;
+// Table sides closer than this to a wall don't get chairs
+const CHAIR_WALL_CLEARANCE: f64 = 0.3;
+
 impl Furniture {
     pub fn new(
         name: &str,
@@ -125,9 +169,14 @@ impl Furniture {
             pos,
             size,
             rotation,
+            phase: Phase::Existing,
+            stack_on: None,
+            tags: Vec::new(),
+            locked: false,
             power_draw_entity: String::new(),
+            state_entity: String::new(),
             misc_sensors: Vec::new(),
-            misc_data: AHashMap::new(),
+            misc_data: BTreeMap::new(),
             hover_amount: 0.0,
             rendered_data: None,
             hass_data: AHashMap::new(),
@@ -225,10 +274,31 @@ impl Furniture {
         if !self.power_draw_entity.is_empty() {
             sensors.push(self.power_draw_entity.clone());
         }
+        if !self.state_entity.is_empty() {
+            sensors.push(self.state_entity.clone());
+        }
         sensors.extend(self.misc_sensors.iter().cloned());
         sensors
     }
 
+    /// Current power draw in watts, if the power entity reports a usable value
+    pub fn power_draw(&self) -> Option<f64> {
+        if self.power_draw_entity.is_empty() {
+            return None;
+        }
+        self.hass_data
+            .get(&self.power_draw_entity)
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+    }
+
+    /// Whether the state entity reports on, false when there isn't one
+    pub fn is_on(&self) -> bool {
+        self.hass_data
+            .get(&self.state_entity)
+            .is_some_and(|state| state == "on")
+    }
+
     pub fn height_shadow(&self) -> f64 {
         ((f64::from(self.get_render_order()) / 6.0) + 0.5) / 1.5
     }
@@ -246,10 +316,16 @@ impl Furniture {
             FurnitureType::Table(_)
                 | FurnitureType::Chair(ChairType::Dining)
                 | FurnitureType::Storage(_)
+                | FurnitureType::Stairs(_)
                 | FurnitureType::Misc
         )
     }
 
+    /// Tables leave out chairs on sides that are against a wall, so they need the walls nearby
+    pub const fn avoids_walls(&self) -> bool {
+        matches!(self.furniture_type, FurnitureType::Table(_))
+    }
+
     pub const fn has_children_material(&self) -> bool {
         matches!(
             self.furniture_type,
@@ -257,18 +333,19 @@ impl Furniture {
         )
     }
 
-    pub fn contains(&self, room_pos: Vec2, point: Vec2) -> bool {
-        Shape::Rectangle.contains(point, room_pos + self.pos, self.size, self.rotation)
-    }
-
     pub fn render(
         &self,
         primary_material: &GlobalMaterial,
         child_material: &GlobalMaterial,
+        detail: DetailLevel,
+        walls: &[Line],
     ) -> FurnRender {
-        let material = FurnMaterial::new(primary_material.material, primary_material.tint);
+        let material = FurnMaterial {
+            rotation: primary_material.rotation,
+            ..FurnMaterial::new(primary_material.material, primary_material.tint)
+        };
 
-        let polygons = self.polygons(material);
+        let polygons = self.polygons(material, detail);
 
         // Create triangles for each material
         let mut triangles = Vec::new();
@@ -289,6 +366,7 @@ impl Furniture {
             // Use simple shape for shadow unless complex is needed
             let use_simple = match self.furniture_type {
                 FurnitureType::Bed(_) => false,
+                FurnitureType::Chair(ChairType::Sofa(_, shape)) => shape == SofaShape::Straight,
                 FurnitureType::Bathroom(sub_type) => {
                     !matches!(sub_type, BathroomType::Toilet | BathroomType::Sink)
                 }
@@ -304,7 +382,7 @@ impl Furniture {
             (Color::TRANSPARENT, Vec::new())
         };
 
-        let children = self.render_children(child_material);
+        let children = self.render_children(child_material, detail, walls);
 
         FurnRender {
             hash: 0,
@@ -314,16 +392,18 @@ impl Furniture {
         }
     }
 
-    fn polygons(&self, material: FurnMaterial) -> FurniturePolygons {
+    fn polygons(&self, material: FurnMaterial, detail: DetailLevel) -> FurniturePolygons {
         match self.furniture_type {
             FurnitureType::Chair(sub_type) => self.chair_render(material, sub_type),
             FurnitureType::Table(_) => self.table_render(material),
-            FurnitureType::Bed(color) => self.bed_render(color),
+            FurnitureType::Bed(color) => self.bed_render(color, detail),
             FurnitureType::Storage(_) => self.storage_render(material),
             FurnitureType::Rug(color) => self.rug_render(color),
-            FurnitureType::Kitchen(sub_type) => self.kitchen_render(sub_type),
-            FurnitureType::Bathroom(sub_type) => self.bathroom_render(sub_type),
-            FurnitureType::Radiator => self.radiator_render(),
+            FurnitureType::Kitchen(sub_type) => self.kitchen_render(sub_type, detail),
+            FurnitureType::Bathroom(sub_type) => self.bathroom_render(sub_type, detail),
+            FurnitureType::Radiator => self.radiator_render(detail),
+            FurnitureType::Fireplace => self.fireplace_render(detail),
+            FurnitureType::Stairs(sub_type) => self.stairs_render(material, sub_type),
             FurnitureType::Electronic(sub_type) => self.electronic_render(sub_type),
             FurnitureType::Sensor(_) => vec![],
             FurnitureType::AnimatedPiece(sub_type) => self.animated_render(material, sub_type),
@@ -331,24 +411,38 @@ impl Furniture {
         }
     }
 
-    fn render_children(&self, material: &GlobalMaterial) -> Vec<Self> {
+    /// Walls are in this furniture's local space
+    fn render_children(
+        &self,
+        material: &GlobalMaterial,
+        detail: DetailLevel,
+        walls: &[Line],
+    ) -> Vec<Self> {
         let mut children = match self.furniture_type {
-            FurnitureType::Table(sub_type) => self.table_children(sub_type),
+            FurnitureType::Table(sub_type) => self.table_children(sub_type, walls),
             FurnitureType::Storage(sub_type) => self.storage_children(sub_type),
             _ => Vec::new(),
         };
         for child in &mut children {
-            child.rendered_data = Some(child.render(material, material));
+            child.rendered_data = Some(child.render(material, material, detail, &[]));
         }
         children
     }
 
-    fn table_children(&self, sub_type: TableType) -> Vec<Self> {
+    fn table_children(&self, sub_type: TableType, walls: &[Line]) -> Vec<Self> {
         let mut children = Vec::new();
         let chair_size = vec2(0.5, 0.5);
         let chair_push = 0.1;
 
         let mut add_chair = |x: f64, y: f64, rotation: i32| {
+            // Skip chairs where the table edge behind them is up against a wall
+            let table_edge = vec2(x, y) - rotate_point_i32(vec2(0.0, chair_push), -rotation);
+            if walls
+                .iter()
+                .any(|&(a, b)| segment_distance(table_edge, a, b) < CHAIR_WALL_CLEARANCE)
+            {
+                return;
+            }
             children.push(
                 Self::new(
                     "Child Chair",
@@ -451,13 +545,16 @@ impl Furniture {
         let material = match sub_type {
             ChairType::Dining => material,
             ChairType::Office => FurnMaterial::new(Material::Empty, Color::from_rgb(40, 40, 40)),
-            ChairType::Sofa(color) => FurnMaterial::new(Material::Fabric, color),
+            ChairType::Sofa(color, _) => FurnMaterial::new(Material::Fabric, color),
         };
+        if let ChairType::Sofa(_, shape @ (SofaShape::LShape | SofaShape::UShape)) = sub_type {
+            return self.sectional_sofa_render(material, shape);
+        }
 
         polygons.push((material, self.full_shape()));
         let inset = match sub_type {
             ChairType::Office | ChairType::Dining => 0.1,
-            ChairType::Sofa(_) => 0.25,
+            ChairType::Sofa(..) => 0.25,
         };
         if self.size.x > inset * 3.0 && self.size.y > inset * 3.0 {
             polygons.push((
@@ -471,11 +568,59 @@ impl Furniture {
         polygons
     }
 
+    /// Back run along the top edge with a return down the right side, or both sides for a U,
+    /// the frame left showing around the cushions forms the back and arms
+    fn sectional_sofa_render(&self, material: FurnMaterial, shape: SofaShape) -> FurniturePolygons {
+        let half = self.size / 2.0;
+        let inset = 0.25;
+        let depth = (self.size.min_element() * 0.5).min(1.0);
+        let span = |min: Vec2, max: Vec2| rect((min + max) / 2.0, max - min);
+
+        let mut sections = vec![(
+            span(vec2(-half.x, half.y - depth), half),
+            span(
+                vec2(-half.x + inset, half.y - depth),
+                vec2(half.x - inset, half.y - inset),
+            ),
+        )];
+        let mut add_return = |side: f64| {
+            let (outer, inner) = (side * half.x, side * (half.x - depth));
+            let cushion_outer = side * (half.x - inset);
+            sections.push((
+                span(
+                    vec2(outer.min(inner), -half.y),
+                    vec2(outer.max(inner), half.y),
+                ),
+                span(
+                    vec2(cushion_outer.min(inner), -half.y + inset),
+                    vec2(cushion_outer.max(inner), half.y - depth),
+                ),
+            ));
+        };
+        add_return(1.0);
+        if shape == SofaShape::UShape {
+            add_return(-1.0);
+        }
+
+        let cushion_material = material.lighten(0.05).saturate(-0.1);
+        let has_cushions = depth > inset * 1.5;
+        let mut polygons = Vec::with_capacity(sections.len() * 2);
+        for (frame, _) in &sections {
+            polygons.push((material, frame.clone()));
+        }
+        if has_cushions {
+            for (_, cushion) in sections {
+                polygons.push((cushion_material, cushion));
+            }
+        }
+        polygons
+    }
+
     fn table_render(&self, material: FurnMaterial) -> FurniturePolygons {
         fancy_rectangle(Vec2::ZERO, self.size, material, 0.04, 0.0, 0.1)
     }
 
-    fn kitchen_render(&self, sub_type: KitchenType) -> FurniturePolygons {
+    fn kitchen_render(&self, sub_type: KitchenType, detail: DetailLevel) -> FurniturePolygons {
         match sub_type {
             KitchenType::Hob => {
                 let mut polygons = Vec::with_capacity(5);
@@ -483,6 +628,9 @@ impl Furniture {
                     FurnMaterial::new(Material::Empty, Color::from_rgb(80, 80, 80)),
                     self.full_shape(),
                 ));
+                if detail == DetailLevel::Simple {
+                    return polygons;
+                }
                 // Render 4 black circles
                 let black = FurnMaterial::new(Material::Empty, Color::from_rgb(40, 40, 40));
                 let circle_size = self.size.min_element() * 0.3;
@@ -502,9 +650,10 @@ impl Furniture {
         }
     }
 
-    fn bathroom_render(&self, sub_type: BathroomType) -> FurniturePolygons {
+    fn bathroom_render(&self, sub_type: BathroomType, detail: DetailLevel) -> FurniturePolygons {
         let mut polygons = Vec::new();
         let ceramic_light = 0.06;
+        let show_fittings = detail == DetailLevel::Full;
         match sub_type {
             BathroomType::Bath => {
                 polygons.push((CERAMIC, self.full_shape()));
@@ -518,10 +667,12 @@ impl Furniture {
                         ),
                     ));
                     // Tap
-                    polygons.push((
-                        METAL_DARK,
-                        rect(vec2(0.0, self.size.y * 0.5 - 0.15), vec2(0.2, 0.1)),
-                    ));
+                    if show_fittings {
+                        polygons.push((
+                            METAL_DARK,
+                            rect(vec2(0.0, self.size.y * 0.5 - 0.15), vec2(0.2, 0.1)),
+                        ));
+                    }
                 }
                 polygons
             }
@@ -535,10 +686,12 @@ impl Furniture {
                     0.1,
                 ));
                 // Tap
-                polygons.push((
-                    METAL_DARK,
-                    rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.2, 0.1)),
-                ));
+                if show_fittings {
+                    polygons.push((
+                        METAL_DARK,
+                        rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.2, 0.1)),
+                    ));
+                }
                 polygons
             }
             BathroomType::Toilet => {
@@ -565,10 +718,12 @@ impl Furniture {
                     ),
                 ));
                 // Flusher
-                polygons.push((
-                    METAL_DARK,
-                    rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.1, 0.1)),
-                ));
+                if show_fittings {
+                    polygons.push((
+                        METAL_DARK,
+                        rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.1, 0.1)),
+                    ));
+                }
                 polygons
             }
             BathroomType::Sink => {
@@ -595,16 +750,18 @@ impl Furniture {
                     ),
                 ));
                 // Tap
-                polygons.push((
-                    METAL_DARK,
-                    rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.1, 0.1)),
-                ));
+                if show_fittings {
+                    polygons.push((
+                        METAL_DARK,
+                        rect(vec2(0.0, self.size.y * 0.5 - 0.05), vec2(0.1, 0.1)),
+                    ));
+                }
                 polygons
             }
         }
     }
 
-    fn bed_render(&self, color: Color) -> FurniturePolygons {
+    fn bed_render(&self, color: Color, detail: DetailLevel) -> FurniturePolygons {
         let mut polygons = Vec::new();
         let sheet_color = Color::from_rgb(250, 230, 210);
         let pillow_color = Color::from_rgb(255, 255, 255);
@@ -620,7 +777,10 @@ impl Furniture {
         let available_width = self.size.x - pillow_spacing;
         let (pillow_width, pillow_height) = (0.62, 0.45);
         let pillow_full_width = pillow_width + 0.05;
-        let num_pillows = (available_width / pillow_full_width).floor().max(1.0) as usize;
+        let num_pillows = match detail {
+            DetailLevel::Simple => 0,
+            DetailLevel::Full => (available_width / pillow_full_width).floor().max(1.0) as usize,
+        };
         for i in 0..num_pillows {
             let pillow_pos = vec2(
                 pillow_full_width * i as f64 - ((num_pillows - 1) as f64 * pillow_full_width) * 0.5,
@@ -660,13 +820,13 @@ impl Furniture {
         vec![(material, self.full_shape())]
     }
 
-    fn radiator_render(&self) -> FurniturePolygons {
+    fn radiator_render(&self, detail: DetailLevel) -> FurniturePolygons {
         let mut polygons = Vec::new();
         polygons.push((
             FurnMaterial::new(Material::Empty, Color::from_rgb(255, 255, 255)),
             self.full_shape(),
         ));
-        if self.size.x > 0.2 && self.size.y > 0.05 {
+        if detail == DetailLevel::Full && self.size.x > 0.2 && self.size.y > 0.05 {
             let stripe_width = 0.1;
             let total_stripe_width = self.size.x / 2.0 - stripe_width * 0.5;
             let num_stripes = (total_stripe_width / stripe_width).floor() as usize;
@@ -683,6 +843,71 @@ impl Furniture {
         polygons
     }
 
+    /// Stone hearth with the firebox at the back, showing flames while lit
+    fn fireplace_render(&self, detail: DetailLevel) -> FurniturePolygons {
+        let stone = FurnMaterial::new(Material::Granite, Color::from_rgb(170, 160, 150));
+        let firebox_size = vec2(self.size.x * 0.6, self.size.y * 0.5);
+        let firebox_pos = vec2(0.0, (self.size.y - firebox_size.y) * 0.5);
+        let mut polygons = fancy_rectangle(Vec2::ZERO, self.size, stone, 0.1, 0.0, 0.05);
+        polygons.push((METAL_DARK.lighten(-0.2), rect(firebox_pos, firebox_size)));
+        if self.is_on() {
+            polygons.push((
+                FurnMaterial::new(Material::Empty, Color::from_rgb(255, 120, 30)),
+                rect(firebox_pos, firebox_size * 0.8),
+            ));
+            if detail == DetailLevel::Full {
+                polygons.push((
+                    FurnMaterial::new(Material::Empty, Color::from_rgb(255, 210, 80)),
+                    rect(
+                        firebox_pos - vec2(0.0, firebox_size.y * 0.1),
+                        firebox_size * 0.4,
+                    ),
+                ));
+            }
+        }
+        polygons
+    }
+
+    /// Steps climb towards +y, a landing takes a square at the top of the flight
+    fn stairs_render(&self, material: FurnMaterial, sub_type: StairType) -> FurniturePolygons {
+        let mut polygons = vec![(material, self.full_shape())];
+        let landing_depth = match sub_type {
+            StairType::Straight => 0.0,
+            StairType::Landing => self.size.x.min(self.size.y * 0.5),
+        };
+        let flight_length = self.size.y - landing_depth;
+        let num_steps = (flight_length / TREAD_DEPTH).floor().max(1.0) as usize;
+        let adjusted_tread_depth = flight_length / num_steps as f64;
+        let nosing = material.lighten(-0.2);
+        for i in 1..=num_steps {
+            let y_pos = -self.size.y * 0.5 + i as f64 * adjusted_tread_depth;
+            polygons.push((nosing, rect(vec2(0.0, y_pos), vec2(self.size.x, 0.02))));
+        }
+
+        // Arrow pointing up the flight
+        let arrow = METAL_DARK;
+        let head_size = (self.size.x * 0.4).min(0.3);
+        let tail = -self.size.y * 0.5 + adjusted_tread_depth * 0.5;
+        let tip = self.size.y * 0.5 - landing_depth * 0.5 - head_size * 0.25;
+        let shaft_length = (tip - head_size - tail).max(0.0);
+        polygons.push((
+            arrow,
+            rect(
+                vec2(0.0, tail + shaft_length * 0.5),
+                vec2(0.03, shaft_length),
+            ),
+        ));
+        polygons.push((
+            arrow,
+            create_polygons(&[
+                vec2(0.0, tip),
+                vec2(-head_size * 0.5, tip - head_size),
+                vec2(head_size * 0.5, tip - head_size),
+            ]),
+        ));
+        polygons
+    }
+
     fn electronic_render(&self, sub_type: ElectronicType) -> FurniturePolygons {
         match sub_type {
             ElectronicType::Display => {
@@ -774,6 +999,7 @@ impl Hash for Furniture {
         self.material.hash(state);
         self.material_children.hash(state);
         hash_vec2(self.size, state);
+        self.is_on().hash(state);
     }
 }
 
@@ -781,24 +1007,31 @@ impl Hash for Furniture {
 pub struct FurnMaterial {
     pub material: Material,
     pub tint: Color,
+    // Degrees the texture is turned, taken from the global material
+    #[serde(default)]
+    pub rotation: i32,
 }
 
 impl FurnMaterial {
     const fn new(material: Material, tint: Color) -> Self {
-        Self { material, tint }
+        Self {
+            material,
+            tint,
+            rotation: 0,
+        }
     }
 
     fn lighten(self, lighten: f64) -> Self {
         Self {
-            material: self.material,
             tint: self.tint.lighten(lighten),
+            ..self
         }
     }
 
     fn saturate(self, saturate: f64) -> Self {
         Self {
-            material: self.material,
             tint: self.tint.saturate(saturate),
+            ..self
         }
     }
 }
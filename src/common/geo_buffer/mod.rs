@@ -109,6 +109,24 @@
 //! <img src="https://raw.githubusercontent.com/1011-git/geo-buffer/main/assets/ex4.svg" style="padding: 25px 30%;"/>
 //! </details>
 //!
+//! ### Example 5
+//!
+//! The straight skeleton itself is available as line segments, for example to draw the ridges and hips of a roof.
+//!
+//! ```
+//! use geo_buffer::straight_skeleton;
+//! use geo::{Polygon, LineString};
+//!
+//! let p1 = Polygon::new(
+//!     LineString::from(vec![(0., 0.), (4., 0.), (4., 2.), (0., 2.)]), vec![],
+//! );
+//! let edges = straight_skeleton(&p1);
+//!
+//! // Four hips from the corners and the ridge between where they meet
+//! assert_eq!(edges.len(), 5);
+//!
+//! ```
+//!
 //! # Reference
 //!
 //! This is a Rust implementation of this paper[^note1][^note2]. (See also [Notes](#Notes) below.)
@@ -139,8 +157,11 @@ mod vertex_queue;
 
 // Main functions in this module
 
-use geo_types::{MultiPolygon, Polygon};
+use geo::orient::{Direction, Orient};
+use geo::Area;
+use geo_types::{Coord, Line, LineString, MultiPolygon, Polygon};
 use skeleton::Skeleton;
+use util::feq;
 
 /// This function returns the buffered (multi-)polygon of the given polygon. This function creates a miter-joint-like corners around each convex vertex.
 ///
@@ -198,9 +219,162 @@ pub fn buffer_polygon(input_polygon: &Polygon, distance: f64) -> MultiPolygon {
 ///
 /// ```
 pub fn buffer_multi_polygon(input_multi_polygon: &MultiPolygon, distance: f64) -> MultiPolygon {
-    let orientation = distance < 0.;
-    let offset_distance = f64::abs(distance);
-    let skel = Skeleton::skeleton_of_polygon_vector(&input_multi_polygon.0, orientation);
-    let vq = skel.get_vertex_queue(offset_distance);
-    skel.apply_vertex_queue(&vq, offset_distance)
+    skeleton_of_multi_polygon(input_multi_polygon, distance >= 0.).apply(distance)
+}
+
+/// This function returns the straight skeleton of the given multi-polygon. Building the skeleton is the expensive part of
+/// buffering, so when the same input is offset by several distances in one direction it can be built once and then applied
+/// with each distance by [`Skeleton::apply`].
+///
+/// # Arguments
+///
+/// + `input_multi_polygon`: `MultiPolygon` to build the skeleton of.
+/// + `inflate`: whether the skeleton grows outward (for positive distances) or inward (for negative distances).
+///
+/// # Example
+///
+/// ```
+/// use geo_buffer::skeleton_of_multi_polygon;
+/// use geo::{Polygon, MultiPolygon, LineString};
+///
+/// let p1 = Polygon::new(
+///     LineString::from(vec![(0., 0.), (2., 0.), (2., 2.), (0., 2.)]), vec![],
+/// );
+/// let skeleton = skeleton_of_multi_polygon(&MultiPolygon::new(vec![p1]), false);
+/// let mp1 = skeleton.apply(-0.2);
+/// let mp2 = skeleton.apply(-0.4);
+///
+/// ```
+pub fn skeleton_of_multi_polygon(input_multi_polygon: &MultiPolygon, inflate: bool) -> Skeleton {
+    let polygons = input_multi_polygon
+        .0
+        .iter()
+        .filter_map(clean_polygon)
+        .collect();
+    Skeleton::skeleton_of_polygon_vector(&polygons, !inflate)
+}
+
+/// This function returns the straight skeleton of the given polygon as line segments, running from each vertex of the
+/// polygon inward to where the wavefronts meet. It can be used for hip roofs or to find the centerline of a corridor.
+///
+/// Degenerate polygons (fewer than 3 distinct vertices or no area) have an empty skeleton.
+///
+/// # Arguments
+///
+/// + `input_polygon`: `Polygon` to build the skeleton of.
+///
+/// # Example
+///
+/// ```
+/// use geo_buffer::straight_skeleton;
+/// use geo::{Polygon, LineString};
+///
+/// let p1 = Polygon::new(
+///     LineString::from(vec![(0., 0.), (2., 0.), (2., 2.), (0., 2.)]), vec![],
+/// );
+/// let edges = straight_skeleton(&p1);
+///
+/// // Each corner of a square runs diagonally to its center
+/// assert_eq!(edges.len(), 4);
+/// assert!(edges.iter().all(|edge| edge.end == (1., 1.).into()));
+///
+/// ```
+#[allow(dead_code)]
+pub fn straight_skeleton(input_polygon: &Polygon) -> Vec<Line> {
+    skeleton_of_multi_polygon(&MultiPolygon::new(vec![input_polygon.clone()]), false).edges()
+}
+
+/// Prepares a polygon for the skeleton, which expects counter-clockwise exteriors, clockwise holes and no degenerate corners.
+/// Repeated and collinear vertices are removed, and `None` is returned if the exterior has fewer than 3 distinct vertices
+/// or no area, degenerate holes are dropped.
+fn clean_polygon(polygon: &Polygon) -> Option<Polygon> {
+    let exterior = clean_ring(polygon.exterior())?;
+    let interiors = polygon.interiors().iter().filter_map(clean_ring).collect();
+    Some(Polygon::new(exterior, interiors).orient(Direction::Default))
+}
+
+/// Closed ring without repeated vertices or vertices lying on the line between their neighbours, `None` if it has no area.
+fn clean_ring(ring: &LineString) -> Option<LineString> {
+    let mut points: Vec<Coord> = ring.0.clone();
+    if points.len() > 1 && ring.is_closed() {
+        points.pop();
+    }
+    while let Some(index) = (0..points.len()).find(|&i| {
+        let n = points.len();
+        let (prev, cur, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+        let (a, b) = (cur - prev, next - cur);
+        feq(a.x * b.y - a.y * b.x, 0.)
+    }) {
+        points.remove(index);
+    }
+    if points.len() < 3 {
+        return None;
+    }
+    let mut ring = LineString::from(points);
+    ring.close();
+    (!feq(Polygon::new(ring.clone(), vec![]).unsigned_area(), 0.)).then_some(ring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: Vec<(f64, f64)>) -> Polygon {
+        Polygon::new(LineString::from(points), vec![])
+    }
+
+    #[test]
+    fn zero_area_rings_are_rejected() {
+        let flat = polygon(vec![(0., 0.), (1., 0.), (2., 0.), (0., 0.)]);
+        assert!(clean_ring(flat.exterior()).is_none());
+        assert!(clean_polygon(&flat).is_none());
+        assert!(buffer_polygon(&flat, 0.1).0.is_empty());
+
+        let point = polygon(vec![(1., 1.), (1., 1.), (1., 1.)]);
+        assert!(clean_ring(point.exterior()).is_none());
+        assert!(buffer_polygon(&point, -0.1).0.is_empty());
+    }
+
+    #[test]
+    fn collinear_and_repeated_vertices_are_removed() {
+        // A unit square with a repeated corner and a point halfway along its bottom edge
+        let square = polygon(vec![
+            (0., 0.),
+            (0.5, 0.),
+            (1., 0.),
+            (1., 0.),
+            (1., 1.),
+            (0., 1.),
+        ]);
+        let cleaned = clean_polygon(&square).unwrap();
+        assert_eq!(cleaned.exterior().0.len(), 5);
+        assert!(feq(cleaned.unsigned_area(), 1.));
+        assert!(!buffer_polygon(&square, -0.2).0.is_empty());
+    }
+
+    #[test]
+    fn rectangle_skeleton_is_an_x_with_a_ridge() {
+        let rectangle = polygon(vec![(0., 0.), (4., 0.), (4., 2.), (0., 2.)]);
+        let edges = straight_skeleton(&rectangle);
+        let close = |a: Coord, b: Coord| feq(a.x, b.x) && feq(a.y, b.y);
+        let joins = |a: Coord, b: Coord| {
+            edges.iter().any(|edge| {
+                (close(edge.start, a) && close(edge.end, b))
+                    || (close(edge.start, b) && close(edge.end, a))
+            })
+        };
+
+        // Each corner runs to the nearer of two interior nodes, which are joined by the ridge
+        let (left, right) = (Coord { x: 1., y: 1. }, Coord { x: 3., y: 1. });
+        assert_eq!(edges.len(), 5);
+        assert!(joins(left, right));
+        for (corner, node) in [
+            ((0., 0.), left),
+            ((0., 2.), left),
+            ((4., 0.), right),
+            ((4., 2.), right),
+        ] {
+            assert!(joins(corner.into(), node), "{corner:?} to {node:?}");
+        }
+    }
 }
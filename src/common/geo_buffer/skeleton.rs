@@ -3,7 +3,7 @@ use std::fmt;
 
 use geo::winding_order::WindingOrder;
 use geo::{Contains, Winding};
-use geo_types::{LineString, MultiPolygon, Polygon};
+use geo_types::{Line, LineString, MultiPolygon, Polygon};
 
 use super::priority_queue::PriorityQueue;
 use super::util::{feq, fgeq, fleq, fneq, Coordinate, Ray};
@@ -304,9 +304,23 @@ pub struct Skeleton {
     ray_vector: Vec<VertexType>,
     event_queue: Vec<Event>,
     initial_vertex_queue: VertexQueue,
+    orient: bool,
 }
 
 impl Skeleton {
+    /// Offsets the polygon this skeleton was built from by the given distance. The skeleton only
+    /// grows in one direction, so the sign of `distance` must match the one it was built for
+    /// (`+` for an inflating skeleton, `-` for a deflating one).
+    pub fn apply(&self, distance: f64) -> MultiPolygon {
+        debug_assert!(
+            distance == 0. || (distance < 0.) == self.orient,
+            "offset direction doesn't match the skeleton"
+        );
+        let offset_distance = f64::abs(distance);
+        let vq = self.get_vertex_queue(offset_distance);
+        self.apply_vertex_queue(&vq, offset_distance)
+    }
+
     pub fn apply_vertex_queue(
         &self,
         vertex_queue: &VertexQueue,
@@ -355,6 +369,21 @@ impl Skeleton {
         MultiPolygon::new(res)
     }
 
+    /// Returns every edge of the skeleton as a line segment, from each vertex to the vertex it merges into.
+    pub fn edges(&self) -> Vec<Line> {
+        self.ray_vector
+            .iter()
+            .filter_map(|vertex| match vertex {
+                VertexType::Tree { parent, .. } if *parent != usize::MAX => {
+                    let start = vertex.unwrap_location();
+                    let end = self.ray_vector[*parent].unwrap_location();
+                    fneq(start.dist_coord(&end), 0.).then(|| Line::new(start, end))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn get_vertex_queue(&self, time_elapsed: f64) -> VertexQueue {
         let mut ret = self.initial_vertex_queue.clone();
         for e in &self.event_queue {
@@ -715,6 +744,7 @@ impl Skeleton {
             ray_vector: vertex_vector,
             event_queue,
             initial_vertex_queue,
+            orient,
         }
     }
 }
@@ -197,7 +197,7 @@ impl VertexQueue {
         }
     }
 
-    pub const fn iter(&self) -> Iter {
+    pub const fn iter(&self) -> Iter<'_> {
         Iter {
             item: self,
             sv_idx: 0,
@@ -3,17 +3,18 @@ use crate::common::{
     furniture::Furniture,
     shape::{Line, ShadowsData},
     utils::Material,
+    HAState,
 };
 use ahash::AHashMap;
-use geo_types::MultiPolygon;
+use geo_types::{MultiPolygon, Polygon};
 use glam::DVec2 as Vec2;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::hash::Hash;
+use std::{collections::BTreeMap, hash::Hash};
 use strum_macros::{Display, EnumIter};
 use uuid::Uuid;
 
-pub const LAYOUT_VERSION: &str = "0.5";
+pub const LAYOUT_VERSION: &str = "0.6";
 
 nestify::nest! {
     #[derive(Serialize, Deserialize, Clone)]*
@@ -24,20 +25,109 @@ nestify::nest! {
             pub name: String,
             pub material: Material,
             pub tint: Color,
-            #>[derive(Default)]
+            // Multiplier on the texture size and tile spacing
+            #[serde(default = "default_material_scale")]
+            pub scale: f64,
+            // Degrees the texture and tile grid are turned, to run planks along a room
+            #[serde(default)]
+            pub rotation: i32,
             pub tiles: Option<pub struct TileOptions {
                 pub spacing: f64,
+                // Lines drawn between tiles, off for butted tiles
+                #[serde(default = "default_grout")]
+                pub grout: bool,
                 pub grout_width: f64,
                 pub grout_color: Color,
+                // Every other tile takes this tint in a checkerboard
+                #[serde(default)]
+                pub alternate_tint: Option<Color>,
+                // How tiles are laid, patterned tiles are spacing long and half as wide
+                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+                #[serde(default)]
+                pub pattern: pub enum TilePattern {
+                    #[default]
+                    Grid,
+                    Herringbone,
+                    Basketweave,
+                },
             }>,
         }>,
+        // Global material to texture walls with, flat colored when None
+        #[serde(default)]
+        pub wall_material: Option<String>,
+        // Snapshot of states shown when Home Assistant can't be reached
+        #[serde(default)]
+        pub demo_states: Option<HAState>,
+        // Where the home is, for the sun path
+        #>[derive(Default)]
+        #[serde(default)]
+        pub location: pub struct Location {
+            pub latitude: f64,
+            // Compass bearing the plans up direction faces, 0 when up is north
+            pub north_rotation: f64,
+        },
+        // Daylight through windows at each devices sun time, no daylight when None
+        #[serde(default)]
+        pub sun: Option<pub struct SunConfig {
+            pub intensity: f64,
+        }>,
 
+        // Named sets of light states that can be applied together
+        #[serde(default)]
+        pub scenes: Vec<pub struct Scene {
+            pub id: Uuid,
+            pub name: String,
+            // Light entity id -> Target state, ordered so saves don't shuffle it
+            pub lights: BTreeMap<String, u8>,
+        }>,
+
+        // Stacking order, later rooms draw on top and carve into the walls of earlier rooms
         pub rooms: Vec<pub struct Room {
             pub id: Uuid,
             pub name: String,
+            #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+            #[serde(default)]
+            pub room_type: pub enum RoomType {
+                #[default]
+                Other,
+                Bedroom,
+                Bathroom,
+                Kitchen,
+                Living,
+                Dining,
+                Office,
+                Hallway,
+                Storage,
+                Utility,
+            },
             pub material: String,
             pub pos: Vec2,
             pub size: Vec2,
+            /// Primitive the room starts from before operations are applied
+            #[serde(default)]
+            pub base_shape: Shape,
+            /// Drawn outline used instead of the base shape, vertices are relative to the room and scaled by its size
+            #[serde(default)]
+            pub freeform: Option<Vec<Vec2>>,
+            /// Renovation phase, drawn with its own style
+            #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default, Debug)]
+            #[serde(default)]
+            pub phase: pub enum Phase {
+                #[default]
+                Existing,
+                New,
+                #[strum(to_string = "To Remove")]
+                ToRemove,
+            },
+            /// Always drawn fully lit, ignoring the baked lighting
+            #[serde(default)]
+            pub always_lit: bool,
+            // Layers the room belongs to, hidden along with its furniture when all of them are hidden
+            #[serde(default)]
+            pub tags: Vec<String>,
+            // Can't be dragged or nudged in edit mode
+            #[serde(default)]
+            pub locked: bool,
 
             pub operations: Vec<pub struct Operation {
                 pub id: Uuid,
@@ -47,9 +137,12 @@ nestify::nest! {
                     Subtract,
                     AddWall,
                     SubtractWall,
+                    /// Free standing straight wall along the operations length, at any angle
+                    Wall,
                 },
-                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash)]
+                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
                 pub shape: pub enum Shape {
+                    #[default]
                     Rectangle,
                     Circle,
                     Triangle,
@@ -77,11 +170,25 @@ nestify::nest! {
                 pub opening_type: pub enum OpeningType {
                     Door,
                     Window,
+                    DoubleDoor,
+                    SlidingDoor,
+                },
+                // Only used by windows
+                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+                #[serde(default)]
+                pub window_shape: pub enum WindowShape {
+                    #[default]
+                    Flat,
+                    Arched,
+                    Bay,
                 },
                 pub pos: Vec2,
                 pub rotation: i32,
                 pub width: f64,
                 pub flipped: bool,
+                // Doors left open let light through, hovering only swings them for show
+                #[serde(default)]
+                pub open: bool,
 
                 #[serde(skip)]
                 pub open_amount: f64,
@@ -100,9 +207,34 @@ nestify::nest! {
                     pub room_padding: Vec2,
                     pub rows: u8,
                     pub cols: u8,
+                    #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+                    #[serde(default)]
+                    pub arrangement: pub enum LightArrangement {
+                        #[default]
+                        Grid,
+                        Row,
+                        Ring,
+                        Perimeter,
+                    },
                 }>,
                 pub intensity: f64,
                 pub radius: f64,
+                #[serde(default)]
+                pub exclusions: Vec<Zone>,
+                #[serde(default = "default_light_color")]
+                pub color: Color,
+                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+                #[serde(default)]
+                pub kind: pub enum LightKind {
+                    #[default]
+                    Omni,
+                    Spot,
+                },
+                // Spotlights shine along this rotation, spreading over the cone angle in degrees
+                #[serde(default)]
+                pub direction: i32,
+                #[serde(default = "default_cone_angle")]
+                pub cone_angle: f64,
 
                 #[serde(skip)]
                 pub state: u8,
@@ -117,6 +249,14 @@ nestify::nest! {
             pub outline: Option<pub struct Outline {
                 pub thickness: f64,
                 pub color: Color,
+                #>[derive(Copy, PartialEq, Eq, Display, EnumIter, Hash, Default)]
+                #[serde(default)]
+                pub style: pub enum LineStyle {
+                    #[default]
+                    Solid,
+                    Dashed,
+                    Dotted,
+                },
             }>,
 
             pub furniture: Vec<Furniture>,
@@ -128,6 +268,9 @@ nestify::nest! {
                 pub unit: String,
             }>,
             pub sensors_offset: Vec2,
+            // Home Assistant area whose lights and sensors are offered for adding to the room
+            #[serde(default)]
+            pub ha_area_id: Option<String>,
 
             #[serde(skip)]
             pub rendered_data: Option<RoomRender>,
@@ -137,13 +280,30 @@ nestify::nest! {
 
         #[serde(skip)]
         pub rendered_data: Option<HomeRender>,
+        /// Baked darkness overlay, None means the layout is drawn fully lit
         #[serde(skip)]
         pub light_data: Option<LightData>,
     }
 }
 
+const fn default_material_scale() -> f64 {
+    1.0
+}
+
+const fn default_cone_angle() -> f64 {
+    60.0
+}
+
+const fn default_light_color() -> Color {
+    Color::WARM_WHITE
+}
+
+const fn default_grout() -> bool {
+    true
+}
+
 bitflags::bitflags! {
-    #[derive(Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq)]
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
     pub struct Walls: u8 {
         const LEFT   = 0b0001;
         const TOP    = 0b0010;
@@ -155,17 +315,42 @@ bitflags::bitflags! {
 #[derive(Clone)]
 pub struct HomeRender {
     pub hash: u64,
+    // Area covered by the union of every room
+    pub floor_area: f64,
+    // Walls shared between rooms and walls on the outline of the home, which are drawn heavier
     pub wall_triangles: Vec<Triangles>,
+    pub exterior_wall_triangles: Vec<Triangles>,
+    // Faces of every wall, interior and exterior
     pub wall_lines: Vec<Line>,
     pub wall_shadows: (u64, ShadowsData),
 }
 
+/// Which part of a global material a set of floor triangles is drawn with
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum MaterialLayer {
+    Base(String),
+    Grout(String),
+    Alternate(String),
+}
+
 #[derive(Clone)]
 pub struct RoomRender {
     pub hash: u64,
     pub polygons: MultiPolygon,
-    pub material_triangles: IndexMap<String, Vec<Triangles>>,
+    // Freeform outline before operations, built once for the point in room checks
+    pub base_polygon: Option<Polygon>,
+    pub material_triangles: IndexMap<MaterialLayer, Vec<Triangles>>,
     pub wall_polygons: MultiPolygon,
+    pub carved_walls: Option<CarvedWalls>,
+}
+
+/// A rooms walls once the floors of later rooms are cut out of them, with the hash of the room
+/// and the rooms that cut into it so they're only rebuilt when one of those changes
+#[derive(Clone)]
+pub struct CarvedWalls {
+    pub hash: u64,
+    pub polygons: Vec<MultiPolygon>,
+    pub lines: Vec<Line>,
 }
 
 #[derive(Clone)]
@@ -188,6 +373,8 @@ pub enum DataPoint {
 #[derive(Clone)]
 pub struct LightData {
     pub hash: u64,
+    // False while some lights are still waiting to be rendered into it
+    pub complete: bool,
     pub image: Vec<u8>,
     pub image_center: Vec2,
     pub image_size: Vec2,
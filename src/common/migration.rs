@@ -0,0 +1,100 @@
+//! Upgrades for layouts saved by older builds. New fields are backfilled by `#[serde(default)]`
+//! and renamed fields keep their old name as a `#[serde(alias)]`, since both have to happen while
+//! deserializing. Anything that needs the loaded values, such as deriving a new field from an old
+//! one, goes in `MIGRATIONS` and runs once the `Home` is loaded.
+use crate::common::layout::{Home, LAYOUT_VERSION};
+
+type Migration = fn(&mut Home);
+
+/// Ordered upgrades, each brings a layout from the version before it up to its own version
+const MIGRATIONS: &[(&str, Migration)] = &[("0.6", grout_toggle)];
+
+impl Home {
+    /// Apply every migration newer than the layouts version, then stamp it with the current version
+    pub fn migrate(&mut self) {
+        let from = parse_version(&self.version);
+        for (version, migration) in MIGRATIONS {
+            if from < parse_version(version) {
+                log::info!("Migrating layout from {} to {}", self.version, version);
+                migration(self);
+            }
+        }
+        self.version = LAYOUT_VERSION.to_string();
+    }
+}
+
+/// Dotted version as comparable numbers, missing or invalid parts count as 0
+fn parse_version(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or(0))
+        .collect()
+}
+
+/// Grout became a toggle, before then it was hidden by setting its width to zero
+fn grout_toggle(home: &mut Home) {
+    for tiles in home.materials.iter_mut().filter_map(|m| m.tiles.as_mut()) {
+        if tiles.grout_width <= 0.0 {
+            tiles.grout = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Saved before grout was a toggle, when butted tiles had a zero grout width
+    const LAYOUT_0_5: &str = r#"(
+        version: "0.5",
+        materials: [
+            (
+                name: "Butted",
+                material: Marble,
+                tint: ((255, 255, 255, 255)),
+                tiles: Some((
+                    spacing: 0.5,
+                    grout_width: 0.0,
+                    grout_color: ((80, 80, 80, 255)),
+                )),
+            ),
+            (
+                name: "Grouted",
+                material: Marble,
+                tint: ((255, 255, 255, 255)),
+                tiles: Some((
+                    spacing: 0.5,
+                    grout_width: 0.02,
+                    grout_color: ((80, 80, 80, 255)),
+                )),
+            ),
+        ],
+        rooms: [],
+    )"#;
+
+    #[test]
+    fn zero_width_grout_is_turned_off() {
+        let mut home: Home = ron::from_str(LAYOUT_0_5).unwrap();
+        home.migrate();
+
+        assert_eq!(home.version, LAYOUT_VERSION);
+        let grout: Vec<bool> = home
+            .materials
+            .iter()
+            .map(|m| m.tiles.as_ref().unwrap().grout)
+            .collect();
+        assert_eq!(grout, [false, true]);
+    }
+
+    #[test]
+    fn current_layouts_are_left_alone() {
+        let mut home: Home = ron::from_str(LAYOUT_0_5).unwrap();
+        home.version = LAYOUT_VERSION.to_string();
+        home.migrate();
+
+        assert!(home
+            .materials
+            .iter()
+            .all(|m| m.tiles.as_ref().unwrap().grout));
+    }
+}
@@ -7,16 +7,46 @@ pub mod color;
 pub mod furniture;
 pub mod geo_buffer;
 pub mod layout;
+pub mod migration;
+pub mod schedule;
 pub mod shape;
+pub mod sun;
 pub mod template;
 pub mod utils;
+pub mod validate;
 
 // Packet for communication between the server to the client
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct HAState {
     pub lights: AHashMap<String, u8>,
     pub sensors: AHashMap<String, String>,
-    pub presence_points: Vec<DVec2>,
+    pub presence_points: Vec<PresencePoint>,
+    // Bumped by the server on every layout save so clients know to reload
+    #[serde(default)]
+    pub layout_revision: u64,
+}
+
+// Home Assistant area with the entities assigned to it, ids are in the form rooms store them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HAArea {
+    pub area_id: String,
+    pub name: String,
+    pub lights: Vec<HAEntity>,
+    pub sensors: Vec<HAEntity>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HAEntity {
+    pub entity_id: String,
+    pub name: String,
+    pub unit: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PresencePoint {
+    pub pos: DVec2,
+    // Name of the person at this point, if a tracker identifies them
+    pub label: Option<String>,
 }
 
 // Packets for communication between the client to the server
@@ -25,6 +55,13 @@ pub struct TokenPacket {
     pub token: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct GetStatesPacket {
+    pub token: String,
+    // Slow changing room sensors are only sent when requested
+    pub include_slow: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SaveLayoutPacket {
     pub token: String,
@@ -0,0 +1,161 @@
+use crate::common::layout::Room;
+use anyhow::{anyhow, bail, Result};
+use glam::{dvec2 as vec2, DVec2 as Vec2};
+use serde::Deserialize;
+
+// Gap left between rooms that are laid out automatically
+const AUTO_SPACING: f64 = 0.5;
+
+/// One row of a room schedule, positions are the room center and are laid out automatically when omitted
+#[derive(Deserialize)]
+struct ScheduleEntry {
+    name: String,
+    width: f64,
+    height: f64,
+    #[serde(default)]
+    x: Option<f64>,
+    #[serde(default)]
+    y: Option<f64>,
+}
+
+/// Rooms from a JSON array or CSV of name, width, height and optional x, y,
+/// unplaced rooms are set out in a row to the right of origin with their bottoms on it
+pub fn parse_room_schedule(text: &str, material: &str, origin: Vec2) -> Result<Vec<Room>> {
+    let entries = if text.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<ScheduleEntry>>(text)
+            .map_err(|e| anyhow!("Invalid JSON: {}", e))?
+    } else {
+        parse_csv(text)?
+    };
+    if entries.is_empty() {
+        bail!("No rooms found");
+    }
+
+    let mut next_x = origin.x;
+    let mut rooms = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let size = vec2(entry.width, entry.height);
+        if !size.is_finite() || size.min_element() <= 0.0 {
+            bail!("{}: width and height must be positive", entry.name);
+        }
+        let pos = match (entry.x, entry.y) {
+            (Some(x), Some(y)) if x.is_finite() && y.is_finite() => vec2(x, y),
+            (None, None) => {
+                let pos = vec2(next_x + size.x / 2.0, origin.y + size.y / 2.0);
+                next_x += size.x + AUTO_SPACING;
+                pos
+            }
+            _ => bail!("{}: needs both x and y or neither", entry.name),
+        };
+        let name = if entry.name.is_empty() {
+            format!("Room {}", index + 1)
+        } else {
+            entry.name
+        };
+        rooms.push(Room::new(&name, pos, size, material));
+    }
+    Ok(rooms)
+}
+
+/// Comma separated rows, a first row that doesn't have numeric sizes is taken as a header
+fn parse_csv(text: &str) -> Result<Vec<ScheduleEntry>> {
+    let mut entries = Vec::new();
+    let mut first_row = true;
+    for (index, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+        let line_number = index + 1;
+        if std::mem::take(&mut first_row)
+            && fields.get(1).is_some_and(|f| f.parse::<f64>().is_err())
+        {
+            continue;
+        }
+        if !(3..=5).contains(&fields.len()) {
+            bail!("Line {line_number}: expected name, width, height and optionally x, y");
+        }
+        let number = |column: usize, label: &str| -> Result<Option<f64>> {
+            match fields.get(column).filter(|field| !field.is_empty()) {
+                Some(field) => field
+                    .parse::<f64>()
+                    .map(Some)
+                    .map_err(|_| anyhow!("Line {line_number}: {label} '{field}' isn't a number")),
+                None => Ok(None),
+            }
+        };
+        entries.push(ScheduleEntry {
+            name: fields[0].to_owned(),
+            width: number(1, "width")?
+                .ok_or_else(|| anyhow!("Line {line_number}: missing width"))?,
+            height: number(2, "height")?
+                .ok_or_else(|| anyhow!("Line {line_number}: missing height"))?,
+            x: number(3, "x")?,
+            y: number(4, "y")?,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_and_json_rows_become_rooms() {
+        let csv = "name, width, height, x, y\nKitchen, 4, 3, 1, 2\nHall, 2, 5\n";
+        let json = r#"[
+            {"name": "Kitchen", "width": 4, "height": 3, "x": 1, "y": 2},
+            {"name": "Hall", "width": 2, "height": 5}
+        ]"#;
+        for text in [csv, json] {
+            let rooms = parse_room_schedule(text, "Carpet", Vec2::ZERO).unwrap();
+            let rooms: Vec<_> = rooms
+                .iter()
+                .map(|r| (r.name.as_str(), r.pos, r.size))
+                .collect();
+            assert_eq!(
+                rooms,
+                [
+                    ("Kitchen", vec2(1.0, 2.0), vec2(4.0, 3.0)),
+                    ("Hall", vec2(1.0, 2.5), vec2(2.0, 5.0)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn bad_rows_are_rejected() {
+        for text in [
+            "",
+            "name, width, height\n",
+            "Kitchen, 4\n",
+            "Kitchen, 4, 3, 1, 2, 9\n",
+            "Kitchen, 4, three\n",
+            "Kitchen, 4, -3\n",
+            "Kitchen, 4, 3, 1\n",
+            "Kitchen, 4, 3, , 2\n",
+            r#"[{"name": "Kitchen", "width": 4}]"#,
+        ] {
+            assert!(
+                parse_room_schedule(text, "Carpet", Vec2::ZERO).is_err(),
+                "{text:?} parsed"
+            );
+        }
+    }
+
+    #[test]
+    fn unplaced_rooms_sit_in_a_row_on_the_origin() {
+        let origin = vec2(10.0, -4.0);
+        let rooms = parse_room_schedule("A, 2, 2\n, 3, 1\nC, 1, 4\n", "Carpet", origin).unwrap();
+
+        // Bottoms on the origin, left edges spaced by the gap
+        let mut left = origin.x;
+        for room in &rooms {
+            let (min, _) = room.self_bounds();
+            assert!((min - vec2(left, origin.y)).length() < 1e-9);
+            left += room.size.x + AUTO_SPACING;
+        }
+        assert_eq!(rooms[1].name, "Room 2");
+    }
+}
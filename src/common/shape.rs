@@ -1,30 +1,47 @@
 use crate::common::{
     color::Color,
+    furniture::{DetailLevel, FurnRender, Furniture},
     geo_buffer,
     layout::{
-        Action, GlobalMaterial, Home, HomeRender, OpeningType, Operation, Room, RoomRender, Shape,
-        Triangles, Walls, Zone,
+        Action, CarvedWalls, GlobalMaterial, Home, HomeRender, MaterialLayer, Operation, Room,
+        RoomRender, Shape, TilePattern, Triangles, Walls, Zone,
     },
     utils::hash_vec2,
-    utils::{rotate_point_i32, rotate_point_pivot_i32, Material},
+    utils::{rotate_point_i32, rotate_point_pivot_i32, segment_distance, Material},
 };
+use ahash::AHashMap;
 use geo::{
-    triangulate_spade::SpadeTriangulationConfig, BoundingRect, CoordsIter, LinesIter,
-    TriangulateEarcut, TriangulateSpade,
+    triangulate_spade::SpadeTriangulationConfig, Area, BoundingRect, Contains, CoordsIter,
+    Euclidean, Intersects, Length, LinesIter, TriangulateEarcut, TriangulateSpade,
 };
 use geo_types::{Coord, MultiPolygon, Polygon};
 use glam::{dvec2 as vec2, DVec2 as Vec2};
 use indexmap::IndexMap;
+use rayon::prelude::*;
 use std::hash::{DefaultHasher, Hash, Hasher};
+use uuid::Uuid;
 
 pub const WALL_WIDTH: f64 = 0.1;
+// Extra thickness exterior walls get on their outside face
+pub const EXTERIOR_WALL_EXTRA: f64 = 0.05;
+// Wall pieces smaller than this are left over float error from trimming
+const MIN_WALL_AREA: f64 = 1e-6;
+// Deepest chain of stacked furniture followed, also stops stacking loops
+const MAX_STACK_DEPTH: usize = 4;
+// Openings further than this from every wall are left where they are
+pub const OPENING_SNAP_DISTANCE: f64 = 0.25;
 
 impl Home {
-    pub fn render(&mut self, edit_mode: bool) {
+    fn render_hash(&self, edit_mode: bool, detail: DetailLevel) -> u64 {
         let mut hasher = DefaultHasher::new();
         self.hash(&mut hasher);
         edit_mode.hash(&mut hasher);
-        let home_hash = hasher.finish();
+        detail.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn render(&mut self, edit_mode: bool, detail: DetailLevel) {
+        let home_hash = self.render_hash(edit_mode, detail);
         if let Some(rendered_data) = &self.rendered_data {
             if rendered_data.hash == home_hash {
                 return;
@@ -35,94 +52,239 @@ impl Home {
         for room in &mut self.rooms {
             let mut hasher = DefaultHasher::new();
             room.hash(&mut hasher);
+            // Tiles and their rotation come from the global materials, so editing one re-renders
+            // the rooms using it
+            for name in room.floor_materials() {
+                get_global_material(&self.materials, name).hash(&mut hasher);
+            }
             let hash = hasher.finish();
             if room.rendered_data.is_none() || room.rendered_data.as_ref().unwrap().hash != hash {
                 let polygons = room.polygons();
-                let any_add = room.operations.iter().any(|o| o.action == Action::AddWall);
+                let any_add = room
+                    .operations
+                    .iter()
+                    .any(|o| matches!(o.action, Action::AddWall | Action::Wall));
                 let wall_polys = if room.walls.is_empty() && !any_add {
                     EMPTY_MULTI_POLYGON
                 } else {
                     room.wall_polygons(&polygons)
                 };
                 let mat_tris = room.material_polygons(&self.materials);
+                let base_polygon = room
+                    .freeform
+                    .as_ref()
+                    .map(|_| create_polygon(&room.base_vertices()));
                 room.rendered_data = Some(RoomRender {
                     hash,
                     polygons,
+                    base_polygon,
                     material_triangles: mat_tris,
                     wall_polygons: wall_polys,
+                    carved_walls: None,
                 });
             }
         }
 
-        // Process all furniture
-        let materials = &self.materials;
-        for room in &mut self.rooms {
-            for furniture in &mut room.furniture {
-                let mut hasher = DefaultHasher::new();
-                furniture.hash(&mut hasher);
-                let hash = hasher.finish();
-                if furniture.rendered_data.is_none()
-                    || furniture.rendered_data.as_ref().unwrap().hash != hash
-                {
-                    let material = get_global_material(materials, &furniture.material);
-                    let material_child =
-                        get_global_material(materials, &furniture.material_children);
-                    let mut render = furniture.render(&material, &material_child);
-                    render.hash = hash;
-                    furniture.rendered_data = Some(render);
+        // Collect all the rooms together to build up the walls, later rooms cut into the walls of
+        // earlier ones, so each rooms walls only need rebuilding when it or a later room over them changed
+        for index in 0..self.rooms.len() {
+            let (rooms, later_rooms) = self.rooms.split_at_mut(index + 1);
+            let Some(rendered_data) = &mut rooms[index].rendered_data else {
+                continue;
+            };
+            let wall_bounds = rendered_data.wall_polygons.bounding_rect();
+            let cutting: Vec<&RoomRender> = later_rooms
+                .iter()
+                .filter_map(|room| room.rendered_data.as_ref())
+                .filter(|later| {
+                    wall_bounds
+                        .zip(later.polygons.bounding_rect())
+                        .is_some_and(|(walls, floor)| walls.intersects(&floor))
+                })
+                .collect();
+            let mut hasher = DefaultHasher::new();
+            rendered_data.hash.hash(&mut hasher);
+            for later in &cutting {
+                later.hash.hash(&mut hasher);
+            }
+            let hash = hasher.finish();
+            if rendered_data
+                .carved_walls
+                .as_ref()
+                .is_some_and(|carved| carved.hash == hash)
+            {
+                continue;
+            }
+            let polygons: Vec<MultiPolygon> = rendered_data
+                .wall_polygons
+                .iter()
+                .map(|poly| {
+                    cutting.iter().fold(poly.clone().into(), |walls, later| {
+                        difference_polygons(&walls, &later.polygons)
+                    })
+                })
+                .collect();
+            // Wall lines from the polygons
+            let mut lines = Vec::new();
+            for poly in polygons.iter().flatten() {
+                let walls_offset = offset_polygon(poly, -0.025);
+                for line in walls_offset.lines_iter() {
+                    lines.push((coord_to_vec2(line.start), coord_to_vec2(line.end)));
                 }
             }
+            rendered_data.carved_walls = Some(CarvedWalls {
+                hash,
+                polygons,
+                lines,
+            });
+        }
+        let carved_walls = self
+            .rooms
+            .iter()
+            .filter_map(|room| room.rendered_data.as_ref()?.carved_walls.as_ref());
+        let mut wall_polygons: Vec<MultiPolygon> = Vec::new();
+        let mut wall_lines: Vec<Line> = Vec::new();
+        for carved in carved_walls {
+            wall_polygons.extend(carved.polygons.iter().cloned());
+            wall_lines.extend(&carved.lines);
         }
 
-        // Collect all the rooms together to build up the walls
-        let mut wall_polygons = vec![];
-        for room in &self.rooms {
-            if let Some(rendered_data) = &room.rendered_data {
-                for poly in &mut wall_polygons {
-                    *poly = difference_polygons(poly, &rendered_data.polygons);
-                }
-                for poly in &rendered_data.wall_polygons {
-                    wall_polygons.push(poly.clone().into());
+        // Process all furniture, tables get the walls near them so chairs stay out of them.
+        // Pieces whose hash changed are rendered in parallel then assigned back by id
+        let materials = &self.materials;
+        let jobs: Vec<(&Furniture, u64, Vec<Line>)> = self
+            .rooms
+            .iter()
+            .flat_map(|room| {
+                room.furniture.iter().filter_map(|furniture| {
+                    let (pos, rotation) = room.furniture_placement(furniture);
+                    let walls = if furniture.avoids_walls() {
+                        nearby_walls(&wall_lines, furniture, pos, rotation)
+                    } else {
+                        Vec::new()
+                    };
+                    let mut hasher = DefaultHasher::new();
+                    furniture.hash(&mut hasher);
+                    detail.hash(&mut hasher);
+                    get_global_material(materials, &furniture.material).hash(&mut hasher);
+                    get_global_material(materials, &furniture.material_children).hash(&mut hasher);
+                    for &(a, b) in &walls {
+                        hash_vec2(a, &mut hasher);
+                        hash_vec2(b, &mut hasher);
+                    }
+                    let hash = hasher.finish();
+                    let up_to_date = furniture
+                        .rendered_data
+                        .as_ref()
+                        .is_some_and(|rendered_data| rendered_data.hash == hash);
+                    (!up_to_date).then_some((furniture, hash, walls))
+                })
+            })
+            .collect();
+        let renders: Vec<(Uuid, FurnRender)> = jobs
+            .into_par_iter()
+            .map(|(furniture, hash, walls)| {
+                let material = get_global_material(materials, &furniture.material);
+                let material_child = get_global_material(materials, &furniture.material_children);
+                let mut render = furniture.render(&material, &material_child, detail, &walls);
+                render.hash = hash;
+                (furniture.id, render)
+            })
+            .collect();
+        let mut renders: AHashMap<Uuid, FurnRender> = renders.into_iter().collect();
+        for room in &mut self.rooms {
+            for furniture in &mut room.furniture {
+                if let Some(render) = renders.remove(&furniture.id) {
+                    furniture.rendered_data = Some(render);
                 }
             }
         }
 
-        // Gather wall lines from the polygons
-        let mut wall_lines = Vec::new();
-        for multipoly in &wall_polygons {
-            for poly in multipoly {
-                let walls_offset = offset_polygon(poly, -0.025);
-                for line in walls_offset.lines_iter() {
-                    wall_lines.push((coord_to_vec2(line.start), coord_to_vec2(line.end)));
-                }
-            }
+        // Walls along the outline of the whole home are exterior, the rest are shared between rooms,
+        // rooms overlap where later ones carve into earlier ones so union them first
+        let floor = self
+            .rooms
+            .iter()
+            .filter_map(|room| room.rendered_data.as_ref())
+            .fold(EMPTY_MULTI_POLYGON, |floor, rendered_data| {
+                union_polygons(&floor, &rendered_data.polygons)
+            });
+        // Bands are built edge by edge rather than by buffering, so every room outline works
+        let edges: Vec<(Line, Vec2)> = floor
+            .lines_iter()
+            .filter_map(|line| {
+                let (start, end) = (coord_to_vec2(line.start), coord_to_vec2(line.end));
+                let normal = (end - start).normalize_or_zero().perp();
+                let inside = floor.contains(&vec2_to_coord(&((start + end) / 2.0 + normal * 0.01)));
+                (normal != Vec2::ZERO)
+                    .then_some(((start, end), if inside { -normal } else { normal }))
+            })
+            .collect();
+        let band = WALL_WIDTH / 2.0 + 0.01;
+        let boundary = edges
+            .iter()
+            .fold(EMPTY_MULTI_POLYGON, |boundary, &(line, outward)| {
+                union_polygons(&boundary, &line_band(line, outward, -band, band))
+            });
+        let mut exterior_walls = EMPTY_MULTI_POLYGON;
+        for poly in &mut wall_polygons {
+            exterior_walls =
+                union_polygons(&exterior_walls, &intersection_polygons(poly, &boundary));
+            *poly = difference_polygons(poly, &boundary);
         }
+        // Thicken exterior walls outward only, along the edges that have a wall
+        let wall_face = WALL_WIDTH / 2.0;
+        let thickening = edges
+            .iter()
+            .filter(|&&((start, end), outward)| {
+                exterior_walls.contains(&vec2_to_coord(
+                    &((start + end) / 2.0 + outward * wall_face / 2.0),
+                ))
+            })
+            .fold(EMPTY_MULTI_POLYGON, |thickening, &(line, outward)| {
+                let strip = line_band(
+                    line,
+                    outward,
+                    wall_face - 0.001,
+                    wall_face + EXTERIOR_WALL_EXTRA,
+                );
+                union_polygons(&thickening, &strip)
+            });
+        exterior_walls = union_polygons(&exterior_walls, &difference_polygons(&thickening, &floor));
 
-        // Subtract doors
+        // Subtract doors and bay windows
         for room in &self.rooms {
             for opening in &room.openings {
-                if opening.opening_type != OpeningType::Door {
+                if !opening.cuts_wall() {
                     continue;
                 }
                 let opening_polygon = Shape::Rectangle.polygons(
                     room.pos + opening.pos,
-                    vec2(opening.width, WALL_WIDTH * 1.01),
+                    vec2(
+                        opening.width,
+                        (WALL_WIDTH + EXTERIOR_WALL_EXTRA * 2.0) * 1.01,
+                    ),
                     opening.rotation,
                 );
                 for poly in &mut wall_polygons {
                     *poly = difference_polygons(poly, &opening_polygon);
                 }
+                exterior_walls = difference_polygons(&exterior_walls, &opening_polygon);
             }
         }
 
         // Create triangles for each polygon
-        let mut wall_triangles = Vec::new();
-        for multipolygon in &wall_polygons {
-            for polygon in multipolygon {
-                let (indices, vertices) = triangulate_polygon(polygon);
-                wall_triangles.push(Triangles { indices, vertices });
-            }
-        }
+        let triangulate = |multipolygon: &MultiPolygon| {
+            multipolygon
+                .iter()
+                .map(|polygon| {
+                    let (indices, vertices) = triangulate_polygon(polygon);
+                    Triangles { indices, vertices }
+                })
+                .collect::<Vec<_>>()
+        };
+        let wall_triangles = wall_polygons.iter().flat_map(triangulate).collect();
+        let exterior_wall_triangles = triangulate(&exterior_walls);
 
         // If the hashes match, reuse the existing shadows
         let walls_hash = {
@@ -133,7 +295,10 @@ impl Home {
             hasher.finish()
         };
 
-        let compute_shadows = || polygons_to_shadows(wall_polygons.iter().collect(), 1.0);
+        let compute_shadows = || {
+            let all_walls = wall_polygons.iter().chain(std::iter::once(&exterior_walls));
+            polygons_to_shadows(all_walls.collect(), 1.0)
+        };
         let wall_shadows = if edit_mode {
             (walls_hash, (Color::TRANSPARENT, vec![]))
         } else {
@@ -151,64 +316,70 @@ impl Home {
 
         self.rendered_data = Some(HomeRender {
             hash: home_hash,
+            floor_area: floor.unsigned_area(),
             wall_triangles,
+            exterior_wall_triangles,
             wall_lines,
             wall_shadows,
         });
     }
 
     #[cfg(feature = "gui")]
-    pub fn render_lighting(&mut self) {
+    /// Hash of everything the baked lighting depends on, the sun time only matters with daylight
+    pub fn lighting_hash(&self, (day, hour): (u32, f64)) -> u64 {
         let mut hasher = DefaultHasher::new();
         for room in &self.rooms {
             hash_vec2(room.pos, &mut hasher);
             hash_vec2(room.size, &mut hasher);
+            room.always_lit.hash(&mut hasher);
             room.operations.hash(&mut hasher);
             room.walls.hash(&mut hasher);
             room.lights.hash(&mut hasher);
-        }
-        let mut hash = hasher.finish();
-        if let Some(light_data) = &self.light_data {
-            if light_data.hash == hash {
-                return;
+            for opening in &room.openings {
+                opening.hash(&mut hasher);
+                opening.lets_light_through().hash(&mut hasher);
             }
-        }
-
-        let all_walls = &self.rendered_data.as_ref().unwrap().wall_lines;
-
-        let (bounds_min, bounds_max) = self.bounds();
-        let (update_complete, mut light_data) = crate::client::light_render::render_lighting(
-            bounds_min,
-            bounds_max,
-            &self.rooms,
-            all_walls,
-        );
-
-        // Override light data for each light
-        for room in &mut self.rooms {
-            for light in &mut room.lights {
-                if let Some(data) = light_data.remove(&light.id) {
-                    light.light_data = Some(data);
+            for furniture in &room.furniture {
+                if furniture.furniture_type == crate::common::furniture::FurnitureType::Fireplace {
+                    hash_vec2(furniture.pos, &mut hasher);
+                    furniture.is_on().hash(&mut hasher);
                 }
             }
         }
-
-        // Combine each lights contribution into a single image
-        if !update_complete {
-            hash -= 1;
+        self.location.latitude.to_bits().hash(&mut hasher);
+        self.location.north_rotation.to_bits().hash(&mut hasher);
+        if self.sun.is_some() {
+            day.hash(&mut hasher);
+            hour.to_bits().hash(&mut hasher);
         }
-        self.light_data = Some(crate::client::light_render::combine_lighting(
-            bounds_min,
-            bounds_max,
-            &self.rooms,
-            hash,
-        ));
+        self.sun.hash(&mut hasher);
+        hasher.finish()
     }
 
     pub fn get_global_material(&self, string: &str) -> GlobalMaterial {
         get_global_material(&self.materials, string)
     }
 
+    /// Material to draw a floor layer with, grout and alternate tiles keep the texture but take their own tint
+    pub fn layer_material(&self, layer: &MaterialLayer) -> GlobalMaterial {
+        match layer {
+            MaterialLayer::Base(name) => self.get_global_material(name),
+            MaterialLayer::Grout(name) | MaterialLayer::Alternate(name) => {
+                let material = self.get_global_material(name);
+                let tiles = material.tiles.clone().unwrap_or_default();
+                let tint = match layer {
+                    MaterialLayer::Grout(_) => tiles.grout_color,
+                    _ => tiles.alternate_tint.unwrap_or(material.tint),
+                };
+                GlobalMaterial {
+                    tint,
+                    tiles: None,
+                    ..material
+                }
+            }
+        }
+    }
+
     pub fn bounds(&self) -> (Vec2, Vec2) {
         let mut min = Vec2::splat(f64::INFINITY);
         let mut max = Vec2::splat(f64::NEG_INFINITY);
@@ -221,34 +392,171 @@ impl Home {
     }
 }
 
-pub fn get_global_material(materials: &[GlobalMaterial], string: &str) -> GlobalMaterial {
-    let is_grout = string.ends_with("-grout");
-    let search_string = if is_grout {
-        string.trim_end_matches("-grout")
-    } else {
-        string
-    };
+/// Centers and sizes of the tiles in a pattern covering the bounds, in the materials frame, and
+/// whether each is one of the alternately tinted tiles. Tiles are spacing long and half as wide,
+/// the alternate ones are those running vertically
+fn pattern_tiles(
+    pattern: TilePattern,
+    spacing: f64,
+    min: Vec2,
+    max: Vec2,
+) -> Vec<(Vec2, Vec2, bool)> {
+    let width = spacing / 2.0;
+    let horizontal = vec2(spacing, width);
+    let vertical = vec2(width, spacing);
+    let mut tiles = Vec::new();
+    match pattern {
+        TilePattern::Grid => {}
+        TilePattern::Herringbone => {
+            // Pairs of a horizontal tile with a vertical one standing on its left end, stepped
+            // diagonally into a staircase and the staircases repeated alongside each other
+            let step = vec2(width, width);
+            let row = vec2(spacing, -spacing);
+            let lattice = |p: Vec2| vec2((p.x + p.y) / (2.0 * width), (p.x - p.y) / (4.0 * width));
+            let corners = [min, max, vec2(min.x, max.y), vec2(max.x, min.y)].map(lattice);
+            let low = corners.into_iter().fold(Vec2::INFINITY, Vec2::min).floor() - 2.0;
+            let high = corners
+                .into_iter()
+                .fold(Vec2::NEG_INFINITY, Vec2::max)
+                .ceil()
+                + 2.0;
+            for i in low.x as i64..=high.x as i64 {
+                for j in low.y as i64..=high.y as i64 {
+                    let corner = step * i as f64 + row * j as f64;
+                    tiles.push((corner + horizontal / 2.0, horizontal, false));
+                    tiles.push((corner + vec2(0.0, width) + vertical / 2.0, vertical, true));
+                }
+            }
+        }
+        TilePattern::Basketweave => {
+            // Square blocks of two tiles side by side, turning a quarter each block
+            let low = (min / spacing).floor();
+            let high = (max / spacing).ceil();
+            for x in low.x as i64..high.x as i64 {
+                for y in low.y as i64..high.y as i64 {
+                    let corner = vec2(x as f64, y as f64) * spacing;
+                    let upright = (x + y).rem_euclid(2) == 1;
+                    for half in [0.0, 1.0] {
+                        let (offset, size) = if upright {
+                            (vec2(width * (half + 0.5), spacing / 2.0), vertical)
+                        } else {
+                            (vec2(spacing / 2.0, width * (half + 0.5)), horizontal)
+                        };
+                        tiles.push((corner + offset, size, upright));
+                    }
+                }
+            }
+        }
+    }
+    tiles.retain(|(center, size, _)| {
+        let (tile_min, tile_max) = (*center - *size / 2.0, *center + *size / 2.0);
+        tile_min.x < max.x && tile_min.y < max.y && tile_max.x > min.x && tile_max.y > min.y
+    });
+    tiles
+}
 
+pub fn get_global_material(materials: &[GlobalMaterial], string: &str) -> GlobalMaterial {
     materials
         .iter()
-        .find(|&material| material.name == search_string)
+        .find(|&material| material.name == string)
         .map_or_else(
             || GlobalMaterial::new(string, Material::Carpet, Color::WHITE),
-            |material| {
-                if is_grout {
-                    let tiles_colour = material
-                        .tiles
-                        .as_ref()
-                        .map_or(Color::WHITE, |t| t.grout_color);
-                    GlobalMaterial::new(search_string, material.material, tiles_colour)
-                } else {
-                    material.clone()
-                }
-            },
+            Clone::clone,
         )
 }
 
 impl Room {
+    /// Closest point on the rooms outline or its free standing walls and the rotation along that
+    /// wall, None when nothing is within the distance
+    pub fn nearest_wall(
+        &self,
+        polygons: &MultiPolygon,
+        point: Vec2,
+        max_distance: f64,
+    ) -> Option<(Vec2, f64)> {
+        let wall_lines = self
+            .operations
+            .iter()
+            .filter(|operation| operation.action == Action::Wall)
+            .map(|operation| operation.wall_ends(self.pos));
+        let outline_lines = polygons
+            .lines_iter()
+            .map(|line| (coord_to_vec2(line.start), coord_to_vec2(line.end)));
+        nearest_line(outline_lines.chain(wall_lines), point)
+            .filter(|(closest, _)| closest.distance(point) <= max_distance)
+    }
+
+    /// Move an opening onto the nearest wall of the room, a rotation already along that wall is
+    /// kept so doors don't swap which way they open
+    pub fn snap_opening(&mut self, index: usize, max_distance: f64) {
+        let polygons = self
+            .rendered_data
+            .as_ref()
+            .map_or_else(|| self.polygons(), |rendered| rendered.polygons.clone());
+        self.snap_opening_to(&polygons, index, max_distance);
+    }
+
+    /// Move openings near a wall back onto it after the room or its operations change
+    pub fn snap_openings(&mut self) {
+        let polygons = self.polygons();
+        for index in 0..self.openings.len() {
+            self.snap_opening_to(&polygons, index, OPENING_SNAP_DISTANCE);
+        }
+    }
+
+    fn snap_opening_to(&mut self, polygons: &MultiPolygon, index: usize, max_distance: f64) {
+        let point = self.pos + self.openings[index].pos;
+        let Some((closest, wall_rotation)) = self.nearest_wall(polygons, point, max_distance)
+        else {
+            return;
+        };
+        let opening = &mut self.openings[index];
+        // Leave openings already on the wall alone so rounding doesn't show up as an edit
+        if closest.distance(point) > 1e-6 {
+            opening.pos = closest - self.pos;
+        }
+        opening.rotation = along_wall(opening.rotation, wall_rotation);
+    }
+
+    /// Hash of everything the rooms walls are shaped by, relative to the room
+    pub fn walls_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_vec2(self.size, &mut hasher);
+        self.base_shape.hash(&mut hasher);
+        if let Some(freeform) = &self.freeform {
+            for vertex in freeform {
+                hash_vec2(*vertex, &mut hasher);
+            }
+        }
+        self.operations.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Room with a freeform outline from world space points, None if the outline is degenerate or crosses itself
+    pub fn from_freeform(name: &str, points: &[Vec2], material: &str) -> Option<Self> {
+        let polygon = create_polygons(points);
+        if points.len() < 3 || polygon.unsigned_area() < 0.01 || !polygons_are_valid(&polygon) {
+            return None;
+        }
+        let (min, max) = points.iter().fold(
+            (Vec2::splat(f64::INFINITY), Vec2::splat(f64::NEG_INFINITY)),
+            |(min, max), &point| (min.min(point), max.max(point)),
+        );
+        let (pos, size) = ((min + max) / 2.0, max - min);
+        let mut room = Self::new(name, pos, size, material);
+        room.freeform = Some(points.iter().map(|&point| (point - pos) / size).collect());
+        Some(room)
+    }
+
+    /// Outline the room starts from before operations, the freeform outline if drawn or else the base shape
+    pub fn base_vertices(&self) -> Vec<Vec2> {
+        self.freeform.as_ref().map_or_else(
+            || self.base_shape.vertices(self.pos, self.size, 0),
+            |vertices| vertices.iter().map(|&v| self.pos + v * self.size).collect(),
+        )
+    }
+
+    /// Unrotated base shapes and freeform outlines all fill their size, so this holds for any base
     pub fn self_bounds(&self) -> (Vec2, Vec2) {
         (self.pos - self.size / 2.0, self.pos + self.size / 2.0)
     }
@@ -263,6 +571,123 @@ impl Room {
             })
     }
 
+    /// Furniture in this room that the piece is stacked on, None when it isn't or the parent is gone
+    pub fn stack_parent(&self, furniture: &Furniture) -> Option<&Furniture> {
+        let parent_id = furniture.stack_on?;
+        self.furniture
+            .iter()
+            .find(|f| f.id == parent_id && f.id != furniture.id)
+    }
+
+    /// Chain of furniture a piece is stacked on, nearest parent first
+    fn stack_chain<'a>(&'a self, furniture: &'a Furniture) -> Vec<&'a Furniture> {
+        let mut chain = Vec::new();
+        let mut current = furniture;
+        while chain.len() < MAX_STACK_DEPTH {
+            let Some(parent) = self.stack_parent(current) else {
+                break;
+            };
+            chain.push(parent);
+            current = parent;
+        }
+        chain
+    }
+
+    /// World position and rotation of furniture, following any furniture it is stacked on
+    pub fn furniture_placement(&self, furniture: &Furniture) -> (Vec2, i32) {
+        let (pos, rotation) = self.stack_chain(furniture).into_iter().fold(
+            (furniture.pos, furniture.rotation),
+            |(pos, rotation), parent| {
+                (
+                    parent.pos + rotate_point_i32(pos, -parent.rotation),
+                    rotation + parent.rotation,
+                )
+            },
+        );
+        (self.pos + pos, rotation)
+    }
+
+    /// Change what furniture sits on while keeping it in place, ignored if it would stack onto itself
+    pub fn restack_furniture(&mut self, index: usize, stack_on: Option<Uuid>) {
+        let furniture = &self.furniture[index];
+        let parent = stack_on.and_then(|id| self.furniture.iter().find(|f| f.id == id));
+        if parent.is_some_and(|parent| {
+            parent.id == furniture.id
+                || self
+                    .stack_chain(parent)
+                    .iter()
+                    .any(|p| p.id == furniture.id)
+        }) {
+            return;
+        }
+        let (pos, rotation) = self.furniture_placement(furniture);
+        let (origin, parent_rotation) =
+            parent.map_or((self.pos, 0), |parent| self.furniture_placement(parent));
+        let furniture = &mut self.furniture[index];
+        furniture.stack_on = stack_on;
+        furniture.pos = rotate_point_i32(pos - origin, parent_rotation);
+        furniture.rotation = rotation - parent_rotation;
+    }
+
+    /// Remove furniture, anything stacked on it is left where it is
+    pub fn remove_furniture(&mut self, index: usize) {
+        let id = self.furniture[index].id;
+        for stacked in 0..self.furniture.len() {
+            if self.furniture[stacked].stack_on == Some(id) {
+                self.restack_furniture(stacked, None);
+            }
+        }
+        self.furniture.remove(index);
+    }
+
+    /// Render order of furniture, stacked pieces always draw above what they sit on
+    pub fn furniture_render_order(&self, furniture: &Furniture) -> u8 {
+        self.stack_chain(furniture)
+            .into_iter()
+            .rev()
+            .chain(std::iter::once(furniture))
+            .fold(None, |below: Option<u8>, piece| {
+                let order = piece.get_render_order();
+                Some(below.map_or(order, |below| order.max(below + 1)))
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn furniture_contains(&self, furniture: &Furniture, point: Vec2) -> bool {
+        let (pos, rotation) = self.furniture_placement(furniture);
+        Shape::Rectangle.contains(point, pos, furniture.size, rotation)
+    }
+
+    /// Sides of this room that lie along an edge of another room, where the neighbour already provides the wall
+    pub fn shared_walls(&self, others: &[Self]) -> Walls {
+        const TOLERANCE: f64 = 0.01;
+        let (min, max) = self.bounds();
+        let overlaps = |a_min: f64, a_max: f64, b_min: f64, b_max: f64| {
+            a_min.max(b_min) + TOLERANCE < a_max.min(b_max)
+        };
+        let mut shared = Walls::empty();
+        for other in others.iter().filter(|other| other.id != self.id) {
+            let (other_min, other_max) = other.bounds();
+            if overlaps(min.y, max.y, other_min.y, other_max.y) {
+                if (min.x - other_max.x).abs() < TOLERANCE {
+                    shared |= Walls::LEFT;
+                }
+                if (max.x - other_min.x).abs() < TOLERANCE {
+                    shared |= Walls::RIGHT;
+                }
+            }
+            if overlaps(min.x, max.x, other_min.x, other_max.x) {
+                if (max.y - other_min.y).abs() < TOLERANCE {
+                    shared |= Walls::TOP;
+                }
+                if (min.y - other_max.y).abs() < TOLERANCE {
+                    shared |= Walls::BOTTOM;
+                }
+            }
+        }
+        shared
+    }
+
     pub fn contains(&self, point: Vec2) -> bool {
         // Iterate over operations in reverse to give precedence to the last operation
         for operation in self.operations.iter().rev() {
@@ -274,12 +699,23 @@ impl Room {
                 }
             }
         }
-        // If no operations contain the point, check the base rectangle
-        Shape::Rectangle.contains(point, self.pos, self.size, 0)
+        // If no operations contain the point, check the base shape
+        if self.freeform.is_some() {
+            let point = vec2_to_coord(&point);
+            self.rendered_data
+                .as_ref()
+                .and_then(|rendered_data| rendered_data.base_polygon.as_ref())
+                .map_or_else(
+                    || create_polygon(&self.base_vertices()).contains(&point),
+                    |polygon| polygon.contains(&point),
+                )
+        } else {
+            self.base_shape.contains(point, self.pos, self.size, 0)
+        }
     }
 
     pub fn polygons(&self) -> MultiPolygon {
-        let mut polygons = Shape::Rectangle.polygons(self.pos, self.size, 0);
+        let mut polygons = create_polygons(&self.base_vertices());
         for operation in &self.operations {
             let polys = operation.polygons(self.pos);
             match operation.action {
@@ -292,17 +728,59 @@ impl Room {
                 _ => {}
             }
         }
+
+        // Dragged operations can leave self intersecting rings, re-running the boolean union
+        // resolves them into valid polygons before they reach triangulation
+        if !polygons_are_valid(&polygons) {
+            polygons = union_polygons(&polygons, &EMPTY_MULTI_POLYGON);
+            polygons
+                .0
+                .retain(|poly| poly.unsigned_area() > f64::EPSILON);
+        }
         polygons
     }
 
+    /// Floor area after operations, excluding any holes cut by subtractions
+    pub fn area(&self) -> f64 {
+        self.rendered_data.as_ref().map_or_else(
+            || self.polygons().unsigned_area(),
+            |rendered_data| rendered_data.polygons.unsigned_area(),
+        )
+    }
+
+    /// Length of every outline after operations, including the edges around holes
+    pub fn perimeter(&self) -> f64 {
+        let perimeter = |polygons: &MultiPolygon| {
+            polygons
+                .iter()
+                .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+                .map(|ring| ring.length::<Euclidean>())
+                .sum()
+        };
+        self.rendered_data.as_ref().map_or_else(
+            || perimeter(&self.polygons()),
+            |rendered_data| perimeter(&rendered_data.polygons),
+        )
+    }
+
+    /// Names of the materials the floor is laid with
+    fn floor_materials(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.material).chain(
+            self.operations
+                .iter()
+                .filter(|operation| operation.action == Action::Add)
+                .filter_map(|operation| operation.material.as_ref()),
+        )
+    }
+
     pub fn material_polygons(
         &self,
         global_materials: &[GlobalMaterial],
-    ) -> IndexMap<String, Vec<Triangles>> {
+    ) -> IndexMap<MaterialLayer, Vec<Triangles>> {
         let mut polygons = IndexMap::new();
         polygons.insert(
             self.material.clone(),
-            Shape::Rectangle.polygons(self.pos, self.size, 0),
+            create_polygons(&self.base_vertices()),
         );
         for operation in &self.operations {
             match operation.action {
@@ -333,40 +811,128 @@ impl Room {
             }
         }
 
-        // Add grout lines every x units
+        // Add grout lines every x units, and checkerboard alternate tiles between them
         let mut grout_polygons = Vec::new();
         for (material, poly) in &polygons {
             let global_material = global_materials.iter().find(|m| &m.name == material);
             if let Some(global_material) = global_material {
                 if let Some(tile) = &global_material.tiles {
+                    let spacing = tile.spacing * global_material.scale;
                     let mut new_polygons = Vec::new();
-                    let bounds = poly.bounding_rect().unwrap();
-                    let poly_center = coord_to_vec2((bounds.min() + bounds.max()) / 2.0);
+                    let Some(bounds) = poly.bounding_rect() else {
+                        continue;
+                    };
+                    // Lay the grid out in the materials own frame, turned about the polygons center
+                    let rotation = global_material.rotation;
+                    let pivot = coord_to_vec2(bounds.center());
+                    let to_world = |local: Vec2| pivot + rotate_point_i32(local, -rotation);
+                    let (local_min, local_max) = poly.exterior_coords_iter().fold(
+                        (Vec2::INFINITY, Vec2::NEG_INFINITY),
+                        |(min, max), coord| {
+                            let local = rotate_point_i32(coord_to_vec2(coord) - pivot, rotation);
+                            (min.min(local), max.max(local))
+                        },
+                    );
+                    let local_center = (local_min + local_max) / 2.0;
+                    let local_size = local_max - local_min;
+
+                    if tile.pattern != TilePattern::Grid {
+                        let mut alternate_polygons = Vec::new();
+                        for (center, size, alternate) in
+                            pattern_tiles(tile.pattern, spacing, local_min, local_max)
+                        {
+                            if alternate && tile.alternate_tint.is_some() {
+                                let cell =
+                                    Shape::Rectangle.polygons(to_world(center), size, rotation);
+                                alternate_polygons.push(intersection_polygons(&cell, poly));
+                            }
+                            if tile.grout {
+                                // Every shared edge is the left or bottom of some tile
+                                let left = Shape::Rectangle.polygons(
+                                    to_world(center - vec2(size.x / 2.0, 0.0)),
+                                    vec2(tile.grout_width, size.y),
+                                    rotation,
+                                );
+                                let bottom = Shape::Rectangle.polygons(
+                                    to_world(center - vec2(0.0, size.y / 2.0)),
+                                    vec2(size.x, tile.grout_width),
+                                    rotation,
+                                );
+                                new_polygons.push(intersection_polygons(&left, poly));
+                                new_polygons.push(intersection_polygons(&bottom, poly));
+                            }
+                        }
+                        if tile.alternate_tint.is_some() {
+                            grout_polygons.push((
+                                MaterialLayer::Alternate(material.clone()),
+                                alternate_polygons,
+                            ));
+                        }
+                        if tile.grout {
+                            grout_polygons
+                                .push((MaterialLayer::Grout(material.clone()), new_polygons));
+                        }
+                        continue;
+                    }
+
+                    let num_grout_x = (local_size.x / spacing).floor() as usize;
+                    let num_grout_y = (local_size.y / spacing).floor() as usize;
+
+                    if tile.alternate_tint.is_some() {
+                        // Grid lines sit where the grout would, tiles fill the cells between
+                        let origin = local_center
+                            - vec2(num_grout_x as f64 - 1.0, num_grout_y as f64 - 1.0) * spacing
+                                / 2.0;
+                        let min_cell = ((local_min - origin) / spacing).floor();
+                        let max_cell = ((local_max - origin) / spacing).ceil();
+                        let mut alternate_polygons = Vec::new();
+                        for x in min_cell.x as i64..max_cell.x as i64 {
+                            for y in min_cell.y as i64..max_cell.y as i64 {
+                                if (x + y).rem_euclid(2) == 0 {
+                                    continue;
+                                }
+                                let cell_center = to_world(
+                                    origin
+                                        + (vec2(x as f64, y as f64) + Vec2::splat(0.5)) * spacing,
+                                );
+                                let cell = Shape::Rectangle.polygons(
+                                    cell_center,
+                                    Vec2::splat(spacing),
+                                    rotation,
+                                );
+                                alternate_polygons.push(intersection_polygons(&cell, poly));
+                            }
+                        }
+                        grout_polygons.push((
+                            MaterialLayer::Alternate(material.clone()),
+                            alternate_polygons,
+                        ));
+                    }
+                    if !tile.grout {
+                        continue;
+                    }
 
-                    let (startx, endx) = (bounds.min().x, bounds.max().x);
-                    let num_grout_x = ((endx - startx) / tile.spacing).floor() as usize;
                     for i in 0..num_grout_x {
-                        let x_pos = (i as f64 - (num_grout_x - 1) as f64 / 2.0) * tile.spacing;
+                        let x_pos = (i as f64 - (num_grout_x - 1) as f64 / 2.0) * spacing;
                         let line = Shape::Rectangle.polygons(
-                            poly_center + vec2(x_pos, 0.0),
-                            vec2(tile.grout_width, bounds.height()),
-                            0,
+                            to_world(local_center + vec2(x_pos, 0.0)),
+                            vec2(tile.grout_width, local_size.y),
+                            rotation,
                         );
                         new_polygons.push(intersection_polygons(&line, poly));
                     }
 
-                    let num_grout_y = (bounds.height() / tile.spacing).floor() as usize;
                     for i in 0..num_grout_y {
-                        let y_pos = (i as f64 - (num_grout_y - 1) as f64 / 2.0) * tile.spacing;
+                        let y_pos = (i as f64 - (num_grout_y - 1) as f64 / 2.0) * spacing;
                         let line = Shape::Rectangle.polygons(
-                            poly_center + vec2(0.0, y_pos),
-                            vec2(bounds.width(), tile.grout_width),
-                            0,
+                            to_world(local_center + vec2(0.0, y_pos)),
+                            vec2(local_size.x, tile.grout_width),
+                            rotation,
                         );
                         new_polygons.push(intersection_polygons(&line, poly));
                     }
 
-                    grout_polygons.push((format!("{material}-grout"), new_polygons));
+                    grout_polygons.push((MaterialLayer::Grout(material.clone()), new_polygons));
                 }
             }
         }
@@ -378,10 +944,10 @@ impl Room {
                 let (indices, vertices) = triangulate_polygon(polygon);
                 material_triangles.push(Triangles { indices, vertices });
             }
-            triangles.insert(material.clone(), material_triangles);
+            triangles.insert(MaterialLayer::Base(material.clone()), material_triangles);
         }
-        // Add grout triangles
-        for (material, polys) in grout_polygons {
+        // Add grout and alternate tile triangles
+        for (layer, polys) in grout_polygons {
             let mut material_triangles = Vec::new();
             for multipolygon in &polys {
                 for polygon in multipolygon {
@@ -389,13 +955,24 @@ impl Room {
                     material_triangles.push(Triangles { indices, vertices });
                 }
             }
-            triangles.insert(material.clone(), material_triangles);
+            triangles.insert(layer, material_triangles);
         }
 
         triangles
     }
 
+    /// Walls around the room outline plus any free standing wall operations
     pub fn wall_polygons(&self, polygons: &MultiPolygon) -> MultiPolygon {
+        let mut wall_polygons = self.outline_wall_polygons(polygons);
+        for operation in &self.operations {
+            if operation.action == Action::Wall {
+                wall_polygons = union_polygons(&wall_polygons, &operation.polygons(self.pos));
+            }
+        }
+        wall_polygons
+    }
+
+    fn outline_wall_polygons(&self, polygons: &MultiPolygon) -> MultiPolygon {
         let width_half = WALL_WIDTH / 2.0;
 
         // Extract exteriors to ignore inner polygons (holes)
@@ -433,47 +1010,41 @@ impl Room {
         let up = size.y * 0.5 - width_half * 3.0;
         let right = size.x * 0.5 - width_half * 3.0;
 
+        // The plane around the center splits into a wedge per side and a quadrant per corner,
+        // sides without a wall lose their wedge and corners lose their quadrant unless both
+        // neighbouring sides have walls
         let mut subtract_shape = EMPTY_MULTI_POLYGON;
-        for index in 0..4 {
-            if !match index {
-                0 => self.walls.contains(Walls::LEFT),
-                1 => self.walls.contains(Walls::TOP),
-                2 => self.walls.contains(Walls::RIGHT),
-                _ => self.walls.contains(Walls::BOTTOM),
-            } {
-                let pos_neg = vec2(1.0, -1.0);
-                let neg_pos = vec2(-1.0, 1.0);
-                let neg = vec2(-1.0, -1.0);
-                let pos = vec2(1.0, 1.0);
-                let mut vertices = [
-                    vec![Vec2::ZERO, neg_pos, vec2(-4.0, 1.0), vec2(-4.0, -1.0), neg], // Left
-                    vec![Vec2::ZERO, neg_pos, vec2(-1.0, 4.0), vec2(1.0, 4.0), pos],   // Top
-                    vec![Vec2::ZERO, pos, vec2(4.0, 1.0), vec2(4.0, -1.0), pos_neg],   // Right
-                    vec![Vec2::ZERO, neg, vec2(-1.0, -4.0), vec2(1.0, -4.0), pos_neg], // Bottom
-                ];
-                vertices[index]
-                    .iter_mut()
-                    .for_each(|vertex| *vertex = center + *vertex * vec2(right, up));
-                subtract_shape =
-                    union_polygons(&subtract_shape, &create_polygons(&vertices[index]));
-            }
-        }
-        // Add corners
-        let directions = [(-right, Walls::LEFT), (right, Walls::RIGHT)];
-        let verticals = [(up, Walls::TOP), (-up, Walls::BOTTOM)];
-        for (h_mult, h_wall) in &directions {
-            for (v_mult, v_wall) in &verticals {
-                if !self.walls.contains(*h_wall) && !self.walls.contains(*v_wall) {
-                    subtract_shape = union_polygons(
-                        &subtract_shape,
-                        &create_polygons(&[
-                            center + vec2(*h_mult * 0.9, *v_mult * 0.9),
-                            center + vec2(*h_mult * 4.0, *v_mult * 0.9),
-                            center + vec2(*h_mult * 4.0, *v_mult * 4.0),
-                            center + vec2(*h_mult * 0.9, *v_mult * 4.0),
-                        ]),
-                    );
-                }
+        let sides = [
+            (Walls::LEFT, vec2(-1.0, 1.0), vec2(-1.0, -1.0)),
+            (Walls::TOP, vec2(-1.0, 1.0), vec2(1.0, 1.0)),
+            (Walls::RIGHT, vec2(1.0, 1.0), vec2(1.0, -1.0)),
+            (Walls::BOTTOM, vec2(-1.0, -1.0), vec2(1.0, -1.0)),
+        ];
+        for (wall, a, b) in sides {
+            if !self.walls.contains(wall) {
+                // Outward direction of the side, where the two corner directions agree
+                let out = (a + b) / 2.0;
+                let vertices = [Vec2::ZERO, a, a + out * 3.0, b + out * 3.0, b]
+                    .map(|vertex| center + vertex * vec2(right, up));
+                subtract_shape = union_polygons(&subtract_shape, &create_polygons(&vertices));
+            }
+        }
+        let corners = [
+            (vec2(-1.0, 1.0), Walls::LEFT | Walls::TOP),
+            (vec2(1.0, 1.0), Walls::TOP | Walls::RIGHT),
+            (vec2(1.0, -1.0), Walls::RIGHT | Walls::BOTTOM),
+            (vec2(-1.0, -1.0), Walls::BOTTOM | Walls::LEFT),
+        ];
+        for (corner, neighbours) in corners {
+            if !self.walls.contains(neighbours) {
+                let vertices = [
+                    corner,
+                    corner * vec2(4.0, 1.0),
+                    corner * 4.0,
+                    corner * vec2(1.0, 4.0),
+                ]
+                .map(|vertex| center + vertex * vec2(right, up));
+                subtract_shape = union_polygons(&subtract_shape, &create_polygons(&vertices));
             }
         }
 
@@ -485,19 +1056,55 @@ impl Room {
             }
         }
 
-        difference_polygons(&wall_polygons, &subtract_shape)
+        // Trimming along shared edges leaves slivers of float error behind
+        let mut wall_polygons = difference_polygons(&wall_polygons, &subtract_shape);
+        wall_polygons
+            .0
+            .retain(|poly| poly.unsigned_area() > MIN_WALL_AREA);
+        wall_polygons
     }
 }
 
 impl Operation {
+    /// Shape and size actually used, walls are always a wall thick rectangle along their length
+    fn shape_size(&self) -> (Shape, Vec2) {
+        if self.action == Action::Wall {
+            (Shape::Rectangle, vec2(self.size.x, WALL_WIDTH))
+        } else {
+            (self.shape, self.size)
+        }
+    }
+
     pub fn contains(&self, room_pos: Vec2, point: Vec2) -> bool {
-        self.shape
-            .contains(point, room_pos + self.pos, self.size, self.rotation)
+        let (shape, size) = self.shape_size();
+        shape.contains(point, room_pos + self.pos, size, self.rotation)
     }
 
     pub fn vertices(&self, room_pos: Vec2) -> Vec<Vec2> {
-        self.shape
-            .vertices(room_pos + self.pos, self.size, self.rotation)
+        let (shape, size) = self.shape_size();
+        shape.vertices(room_pos + self.pos, size, self.rotation)
+    }
+
+    /// World space start and end of a wall operation
+    pub fn wall_ends(&self, room_pos: Vec2) -> (Vec2, Vec2) {
+        let half = rotate_point_i32(vec2(self.size.x / 2.0, 0.0), -self.rotation);
+        let center = room_pos + self.pos;
+        (center - half, center + half)
+    }
+
+    /// Move one end of a wall operation keeping the other in place, the angle snaps to whole degrees
+    pub fn move_wall_end(&mut self, room_pos: Vec2, fixed: Vec2, moved: Vec2, is_start: bool) {
+        let direction = if is_start {
+            fixed - moved
+        } else {
+            moved - fixed
+        };
+        if direction.length_squared() > f64::EPSILON {
+            self.rotation = -direction.y.atan2(direction.x).to_degrees().round() as i32;
+        }
+        self.size.x = direction.length();
+        let half = rotate_point_i32(vec2(self.size.x / 2.0, 0.0), -self.rotation);
+        self.pos = if is_start { fixed - half } else { fixed + half } - room_pos;
     }
 
     pub fn polygon(&self, room_pos: Vec2) -> Polygon {
@@ -577,6 +1184,31 @@ fn offset_polygons(polygons: &[Polygon], distance: f64) -> MultiPolygon {
         .fold(EMPTY_MULTI_POLYGON, |acc, poly| union_polygons(&acc, &poly))
 }
 
+/// Checks every ring is closed with finite coordinates and that no two non adjacent edges cross
+pub fn polygons_are_valid(polygons: &MultiPolygon) -> bool {
+    for poly in polygons {
+        for ring in std::iter::once(poly.exterior()).chain(poly.interiors()) {
+            if ring.0.len() < 4
+                || !ring.is_closed()
+                || ring.coords().any(|c| !c.x.is_finite() || !c.y.is_finite())
+            {
+                return false;
+            }
+        }
+        let lines: Vec<_> = poly.lines_iter().collect();
+        for (i, a) in lines.iter().enumerate() {
+            for b in &lines[i + 1..] {
+                let shares_point =
+                    a.start == b.start || a.start == b.end || a.end == b.start || a.end == b.end;
+                if !shares_point && a.intersects(b) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
 fn union_polygons(poly_a: &MultiPolygon, poly_b: &MultiPolygon) -> MultiPolygon {
     geo::BooleanOps::union(poly_a, poly_b)
 }
@@ -591,6 +1223,55 @@ fn intersection_polygons(poly_a: &MultiPolygon, poly_b: &MultiPolygon) -> MultiP
 
 pub type Line = (Vec2, Vec2);
 
+/// Closest point on any of the lines and the rotation that lies along that line
+fn nearest_line(lines: impl Iterator<Item = Line>, point: Vec2) -> Option<(Vec2, f64)> {
+    lines
+        .map(|(start, end)| {
+            let direction = end - start;
+            let t = (point - start).dot(direction) / direction.length_squared().max(f64::EPSILON);
+            let closest = start + direction * t.clamp(0.0, 1.0);
+            (closest, -direction.y.atan2(direction.x).to_degrees())
+        })
+        .min_by(|(a, _), (b, _)| a.distance(point).total_cmp(&b.distance(point)))
+}
+
+/// Keep a rotation that already runs along a wall, either way round, otherwise take the walls
+pub fn along_wall(rotation: i32, wall_rotation: f64) -> i32 {
+    if (f64::from(rotation) - wall_rotation)
+        .rem_euclid(180.0)
+        .round()
+        % 180.0
+        == 0.0
+    {
+        rotation
+    } else {
+        wall_rotation.round() as i32
+    }
+}
+
+/// Strip alongside a line between two distances along its outward normal, carried past both ends
+/// by the outer distance so strips on neighbouring edges close their corners
+fn line_band((start, end): Line, outward: Vec2, inner: f64, outer: f64) -> MultiPolygon {
+    let along = (end - start).normalize_or_zero() * outer.abs();
+    create_polygons(&[
+        start - along + outward * inner,
+        end + along + outward * inner,
+        end + along + outward * outer,
+        start - along + outward * outer,
+    ])
+}
+
+/// Wall lines within reach of a piece of furniture and its children, moved into its local space
+fn nearby_walls(wall_lines: &[Line], furniture: &Furniture, pos: Vec2, rotation: i32) -> Vec<Line> {
+    let reach = furniture.size.length() / 2.0 + 1.0;
+    let to_local = |point: Vec2| rotate_point_i32(point - pos, rotation);
+    wall_lines
+        .iter()
+        .filter(|&&(a, b)| segment_distance(pos, a, b) <= reach)
+        .map(|&(a, b)| (to_local(a), to_local(b)))
+        .collect()
+}
+
 pub type ShadowsData = (Color, Vec<ShadowTriangles>);
 
 pub fn polygons_to_shadows(polygons: Vec<&MultiPolygon>, height: f64) -> ShadowsData {
@@ -699,3 +1380,124 @@ impl Shape {
         self.polygon(pos, size, rotation).into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Point;
+
+    #[test]
+    fn walls_only_keep_corners_between_two_walls() {
+        // Points on the middle of each side and just outside each corner of a 4 by 3 room
+        let sides = [
+            (Walls::LEFT, vec2(-2.0, 0.0)),
+            (Walls::TOP, vec2(0.0, 1.5)),
+            (Walls::RIGHT, vec2(2.0, 0.0)),
+            (Walls::BOTTOM, vec2(0.0, -1.5)),
+        ];
+        let corners = [
+            (Walls::LEFT | Walls::TOP, vec2(-2.03, 1.53)),
+            (Walls::TOP | Walls::RIGHT, vec2(2.03, 1.53)),
+            (Walls::RIGHT | Walls::BOTTOM, vec2(2.03, -1.53)),
+            (Walls::BOTTOM | Walls::LEFT, vec2(-2.03, -1.53)),
+        ];
+        let contains =
+            |polygons: &MultiPolygon, point: Vec2| polygons.contains(&Point::new(point.x, point.y));
+
+        for bits in 0..16 {
+            let mut room = Room::new("Room", Vec2::ZERO, vec2(4.0, 3.0), "Carpet");
+            room.walls = Walls::from_bits_truncate(bits);
+            let walls = room.wall_polygons(&room.polygons());
+
+            for (side, point) in sides {
+                assert_eq!(
+                    contains(&walls, point),
+                    room.walls.contains(side),
+                    "{:?} side with walls {:?}",
+                    side,
+                    room.walls
+                );
+            }
+            for (neighbours, point) in corners {
+                assert_eq!(
+                    contains(&walls, point),
+                    room.walls.contains(neighbours),
+                    "{:?} corner with walls {:?}",
+                    neighbours,
+                    room.walls
+                );
+            }
+            // Every piece left belongs to a wall, so no stubs are left on their own
+            for polygon in &walls {
+                assert!(
+                    sides.iter().any(|&(side, point)| room.walls.contains(side)
+                        && polygon.contains(&Point::new(point.x, point.y))),
+                    "stray wall piece with walls {:?}",
+                    room.walls
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn self_intersecting_rooms_are_repaired() {
+        // Outline that crosses itself in the middle like a bow tie, with an operation added
+        // over the crossing
+        let mut room = Room::new("Room", Vec2::ZERO, vec2(4.0, 2.0), "Carpet");
+        room.freeform = Some(vec![
+            vec2(-0.5, -0.5),
+            vec2(0.5, 0.5),
+            vec2(0.5, -0.5),
+            vec2(-0.5, 0.5),
+        ]);
+        room.operations.push(Operation::new(
+            Action::Add,
+            Shape::Rectangle,
+            vec2(0.5, 0.0),
+            vec2(1.0, 0.5),
+        ));
+        assert!(!polygons_are_valid(&create_polygons(&room.base_vertices())));
+
+        let polygons = room.polygons();
+        assert!(polygons_are_valid(&polygons));
+        assert!(polygons.unsigned_area() > 3.0);
+    }
+
+    #[test]
+    fn pattern_tiles_cover_the_bounds_without_overlapping() {
+        let (min, max) = (vec2(-1.3, 0.7), vec2(2.1, 3.4));
+        for pattern in [TilePattern::Herringbone, TilePattern::Basketweave] {
+            let tiles = pattern_tiles(pattern, 0.4, min, max);
+            let rects: Vec<(Vec2, Vec2)> = tiles
+                .iter()
+                .map(|&(center, size, _)| (center - size / 2.0, center + size / 2.0))
+                .collect();
+
+            for (index, &(a_min, a_max)) in rects.iter().enumerate() {
+                for &(b_min, b_max) in &rects[index + 1..] {
+                    let overlap = a_max.min(b_max) - a_min.max(b_min);
+                    assert!(
+                        overlap.min_element() < 1e-9,
+                        "{pattern} tiles overlap by {overlap}"
+                    );
+                }
+            }
+
+            // Sample points off the tile grid, each must land in exactly one tile
+            for i in 0..97 {
+                for j in 0..89 {
+                    let point = min
+                        + (max - min) * vec2(f64::from(i) + 0.37, f64::from(j) + 0.61)
+                            / vec2(97.0, 89.0);
+                    let covering = rects
+                        .iter()
+                        .filter(|(tile_min, tile_max)| {
+                            point.cmpge(*tile_min).all() && point.cmple(*tile_max).all()
+                        })
+                        .count();
+                    assert_eq!(covering, 1, "{pattern} covers {point} {covering} times");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use glam::{dvec2 as vec2, DVec2 as Vec2};
+
+/// Position of the sun in degrees, azimuth clockwise from north and elevation above the horizon
+#[derive(Clone, Copy, Debug)]
+pub struct SunPosition {
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+/// Approximate solar position from latitude, day of the year and local solar time in hours,
+/// accurate to within a degree or so which is plenty for reasoning about which rooms get sun
+pub fn solar_position(latitude: f64, day_of_year: u32, hour: f64) -> SunPosition {
+    let latitude = latitude.to_radians();
+    let declination = (-23.44_f64).to_radians()
+        * (std::f64::consts::TAU / 365.0 * (f64::from(day_of_year) + 10.0)).cos();
+    let hour_angle = (15.0 * (hour - 12.0)).to_radians();
+
+    let elevation = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    let azimuth = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos())
+        .to_degrees()
+        + 180.0;
+
+    SunPosition {
+        azimuth: azimuth.rem_euclid(360.0),
+        elevation: elevation.to_degrees(),
+    }
+}
+
+/// Unit direction in plan space for a compass bearing, given the bearing the plans up direction faces
+pub fn bearing_to_direction(bearing: f64, north_rotation: f64) -> Vec2 {
+    let angle = (bearing - north_rotation).to_radians();
+    vec2(angle.sin(), angle.cos())
+}
@@ -2,11 +2,11 @@ use crate::common::{
     color::Color,
     furniture::{
         BathroomType, ChairType, ElectronicType, Furniture, FurnitureType, KitchenType,
-        RenderOrder, SensorType, StorageType, TableType,
+        RenderOrder, SensorType, SofaShape, StorageType, TableType,
     },
     layout::{
-        Action, DataPoint, GlobalMaterial, Home, LightType, Operation, Outline, Room, Sensor,
-        Shape, Walls, Zone, LAYOUT_VERSION,
+        Action, DataPoint, GlobalMaterial, Home, LightType, Location, Operation, Outline, Room,
+        RoomType, Sensor, Shape, Walls, Zone, LAYOUT_VERSION,
     },
     utils::Material,
 };
@@ -36,8 +36,14 @@ pub fn default() -> Home {
             )
             .tiles(0.4, 0.02, Color::from_rgba(60, 60, 60, 200)),
         ],
+        wall_material: None,
+        demo_states: None,
+        location: Location::default(),
+        sun: None,
+        scenes: Vec::new(),
         rooms: vec![
             Room::new("Hall", vec2(1.35, 0.5), vec2(4.5, 1.10), "Carpet")
+                .room_type(RoomType::Hallway)
                 .set_walls(Walls::TOP)
                 .add_material(vec2(-1.7, 1.55), vec2(1.1, 2.0), "Wood")
                 .door_flipped(vec2(-1.7, 2.55), 0)
@@ -71,6 +77,7 @@ pub fn default() -> Home {
                     .add_sensors(&["binary_sensor.hall_parasoll_door_sensor_opening"]),
                 ),
             Room::new("Lounge", vec2(-2.75, -1.4), vec2(6.1, 2.7), "Carpet")
+                .room_type(RoomType::Living)
                 .set_walls(Walls::LEFT | Walls::BOTTOM)
                 .operation(Operation::new(
                     Action::SubtractWall,
@@ -145,7 +152,10 @@ pub fn default() -> Home {
                 )
                 .furniture(Furniture::new(
                     "Kivik 3 Seater Sofa",
-                    FurnitureType::Chair(ChairType::Sofa(Color::from_rgb(234, 210, 168))),
+                    FurnitureType::Chair(ChairType::Sofa(
+                        Color::from_rgb(234, 210, 168),
+                        SofaShape::Straight,
+                    )),
                     vec2(-2.525, 0.0),
                     vec2(2.3, 0.95),
                     -90,
@@ -272,6 +282,7 @@ pub fn default() -> Home {
                     ),
                 ]),
             Room::new("Kitchen", vec2(-4.2, 1.5), vec2(3.2, 3.1), "MarbleTiles")
+                .room_type(RoomType::Kitchen)
                 .set_walls(Walls::LEFT | Walls::TOP)
                 .add(vec2(1.65, 0.45), vec2(0.3, 2.2))
                 .subtract(vec2(1.55, -1.15), vec2(0.5, 1.0))
@@ -382,10 +393,13 @@ pub fn default() -> Home {
                 ])
                 .sensor_offset(vec2(0.1, -0.5)),
             Room::new("Storage1", vec2(-1.65, 2.5), vec2(1.5, 1.1), "Carpet")
+                .room_type(RoomType::Storage)
                 .door(vec2(0.75, 0.0), -90),
             Room::new("Storage2", vec2(-1.65, 1.4), vec2(1.5, 1.1), "Carpet")
+                .room_type(RoomType::Storage)
                 .door(vec2(0.75, 0.0), -90),
             Room::new("Bedroom", vec2(3.85, -0.95), vec2(3.9, 3.6), "Carpet")
+                .room_type(RoomType::Bedroom)
                 .subtract(vec2(-1.1, 1.4), vec2(1.7, 1.0))
                 .door(vec2(-0.25, 1.35), -90)
                 .window(vec2(0.0, -1.8), 0)
@@ -453,6 +467,7 @@ pub fn default() -> Home {
                 .add_sensors(&[Sensor::new("tower_fan_temperature", "TMP", "°C")])
                 .sensor_offset(vec2(0.0, -0.4)),
             Room::new("Ensuite", vec2(1.1, -1.4), vec2(1.6, 2.7), "GraniteTiles")
+                .room_type(RoomType::Bathroom)
                 .door(vec2(0.8, -0.85), 90)
                 .window(vec2(0.0, -1.35), 0)
                 .light("Ensuite Downlight", 0.0, -0.4)
@@ -486,6 +501,7 @@ pub fn default() -> Home {
                     0,
                 )),
             Room::new("Boiler Room", vec2(1.5, -0.55), vec2(0.8, 1.0), "Carpet")
+                .room_type(RoomType::Utility)
                 .door_width(vec2(0.0, 0.5), 180, 0.6)
                 .furniture(Furniture::new_materials(
                     "Boiler",
@@ -496,6 +512,7 @@ pub fn default() -> Home {
                     "MetalDark",
                 )),
             Room::new("Spare Room", vec2(4.2, 1.95), vec2(3.2, 2.2), "Carpet")
+                .room_type(RoomType::Bedroom)
                 .subtract(vec2(-1.1, -1.4), vec2(1.0, 1.0))
                 .door(vec2(-1.1, -0.9), 180)
                 .window(vec2(1.6, 0.0), -90)
@@ -515,6 +532,7 @@ pub fn default() -> Home {
                     90,
                 )),
             Room::new("Bathroom", vec2(1.4, 2.05), vec2(2.4, 2.0), "GraniteTiles")
+                .room_type(RoomType::Bathroom)
                 .door_flipped(vec2(0.7, -1.0), 180)
                 .light_center("Bathroom Downlight")
                 .light("Bathroom Shower Downlight", 0.85, 0.55)
@@ -2,8 +2,9 @@ use crate::common::{
     color::Color,
     furniture::{self, Furniture, FurnitureType},
     layout::{
-        Action, GlobalMaterial, Home, Light, LightType, MultiLight, Opening, OpeningType,
-        Operation, Outline, Room, Sensor, Shape, TileOptions, Walls, Zone,
+        Action, GlobalMaterial, Home, Light, LightArrangement, LightKind, LightType, LineStyle,
+        Location, MultiLight, Opening, OpeningType, Operation, Outline, Phase, Room, RoomType,
+        Sensor, Shape, SunConfig, TileOptions, TilePattern, Walls, WindowShape, Zone,
     },
 };
 use ahash::AHashMap;
@@ -52,6 +53,13 @@ pub fn rotate_point_i32(point: Vec2, angle: i32) -> Vec2 {
     rotate_point(point, f64::from(angle))
 }
 
+/// Shortest distance from a point to the line segment between a and b
+pub fn segment_distance(point: Vec2, a: Vec2, b: Vec2) -> f64 {
+    let ab = b - a;
+    let t = ((point - a).dot(ab) / ab.length_squared().max(f64::EPSILON)).clamp(0.0, 1.0);
+    point.distance(a + ab * t)
+}
+
 pub fn rotate_point_pivot(point: Vec2, pivot: Vec2, angle: f64) -> Vec2 {
     let cos_theta = angle.to_radians().cos();
     let sin_theta = angle.to_radians().sin();
@@ -71,11 +79,75 @@ impl Home {
         Self {
             version: String::new(),
             materials: Vec::new(),
+            wall_material: None,
+            demo_states: None,
+            location: Location {
+                latitude: 0.0,
+                north_rotation: 0.0,
+            },
+            sun: None,
+            scenes: Vec::new(),
             rooms: Vec::new(),
             rendered_data: None,
             light_data: None,
         }
     }
+
+    /// Set the floor material of every room of the given type, returns the number of rooms changed
+    pub fn apply_material_to_type(&mut self, room_type: RoomType, material: &str) -> usize {
+        let mut changed = 0;
+        for room in &mut self.rooms {
+            if room.room_type == room_type && room.material != material {
+                material.clone_into(&mut room.material);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Point every use of a material at another one, returns the number of references changed
+    pub fn replace_material(&mut self, from: &str, to: &str) -> usize {
+        let mut changed = 0;
+        let mut replace = |material: &mut String| {
+            if material == from {
+                to.clone_into(material);
+                changed += 1;
+            }
+        };
+        if let Some(wall_material) = &mut self.wall_material {
+            replace(wall_material);
+        }
+        for room in &mut self.rooms {
+            replace(&mut room.material);
+            for operation in &mut room.operations {
+                if let Some(material) = &mut operation.material {
+                    replace(material);
+                }
+            }
+            for furniture in &mut room.furniture {
+                replace(&mut furniture.material);
+                replace(&mut furniture.material_children);
+            }
+        }
+        changed
+    }
+
+    /// Total power draw across all furniture with a power entity, None if nothing reports
+    pub fn total_power_draw(&self) -> Option<f64> {
+        self.rooms
+            .iter()
+            .filter_map(Room::power_draw)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Whether two layouts hold the same saved data, every map in a layout is ordered so equal
+    /// layouts serialise to equal bytes however they were loaded
+    pub fn same_layout(&self, other: &Self) -> bool {
+        match (bincode::serialize(self), bincode::serialize(other)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 impl Hash for Home {
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -90,9 +162,16 @@ impl Room {
         Self {
             id: Uuid::new_v4(),
             name: name.to_owned(),
+            room_type: RoomType::Other,
             material: material.to_owned(),
             pos,
             size,
+            base_shape: Shape::Rectangle,
+            freeform: None,
+            phase: Phase::Existing,
+            always_lit: false,
+            tags: Vec::new(),
+            locked: false,
             walls: Walls::all(),
             operations: Vec::new(),
             zones: Vec::new(),
@@ -101,16 +180,63 @@ impl Room {
             furniture: Vec::new(),
             sensors: Vec::new(),
             sensors_offset: Vec2::ZERO,
+            ha_area_id: None,
             outline: None,
             rendered_data: None,
             hass_data: AHashMap::new(),
         }
     }
 
+    /// Give the room and everything inside it fresh ids, so a copy doesn't share any
+    pub fn regenerate_ids(&mut self) {
+        self.id = Uuid::new_v4();
+        for operation in &mut self.operations {
+            operation.id = Uuid::new_v4();
+        }
+        for zone in &mut self.zones {
+            zone.id = Uuid::new_v4();
+        }
+        for opening in &mut self.openings {
+            opening.id = Uuid::new_v4();
+        }
+        for light in &mut self.lights {
+            light.id = Uuid::new_v4();
+            for exclusion in &mut light.exclusions {
+                exclusion.id = Uuid::new_v4();
+            }
+        }
+        // Keep stacked furniture on the copies of what it sat on
+        let new_ids: AHashMap<Uuid, Uuid> = self
+            .furniture
+            .iter()
+            .map(|furniture| (furniture.id, Uuid::new_v4()))
+            .collect();
+        for furniture in &mut self.furniture {
+            furniture.id = new_ids[&furniture.id];
+            furniture.stack_on = furniture.stack_on.and_then(|id| new_ids.get(&id).copied());
+        }
+        for sensor in &mut self.sensors {
+            sensor.id = Uuid::new_v4();
+        }
+    }
+
+    /// Power draw of the furniture in this room, None if nothing reports
+    pub fn power_draw(&self) -> Option<f64> {
+        self.furniture
+            .iter()
+            .filter_map(Furniture::power_draw)
+            .reduce(|a, b| a + b)
+    }
+
     pub fn default() -> Self {
         Self::new("New Room", Vec2::ZERO, vec2(1.0, 1.0), "")
     }
 
+    pub const fn room_type(mut self, room_type: RoomType) -> Self {
+        self.room_type = room_type;
+        self
+    }
+
     pub const fn outline(mut self, outline: Outline) -> Self {
         self.outline = Some(outline);
         self
@@ -170,6 +296,11 @@ impl Room {
                 multi: None,
                 intensity,
                 radius,
+                exclusions: Vec::new(),
+                color: Color::WARM_WHITE,
+                kind: LightKind::Omni,
+                direction: 0,
+                cone_angle: 60.0,
                 state: 0,
                 lerped_state: 0.0,
                 light_data: None,
@@ -271,6 +402,12 @@ impl Hash for Room {
         self.material.hash(state);
         hash_vec2(self.pos, state);
         hash_vec2(self.size, state);
+        self.base_shape.hash(state);
+        if let Some(freeform) = &self.freeform {
+            for vertex in freeform {
+                hash_vec2(*vertex, state);
+            }
+        }
         self.operations.hash(state);
         self.walls.hash(state);
         self.openings.hash(state);
@@ -294,15 +431,24 @@ impl Sensor {
     }
 }
 
+impl OpeningType {
+    /// Doors of any kind cut a gap through the wall and open when approached
+    pub const fn is_door(self) -> bool {
+        matches!(self, Self::Door | Self::DoubleDoor | Self::SlidingDoor)
+    }
+}
+
 impl Opening {
     pub fn new(opening_type: OpeningType, pos: Vec2, rotation: i32) -> Self {
         Self {
             id: Uuid::new_v4(),
             opening_type,
+            window_shape: WindowShape::Flat,
             pos,
             rotation,
             width: 0.8,
             flipped: false,
+            open: false,
             open_amount: 0.0,
         }
     }
@@ -320,10 +466,36 @@ impl Opening {
         self.flipped = !self.flipped;
         self
     }
+
+    /// World space ends of the opening along its wall
+    pub fn span(&self, room_pos: Vec2) -> (Vec2, Vec2) {
+        let direction = rotate_point_i32(vec2(1.0, 0.0), -self.rotation);
+        let center = room_pos + self.pos;
+        (
+            center + direction * self.width / 2.0,
+            center - direction * self.width / 2.0,
+        )
+    }
+
+    /// Doors and bay windows replace the wall across their width
+    pub fn cuts_wall(&self) -> bool {
+        self.opening_type.is_door()
+            || (self.opening_type == OpeningType::Window && self.window_shape == WindowShape::Bay)
+    }
+
+    /// Windows always let light through, doors only when left open, the hover animation is
+    /// ignored so it doesn't relight the home
+    pub const fn lets_light_through(&self) -> bool {
+        match self.opening_type {
+            OpeningType::Window => true,
+            _ => self.open,
+        }
+    }
 }
 impl Hash for Opening {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.opening_type.hash(state);
+        self.window_shape.hash(state);
         hash_vec2(self.pos, state);
         self.rotation.hash(state);
         self.width.to_bits().hash(state);
@@ -341,6 +513,11 @@ impl Light {
             multi: None,
             intensity: 2.0,
             radius: 0.2,
+            exclusions: Vec::new(),
+            color: Color::WARM_WHITE,
+            kind: LightKind::Omni,
+            direction: 0,
+            cone_angle: 60.0,
             state: 0,
             lerped_state: 0.0,
             light_data: None,
@@ -359,9 +536,15 @@ impl Light {
                 room_padding,
                 rows,
                 cols,
+                arrangement: LightArrangement::Grid,
             }),
             intensity: 2.0,
             radius: 0.2,
+            exclusions: Vec::new(),
+            color: Color::WARM_WHITE,
+            kind: LightKind::Omni,
+            direction: 0,
+            cone_angle: 60.0,
             state: 0,
             lerped_state: 0.0,
             light_data: None,
@@ -369,31 +552,62 @@ impl Light {
         }
     }
 
+    /// Whether a point falls inside the beam from one of the lights points, always for omni lights
+    pub fn in_cone(&self, light_pos: Vec2, point: Vec2) -> bool {
+        match self.kind {
+            LightKind::Omni => true,
+            LightKind::Spot => {
+                let direction = rotate_point_i32(vec2(1.0, 0.0), -self.direction);
+                let to_point = (point - light_pos).normalize_or_zero();
+                to_point == Vec2::ZERO
+                    || direction.angle_to(to_point).abs().to_degrees() <= self.cone_angle / 2.0
+            }
+        }
+    }
+
     pub fn get_points(&self, room_pos: Vec2, room_size: Vec2) -> Vec<Vec2> {
         self.multi.as_ref().map_or_else(
             || vec![room_pos + self.pos],
             |multi| {
-                let mut lights_data = Vec::new();
                 let size = room_size - multi.room_padding;
-                let spacing = if multi.cols > 1 && multi.rows > 1 {
-                    size / vec2(f64::from(multi.cols) - 1.0, f64::from(multi.rows) - 1.0)
-                } else if multi.cols > 1 {
-                    vec2(size.x / (f64::from(multi.cols) - 1.0), 0.0)
-                } else if multi.rows > 1 {
-                    vec2(0.0, size.y / (f64::from(multi.rows) - 1.0))
-                } else {
-                    Vec2::ZERO
-                };
-                for col in 0..multi.cols {
-                    let x_pos =
-                        self.pos.x + (f64::from(col) - f64::from(multi.cols - 1) * 0.5) * spacing.x;
-                    for row in 0..multi.rows {
-                        let y_pos = self.pos.y
-                            + (f64::from(row) - f64::from(multi.rows - 1) * 0.5) * spacing.y;
-                        lights_data.push(room_pos + vec2(x_pos, y_pos));
+                let center = room_pos + self.pos;
+                match multi.arrangement {
+                    LightArrangement::Grid => grid_points(center, size, multi.cols, multi.rows)
+                        .into_iter()
+                        .map(|(point, _)| point)
+                        .collect(),
+                    // A single row along the top wall of the padded area
+                    LightArrangement::Row => {
+                        grid_points(center + vec2(0.0, size.y / 2.0), size, multi.cols, 1)
+                            .into_iter()
+                            .map(|(point, _)| point)
+                            .collect()
+                    }
+                    // Evenly spaced around a circle fitting the padded area
+                    LightArrangement::Ring => {
+                        let count = multi.cols.max(1);
+                        let radius = size.min_element() / 2.0;
+                        (0..count)
+                            .map(|i| {
+                                let angle = std::f64::consts::TAU * f64::from(i) / f64::from(count);
+                                center + vec2(angle.cos(), angle.sin()) * radius
+                            })
+                            .collect()
+                    }
+                    // The outer ring of the grid, following the room edges
+                    LightArrangement::Perimeter => {
+                        grid_points(center, size, multi.cols, multi.rows)
+                            .into_iter()
+                            .filter(|(_, (col, row))| {
+                                *col == 0
+                                    || *row == 0
+                                    || *col == multi.cols - 1
+                                    || *row == multi.rows - 1
+                            })
+                            .map(|(point, _)| point)
+                            .collect()
                     }
                 }
-                lights_data
             },
         )
     }
@@ -402,12 +616,38 @@ impl Light {
         Self::new("", Vec2::ZERO)
     }
 }
+/// Points of a grid centered on center spanning size, with their column and row
+fn grid_points(center: Vec2, size: Vec2, cols: u8, rows: u8) -> Vec<(Vec2, (u8, u8))> {
+    let spacing = if cols > 1 && rows > 1 {
+        size / vec2(f64::from(cols) - 1.0, f64::from(rows) - 1.0)
+    } else if cols > 1 {
+        vec2(size.x / (f64::from(cols) - 1.0), 0.0)
+    } else if rows > 1 {
+        vec2(0.0, size.y / (f64::from(rows) - 1.0))
+    } else {
+        Vec2::ZERO
+    };
+    let mut points = Vec::new();
+    for col in 0..cols {
+        let x_pos = (f64::from(col) - f64::from(cols - 1) * 0.5) * spacing.x;
+        for row in 0..rows {
+            let y_pos = (f64::from(row) - f64::from(rows - 1) * 0.5) * spacing.y;
+            points.push((center + vec2(x_pos, y_pos), (col, row)));
+        }
+    }
+    points
+}
 impl Hash for Light {
     fn hash<H: Hasher>(&self, state: &mut H) {
         hash_vec2(self.pos, state);
         self.multi.hash(state);
         self.intensity.to_bits().hash(state);
         self.radius.to_bits().hash(state);
+        self.exclusions.hash(state);
+        self.color.hash(state);
+        self.kind.hash(state);
+        self.direction.hash(state);
+        self.cone_angle.to_bits().hash(state);
         self.state.hash(state);
         self.lerped_state.to_bits().hash(state);
     }
@@ -418,6 +658,7 @@ impl MultiLight {
             room_padding: vec2(0.5, 0.5),
             rows: 1,
             cols: 1,
+            arrangement: LightArrangement::Grid,
         }
     }
 }
@@ -426,6 +667,7 @@ impl Hash for MultiLight {
         hash_vec2(self.room_padding, state);
         self.rows.hash(state);
         self.cols.hash(state);
+        self.arrangement.hash(state);
     }
 }
 
@@ -489,7 +731,11 @@ impl Hash for Zone {
 
 impl Outline {
     pub const fn new(thickness: f64, color: Color) -> Self {
-        Self { thickness, color }
+        Self {
+            thickness,
+            color,
+            style: LineStyle::Solid,
+        }
     }
 
     pub const fn default() -> Self {
@@ -500,6 +746,7 @@ impl Hash for Outline {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.thickness.to_bits().hash(state);
         self.color.hash(state);
+        self.style.hash(state);
     }
 }
 
@@ -509,6 +756,8 @@ impl GlobalMaterial {
             name: name.to_owned(),
             material,
             tint,
+            scale: 1.0,
+            rotation: 0,
             tiles: None,
         }
     }
@@ -516,17 +765,47 @@ impl GlobalMaterial {
     pub const fn tiles(mut self, spacing: f64, grout_width: f64, grout_color: Color) -> Self {
         self.tiles = Some(TileOptions {
             spacing,
+            grout: true,
             grout_width,
             grout_color,
+            alternate_tint: None,
+            pattern: TilePattern::Grid,
         });
         self
     }
 }
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            spacing: 0.4,
+            grout: true,
+            grout_width: 0.02,
+            grout_color: Color::from_rgb(80, 80, 80),
+            alternate_tint: None,
+            pattern: TilePattern::Grid,
+        }
+    }
+}
+impl Default for SunConfig {
+    fn default() -> Self {
+        Self { intensity: 1.0 }
+    }
+}
+
+impl Hash for SunConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.intensity.to_bits().hash(state);
+    }
+}
+
 impl Hash for GlobalMaterial {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.name.hash(state);
         self.material.hash(state);
         self.tint.hash(state);
+        self.scale.to_bits().hash(state);
+        self.rotation.hash(state);
         self.tiles.hash(state);
     }
 }
@@ -534,8 +813,11 @@ impl Hash for GlobalMaterial {
 impl Hash for TileOptions {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.spacing.to_bits().hash(state);
+        self.grout.hash(state);
         self.grout_width.to_bits().hash(state);
         self.grout_color.hash(state);
+        self.alternate_tint.hash(state);
+        self.pattern.hash(state);
     }
 }
 
@@ -562,3 +844,59 @@ impl Material {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{layout::Scene, template};
+
+    fn layout_with_scene() -> Home {
+        let mut layout = template::default();
+        layout.scenes.push(Scene {
+            id: Uuid::new_v4(),
+            name: "Evening".to_owned(),
+            lights: (0..16).map(|i| (format!("light_{i}"), i)).collect(),
+        });
+        layout
+    }
+
+    #[test]
+    fn regenerated_ids_keep_furniture_stacked() {
+        let mut room = Room::new("Lounge", vec2(2.0, 1.0), vec2(4.0, 3.0), "Carpet");
+        let table = Furniture::new("Table", FurnitureType::Misc, Vec2::ZERO, vec2(1.0, 0.6), 90);
+        let mut lamp = Furniture::new(
+            "Lamp",
+            FurnitureType::Misc,
+            vec2(0.3, 0.0),
+            vec2(0.2, 0.2),
+            0,
+        );
+        lamp.stack_on = Some(table.id);
+        room.furniture = vec![table, lamp];
+        let placement = room.furniture_placement(&room.furniture[1]);
+        let old_ids: Vec<Uuid> = room.furniture.iter().map(|f| f.id).collect();
+
+        room.regenerate_ids();
+        assert!(room.furniture.iter().all(|f| !old_ids.contains(&f.id)));
+        assert_eq!(room.furniture[1].stack_on, Some(room.furniture[0].id));
+        assert_eq!(room.furniture_placement(&room.furniture[1]), placement);
+    }
+
+    #[test]
+    fn reloaded_layout_is_the_same() {
+        let layout = layout_with_scene();
+        let bytes = bincode::serialize(&layout).unwrap();
+        for _ in 0..20 {
+            let reloaded: Home = bincode::deserialize(&bytes).unwrap();
+            assert!(reloaded.same_layout(&layout));
+        }
+    }
+
+    #[test]
+    fn changed_layout_is_not_the_same() {
+        let layout = layout_with_scene();
+        let mut changed = layout.clone();
+        changed.scenes[0].lights.insert("light_0".to_owned(), 255);
+        assert!(!changed.same_layout(&layout));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::common::{
+    layout::{Home, Room},
+    shape::coord_to_vec2,
+    utils::segment_distance,
+};
+use ahash::AHashMap;
+use geo::LinesIter;
+use glam::DVec2 as Vec2;
+use uuid::Uuid;
+
+// How far an openings center can be from a room edge and still count as in the wall
+const OPENING_WALL_TOLERANCE: f64 = 0.2;
+
+/// Problem found in a layout, with the object at fault and the room holding it so it can be selected
+#[derive(Clone, Debug)]
+pub struct LayoutWarning {
+    pub id: Uuid,
+    pub room_id: Option<Uuid>,
+    pub message: String,
+}
+
+impl Home {
+    /// Check for dangling material names, zero sized rooms and furniture, openings away from
+    /// any wall and ids used more than once
+    pub fn validate(&self) -> Vec<LayoutWarning> {
+        let mut warnings = Vec::new();
+        let mut warn = |id: Uuid, room_id: Option<Uuid>, message: String| {
+            warnings.push(LayoutWarning {
+                id,
+                room_id,
+                message,
+            });
+        };
+        let material_exists = |name: &str| self.materials.iter().any(|m| m.name == name);
+
+        if let Some(wall_material) = &self.wall_material {
+            if !material_exists(wall_material) {
+                warn(
+                    Uuid::nil(),
+                    None,
+                    format!("Walls use missing material '{wall_material}'"),
+                );
+            }
+        }
+
+        let room_edges: Vec<(Vec2, Vec2)> = self.rooms.iter().flat_map(room_edges).collect();
+        let mut seen_ids: AHashMap<Uuid, usize> = AHashMap::new();
+        for room in &self.rooms {
+            let in_room = Some(room.id);
+            let name = &room.name;
+            if room.size.min_element() <= 0.0 {
+                warn(room.id, in_room, format!("Room {name} has no area"));
+            }
+            if !material_exists(&room.material) {
+                warn(
+                    room.id,
+                    in_room,
+                    format!("Room {name} uses missing material '{}'", room.material),
+                );
+            }
+            for operation in &room.operations {
+                if let Some(material) = operation.material.as_ref().filter(|m| !material_exists(m))
+                {
+                    warn(
+                        operation.id,
+                        in_room,
+                        format!("Operation in {name} uses missing material '{material}'"),
+                    );
+                }
+            }
+            for furniture in &room.furniture {
+                let furniture_name = &furniture.name;
+                if furniture.size.min_element() <= 0.0 {
+                    warn(
+                        furniture.id,
+                        in_room,
+                        format!("{furniture_name} in {name} has no area"),
+                    );
+                }
+                let materials = [
+                    (furniture.has_material(), &furniture.material),
+                    (
+                        furniture.has_children_material(),
+                        &furniture.material_children,
+                    ),
+                ];
+                for (_, material) in materials
+                    .iter()
+                    .filter(|(used, material)| *used && !material_exists(material))
+                {
+                    warn(
+                        furniture.id,
+                        in_room,
+                        format!("{furniture_name} in {name} uses missing material '{material}'"),
+                    );
+                }
+            }
+            for opening in &room.openings {
+                let pos = room.pos + opening.pos;
+                let near_wall = room_edges
+                    .iter()
+                    .any(|&(a, b)| segment_distance(pos, a, b) <= OPENING_WALL_TOLERANCE);
+                if !near_wall {
+                    warn(
+                        opening.id,
+                        in_room,
+                        format!("{} in {name} isn't on a wall", opening.opening_type),
+                    );
+                }
+            }
+
+            let ids = std::iter::once(room.id)
+                .chain(room.operations.iter().map(|o| o.id))
+                .chain(room.zones.iter().map(|z| z.id))
+                .chain(room.openings.iter().map(|o| o.id))
+                .chain(room.lights.iter().map(|l| l.id))
+                .chain(room.furniture.iter().map(|f| f.id))
+                .chain(room.sensors.iter().map(|s| s.id));
+            for id in ids {
+                *seen_ids.entry(id).or_default() += 1;
+                if seen_ids[&id] == 2 {
+                    warn(
+                        id,
+                        in_room,
+                        format!("Id {id} in {name} is used more than once"),
+                    );
+                }
+            }
+        }
+        warnings
+    }
+}
+
+/// Outline edges of a room, from the rendered polygons when they're up to date
+fn room_edges(room: &Room) -> Vec<(Vec2, Vec2)> {
+    let polygons = room
+        .rendered_data
+        .as_ref()
+        .map_or_else(|| room.polygons(), |rendered| rendered.polygons.clone());
+    polygons
+        .lines_iter()
+        .map(|line| (coord_to_vec2(line.start), coord_to_vec2(line.end)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{
+        furniture::{ChairType, Furniture, FurnitureType},
+        layout::{Opening, OpeningType},
+        template,
+    };
+    use glam::dvec2 as vec2;
+
+    #[test]
+    fn template_layout_has_no_warnings() {
+        let warnings = template::default().validate();
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn problems_are_reported_against_their_objects() {
+        let mut home = template::default();
+        home.wall_material = Some("Missing".to_owned());
+        let room = &mut home.rooms[0];
+        room.material = "Missing".to_owned();
+        let flat_chair = Furniture::new(
+            "Chair",
+            FurnitureType::Chair(ChairType::Dining),
+            Vec2::ZERO,
+            vec2(0.5, 0.0),
+            0,
+        );
+        let flat_chair_id = flat_chair.id;
+        room.furniture.push(flat_chair);
+        // Well inside the room, away from any of its walls
+        let floating_door = Opening::new(OpeningType::Door, room.size / 4.0, 0);
+        let floating_door_id = floating_door.id;
+        room.openings.push(floating_door);
+        let room_id = room.id;
+        let mut copy = home.rooms[1].clone();
+        copy.name = "Copy".to_owned();
+        let copy_id = copy.id;
+        home.rooms.push(copy);
+
+        let warnings: Vec<(Uuid, Option<Uuid>)> = home
+            .validate()
+            .iter()
+            .map(|warning| (warning.id, warning.room_id))
+            .collect();
+        let mut expected = vec![
+            (Uuid::nil(), None),
+            (room_id, Some(room_id)),
+            (flat_chair_id, Some(room_id)),
+            (floating_door_id, Some(room_id)),
+        ];
+        // Every id in the copied room is a duplicate, reported once each
+        let copy = home.rooms.last().unwrap();
+        expected.extend(
+            std::iter::once(copy_id)
+                .chain(copy.operations.iter().map(|o| o.id))
+                .chain(copy.zones.iter().map(|z| z.id))
+                .chain(copy.openings.iter().map(|o| o.id))
+                .chain(copy.lights.iter().map(|l| l.id))
+                .chain(copy.furniture.iter().map(|f| f.id))
+                .chain(copy.sensors.iter().map(|s| s.id))
+                .map(|id| (id, Some(copy_id))),
+        );
+        assert_eq!(warnings, expected);
+    }
+}
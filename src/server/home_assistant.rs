@@ -1,13 +1,22 @@
 use crate::{
     common::{
-        furniture::Furniture, layout::DataPoint, HAState, PostActionsData, PostActionsPacket,
-        TokenPacket,
+        furniture::Furniture, layout::DataPoint, GetStatesPacket, HAArea, HAEntity, HAState,
+        PostActionsData, PostActionsPacket, TokenPacket,
+    },
+    server::{
+        auth::verify_token,
+        presence,
+        routing::{HOME, LAYOUT_REVISION},
     },
-    server::{auth::verify_token, presence, routing::HOME},
 };
 use ahash::AHashMap;
 use anyhow::Result;
-use axum::{body::Bytes, http::StatusCode, response::IntoResponse};
+use axum::{
+    body::Bytes,
+    extract::ws::{Message as ClientMessage, WebSocket, WebSocketUpgrade},
+    http::StatusCode,
+    response::IntoResponse,
+};
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
@@ -17,8 +26,13 @@ use std::{
         atomic::{AtomicI64, Ordering},
         Arc, LazyLock,
     },
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{watch, Mutex},
+    time::{timeout, Instant},
 };
-use tokio::{net::TcpStream, sync::Mutex};
 use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
 fn get_env_variable(key: &str) -> String {
@@ -35,12 +49,49 @@ fn get_env_variable(key: &str) -> String {
 
 static HA_STATE: LazyLock<Mutex<Option<HAState>>> = LazyLock::new(|| Mutex::new(None));
 
+// Device tracker entity -> (Friendly name, State)
+pub static TRACKERS: LazyLock<Mutex<AHashMap<String, (String, String)>>> =
+    LazyLock::new(|| Mutex::new(AHashMap::new()));
+
+// Entity id -> (Friendly name, Unit), for offering entities by name
+static ENTITY_INFO: LazyLock<Mutex<AHashMap<String, (String, String)>>> =
+    LazyLock::new(|| Mutex::new(AHashMap::new()));
+
+#[derive(Clone, Copy)]
+enum RegistryKind {
+    Areas,
+    Devices,
+    Entities,
+}
+
+// Area, device and entity registries used to find the entities in each area
+#[derive(Default)]
+struct Registries {
+    pending: AHashMap<u64, RegistryKind>,
+    areas: Vec<Value>,
+    devices: Vec<Value>,
+    entities: Vec<Value>,
+}
+
+static REGISTRIES: LazyLock<Mutex<Registries>> =
+    LazyLock::new(|| Mutex::new(Registries::default()));
+
+// Notified whenever the states or layout revision change, wakes the streaming clients
+static STATE_CHANGES: LazyLock<watch::Sender<()>> = LazyLock::new(|| watch::channel(()).0);
+
+// Shortest gap between packets sent to a streaming client, bursts of changes are combined
+const STREAM_MIN_INTERVAL: Duration = Duration::from_millis(100);
+// How long a streaming client has to send its token before it's dropped
+const STREAM_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+// Every state is resent this often, correcting states a client set that never took effect
+const STREAM_KEYFRAME_INTERVAL: Duration = Duration::from_secs(30);
+
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 static WS_STREAM: LazyLock<Arc<Mutex<Option<WsStream>>>> =
     LazyLock::new(|| Arc::new(Mutex::new(None)));
 
 pub async fn get_states_server(body: Bytes) -> impl IntoResponse {
-    let packet: TokenPacket = match bincode::deserialize(&body) {
+    let packet: GetStatesPacket = match bincode::deserialize(&body) {
         Ok(packet) => packet,
         Err(e) => {
             log::error!("Failed to deserialize get_states_server packet: {:?}", e);
@@ -51,22 +102,151 @@ pub async fn get_states_server(body: Bytes) -> impl IntoResponse {
         return (StatusCode::UNAUTHORIZED, Vec::new());
     }
 
+    // Leave out slow changing room sensors unless requested, furniture sensors are always sent
+    let slow_sensors = if packet.include_slow {
+        Vec::new()
+    } else {
+        let home = HOME.lock().await;
+        let fast_sensors = home
+            .rooms
+            .iter()
+            .flat_map(|room| room.furniture.iter().flat_map(Furniture::wanted_sensors))
+            .collect::<Vec<_>>();
+        home.rooms
+            .iter()
+            .flat_map(|room| room.sensors.iter().map(|sensor| sensor.entity_id.clone()))
+            .filter(|id| !fast_sensors.contains(id))
+            .collect()
+    };
+
     let ha_state = HA_STATE.lock().await;
     ha_state.as_ref().map_or_else(
         || {
             log::error!("State not found in memory");
             (StatusCode::INTERNAL_SERVER_ERROR, Vec::new())
         },
-        |states| match bincode::serialize(states) {
-            Ok(serialized) => (StatusCode::OK, serialized),
-            Err(e) => {
-                log::error!("Failed to serialize states: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Vec::new())
+        |states| {
+            let mut states = states.clone();
+            states.sensors.retain(|id, _| !slow_sensors.contains(id));
+            states.layout_revision = LAYOUT_REVISION.load(Ordering::Relaxed);
+            match bincode::serialize(&states) {
+                Ok(serialized) => (StatusCode::OK, serialized),
+                Err(e) => {
+                    log::error!("Failed to serialize states: {:?}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, Vec::new())
+                }
             }
         },
     )
 }
 
+pub fn notify_state_change() {
+    STATE_CHANGES.send_replace(());
+}
+
+pub async fn stream_states_server(ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(stream_states)
+}
+
+/// Push states to a client as they change, the client sends its token first then receives every
+/// state followed by only the states that changed. Every state is sent again on each keyframe and
+/// whenever the client sends another message, such as after it reloads its layout
+async fn stream_states(mut socket: WebSocket) {
+    let authorised = match timeout(STREAM_AUTH_TIMEOUT, socket.recv()).await {
+        Ok(Some(Ok(ClientMessage::Binary(data)))) => {
+            match bincode::deserialize::<TokenPacket>(&data) {
+                Ok(packet) => verify_token(&packet.token).await.unwrap_or(false),
+                Err(e) => {
+                    log::error!("Failed to deserialize stream_states packet: {:?}", e);
+                    false
+                }
+            }
+        }
+        _ => false,
+    };
+    if !authorised {
+        socket.send(ClientMessage::Close(None)).await.ok();
+        return;
+    }
+
+    let mut changes = STATE_CHANGES.subscribe();
+    let mut last_sent: Option<HAState> = None;
+    let mut next_keyframe = Instant::now();
+    loop {
+        if Instant::now() >= next_keyframe {
+            last_sent = None;
+            next_keyframe = Instant::now() + STREAM_KEYFRAME_INTERVAL;
+        }
+        let current = HA_STATE.lock().await.clone().map(|mut states| {
+            states.layout_revision = LAYOUT_REVISION.load(Ordering::Relaxed);
+            states
+        });
+        if let Some(current) = current {
+            if let Some(changed) = states_diff(last_sent.as_ref(), &current) {
+                let serialized = match bincode::serialize(&changed) {
+                    Ok(serialized) => serialized,
+                    Err(e) => {
+                        log::error!("Failed to serialize states: {:?}", e);
+                        return;
+                    }
+                };
+                if socket
+                    .send(ClientMessage::Binary(serialized))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                last_sent = Some(current);
+            }
+        }
+
+        tokio::select! {
+            changed = changes.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                None | Some(Err(_) | Ok(ClientMessage::Close(_))) => return,
+                Some(Ok(ClientMessage::Binary(_))) => last_sent = None,
+                Some(Ok(_)) => {}
+            },
+            () = tokio::time::sleep_until(next_keyframe) => {}
+        }
+        tokio::time::sleep(STREAM_MIN_INTERVAL).await;
+    }
+}
+
+/// Lights and sensors that differ from the last states sent, with the current presence points
+/// and layout revision, nothing when they are all unchanged
+fn states_diff(last: Option<&HAState>, current: &HAState) -> Option<HAState> {
+    let Some(last) = last else {
+        return Some(current.clone());
+    };
+    let changed = HAState {
+        lights: current
+            .lights
+            .iter()
+            .filter(|(id, state)| last.lights.get(*id) != Some(*state))
+            .map(|(id, state)| (id.clone(), *state))
+            .collect(),
+        sensors: current
+            .sensors
+            .iter()
+            .filter(|(id, state)| last.sensors.get(*id) != Some(*state))
+            .map(|(id, state)| (id.clone(), state.clone()))
+            .collect(),
+        presence_points: current.presence_points.clone(),
+        layout_revision: current.layout_revision,
+    };
+    let unchanged = changed.lights.is_empty()
+        && changed.sensors.is_empty()
+        && changed.presence_points == last.presence_points
+        && changed.layout_revision == last.layout_revision;
+    (!unchanged).then_some(changed)
+}
+
 pub async fn post_actions_server(body: Bytes) -> impl IntoResponse {
     let packet: PostActionsPacket = match bincode::deserialize(&body) {
         Ok(packet) => packet,
@@ -138,6 +318,8 @@ pub async fn run_server() -> Result<()> {
                     let presence_points = presence::calculate(&state.sensors).await?;
                     state.presence_points = presence_points;
                 }
+                drop(ha_state);
+                notify_state_change();
             }
         }
     }
@@ -163,6 +345,7 @@ async fn handle_ws_message(
                             json!({"id": 2, "type": "get_states"}).to_string(),
                         ))
                         .await?;
+                    request_registries(ws_stream).await?;
                 }
             } else if response["type"] == "event"
                 && response["id"].as_u64() == Some(1)
@@ -173,6 +356,8 @@ async fn handle_ws_message(
                 if let Err(e) = process_full_states(response["result"].take()).await {
                     log::error!("{}", e);
                 }
+            } else if response["type"] == "result" {
+                process_registry(&mut response).await;
             }
         }
         Err(e) => {
@@ -183,6 +368,135 @@ async fn handle_ws_message(
     Ok(())
 }
 
+/// Ask for the area, device and entity registries, their results are matched up by request id
+async fn request_registries(ws_stream: &mut WsStream) -> Result<()> {
+    let requests = [
+        (RegistryKind::Areas, "config/area_registry/list"),
+        (RegistryKind::Devices, "config/device_registry/list"),
+        (RegistryKind::Entities, "config/entity_registry/list"),
+    ];
+    for (kind, request_type) in requests {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        REGISTRIES.lock().await.pending.insert(id as u64, kind);
+        ws_stream
+            .send(Message::Text(
+                json!({"id": id, "type": request_type}).to_string(),
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+async fn process_registry(response: &mut Value) {
+    let Some(id) = response["id"].as_u64() else {
+        return;
+    };
+    let mut registries = REGISTRIES.lock().await;
+    let Some(kind) = registries.pending.remove(&id) else {
+        return;
+    };
+    let entries = match response["result"].take() {
+        Value::Array(entries) => entries,
+        _ => Vec::new(),
+    };
+    match kind {
+        RegistryKind::Areas => registries.areas = entries,
+        RegistryKind::Devices => registries.devices = entries,
+        RegistryKind::Entities => registries.entities = entries,
+    }
+}
+
+pub async fn get_areas_server(body: Bytes) -> impl IntoResponse {
+    let packet: TokenPacket = match bincode::deserialize(&body) {
+        Ok(packet) => packet,
+        Err(e) => {
+            log::error!("Failed to deserialize get_areas_server packet: {:?}", e);
+            return (StatusCode::BAD_REQUEST, Vec::new());
+        }
+    };
+    if !verify_token(&packet.token).await.unwrap_or(false) {
+        return (StatusCode::UNAUTHORIZED, Vec::new());
+    }
+
+    // Refresh the registries in the background so areas edited since connecting show next time
+    tokio::spawn(async {
+        let mut ws_stream = WS_STREAM.lock().await;
+        if let Some(ref mut ws_stream) = *ws_stream {
+            if let Err(e) = request_registries(ws_stream).await {
+                log::error!("Failed to request registries: {:?}", e);
+            }
+        }
+    });
+
+    let areas = collect_areas(&*REGISTRIES.lock().await, &*ENTITY_INFO.lock().await);
+    match bincode::serialize(&areas) {
+        Ok(serialized) => (StatusCode::OK, serialized),
+        Err(e) => {
+            log::error!("Failed to serialize areas: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Vec::new())
+        }
+    }
+}
+
+/// Areas with their lights and sensors, entities without an area of their own take their devices
+fn collect_areas(
+    registries: &Registries,
+    entity_info: &AHashMap<String, (String, String)>,
+) -> Vec<HAArea> {
+    let device_areas: AHashMap<&str, &str> = registries
+        .devices
+        .iter()
+        .filter_map(|device| Some((device["id"].as_str()?, device["area_id"].as_str()?)))
+        .collect();
+    let mut areas: Vec<HAArea> = registries
+        .areas
+        .iter()
+        .filter_map(|area| {
+            Some(HAArea {
+                area_id: area["area_id"].as_str()?.to_string(),
+                name: area["name"].as_str()?.to_string(),
+                lights: Vec::new(),
+                sensors: Vec::new(),
+            })
+        })
+        .collect();
+
+    for entity in &registries.entities {
+        let Some(full_id) = entity["entity_id"].as_str() else {
+            continue;
+        };
+        let area_id = entity["area_id"].as_str().or_else(|| {
+            entity["device_id"]
+                .as_str()
+                .and_then(|device_id| device_areas.get(device_id).copied())
+        });
+        let Some(area) = area_id.and_then(|id| areas.iter_mut().find(|a| a.area_id == id)) else {
+            continue;
+        };
+        let Some((domain, id)) = full_id.split_once('.') else {
+            continue;
+        };
+        let (name, unit) = entity_info
+            .get(full_id)
+            .cloned()
+            .unwrap_or_else(|| (id.to_string(), String::new()));
+        // Lights and plain sensors are stored without their domain, like the states are keyed
+        let (list, entity_id) = match domain {
+            "light" => (&mut area.lights, id),
+            "sensor" => (&mut area.sensors, id),
+            "binary_sensor" | "switch" | "input_boolean" => (&mut area.sensors, full_id),
+            _ => continue,
+        };
+        list.push(HAEntity {
+            entity_id: entity_id.to_string(),
+            name,
+            unit,
+        });
+    }
+    areas.sort_by(|a, b| a.name.cmp(&b.name));
+    areas
+}
+
 async fn process_full_states(states_raw: Value) -> Result<()> {
     let states_raw = serde_json::from_value::<Vec<HassState>>(states_raw)?;
 
@@ -190,6 +504,26 @@ async fn process_full_states(states_raw: Value) -> Result<()> {
     let mut lights = AHashMap::new();
     let mut sensors = AHashMap::new();
 
+    *ENTITY_INFO.lock().await = states_raw
+        .iter()
+        .map(|state_raw| {
+            let attribute = |key: &str| {
+                state_raw
+                    .attributes
+                    .get(key)
+                    .and_then(serde_json::Value::as_str)
+                    .map(ToString::to_string)
+            };
+            (
+                state_raw.entity_id.clone(),
+                (
+                    attribute("friendly_name").unwrap_or_else(|| state_raw.entity_id.clone()),
+                    attribute("unit_of_measurement").unwrap_or_default(),
+                ),
+            )
+        })
+        .collect();
+
     for state_raw in &states_raw {
         if let Some((domain, entity_id)) = state_raw.entity_id.split_once('.') {
             match domain {
@@ -209,6 +543,22 @@ async fn process_full_states(states_raw: Value) -> Result<()> {
                 "sensor" if target_sensors.contains(&entity_id.to_string()) => {
                     sensors.insert(entity_id.to_string(), state_raw.state.clone());
                 }
+                "switch" | "binary_sensor" | "input_boolean"
+                    if target_sensors.contains(&state_raw.entity_id) =>
+                {
+                    sensors.insert(state_raw.entity_id.clone(), state_raw.state.clone());
+                }
+                "device_tracker" => {
+                    let name = state_raw
+                        .attributes
+                        .get("friendly_name")
+                        .and_then(serde_json::Value::as_str)
+                        .unwrap_or(entity_id);
+                    TRACKERS.lock().await.insert(
+                        entity_id.to_string(),
+                        (name.to_string(), state_raw.state.clone()),
+                    );
+                }
                 _ => {}
             }
         }
@@ -221,7 +571,9 @@ async fn process_full_states(states_raw: Value) -> Result<()> {
         lights,
         sensors,
         presence_points,
+        layout_revision: 0,
     });
+    notify_state_change();
     Ok(())
 }
 
@@ -244,7 +596,7 @@ async fn process_state(data: &Value) -> Result<()> {
                         ),
                     );
                 }
-                "sensor" | "binary_sensor" | "input_boolean"
+                "sensor" | "binary_sensor" | "input_boolean" | "switch"
                     if target_sensors.contains(&entity_id.to_string()) =>
                 {
                     ha_state.sensors.insert(
@@ -259,6 +611,19 @@ async fn process_state(data: &Value) -> Result<()> {
                         }
                     }
                 }
+                "device_tracker" => {
+                    let name = new_state["attributes"]["friendly_name"]
+                        .as_str()
+                        .unwrap_or(id);
+                    TRACKERS.lock().await.insert(
+                        id.to_string(),
+                        (
+                            name.to_string(),
+                            new_state["state"].as_str().unwrap_or("unknown").to_string(),
+                        ),
+                    );
+                    needs_presence_update = true;
+                }
                 _ => {}
             }
         }
@@ -272,6 +637,7 @@ async fn process_state(data: &Value) -> Result<()> {
             state.presence_points = presence_points;
         }
     }
+    notify_state_change();
 
     Ok(())
 }
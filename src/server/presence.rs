@@ -1,11 +1,14 @@
 use crate::{
     common::{
         furniture::{FurnitureType, SensorType},
-        layout::DataPoint,
+        layout::{DataPoint, Room},
         utils::rotate_point_i32,
-        PostActionsData,
+        PostActionsData, PresencePoint,
+    },
+    server::{
+        home_assistant::{post_actions_impl, TRACKERS},
+        routing::HOME,
     },
-    server::{home_assistant::post_actions_impl, routing::HOME},
 };
 use ahash::AHashMap;
 use anyhow::Result;
@@ -26,7 +29,7 @@ type PresenceCalibration = (Instant, Vec<Vec2>);
 static PRESENCE_CALIBRATION: LazyLock<Mutex<Option<PresenceCalibration>>> =
     LazyLock::new(|| Mutex::new(None));
 
-pub async fn calculate(sensors: &AHashMap<String, String>) -> Result<Vec<Vec2>> {
+pub async fn calculate(sensors: &AHashMap<String, String>) -> Result<Vec<PresencePoint>> {
     // Begin calibration if needed
     let mut calibration_lock = PRESENCE_CALIBRATION.lock().await;
     let presence_calibration = sensors
@@ -151,15 +154,14 @@ pub async fn calculate(sensors: &AHashMap<String, String>) -> Result<Vec<Vec2>>
                         }));
                     };
                 }
-                FurnitureType::Sensor(SensorType::PresenceBoolean) => {
-                    // If sensed, add a presence point on the furniture's position
+                // If sensed, add a presence point on the furniture's position
+                FurnitureType::Sensor(SensorType::PresenceBoolean)
                     if furniture
                         .misc_sensors
                         .iter()
-                        .any(|id| sensors.get(id).map_or(false, |state| state == "on"))
-                    {
-                        presence_points.push(room.pos + furniture.pos);
-                    }
+                        .any(|id| sensors.get(id).is_some_and(|state| state == "on")) =>
+                {
+                    presence_points.push(room.pos + furniture.pos);
                 }
                 _ => {}
             }
@@ -311,5 +313,38 @@ pub async fn calculate(sensors: &AHashMap<String, String>) -> Result<Vec<Vec2>>
         post_actions_impl(post_data).await;
     }
 
-    Ok(presence_points)
+    Ok(label_presence_points(&layout.rooms, presence_points).await)
+}
+
+/// Label points with the names of people whose room trackers report the room they are in,
+/// trackers without a sensed point in their room get a point at the room center
+async fn label_presence_points(rooms: &[Room], points: Vec<Vec2>) -> Vec<PresencePoint> {
+    let mut presence_points: Vec<PresencePoint> = points
+        .into_iter()
+        .map(|pos| PresencePoint { pos, label: None })
+        .collect();
+
+    for (name, state) in TRACKERS.lock().await.values() {
+        let state = state.to_lowercase().replace('_', " ");
+        let Some(room) = rooms.iter().find(|r| r.name.to_lowercase() == state) else {
+            continue;
+        };
+        let nearest_unlabelled = presence_points
+            .iter_mut()
+            .filter(|p| p.label.is_none() && room.contains(p.pos))
+            .min_by(|a, b| {
+                a.pos
+                    .distance(room.pos)
+                    .total_cmp(&b.pos.distance(room.pos))
+            });
+        if let Some(point) = nearest_unlabelled {
+            point.label = Some(name.clone());
+        } else {
+            presence_points.push(PresencePoint {
+                pos: room.pos,
+                label: Some(name.clone()),
+            });
+        }
+    }
+    presence_points
 }
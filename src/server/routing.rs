@@ -2,13 +2,28 @@ use crate::{
     common::{layout::Home, template, SaveLayoutPacket, TokenPacket},
     server::{
         auth::{login_server, verify_token},
-        home_assistant::{get_states_server, post_actions_server},
+        home_assistant::{
+            get_areas_server, get_states_server, notify_state_change, post_actions_server,
+            stream_states_server,
+        },
     },
 };
 use anyhow::{anyhow, Result};
-use axum::{body::Bytes, http::StatusCode, response::IntoResponse, routing::post, Router};
+use axum::{
+    body::Bytes,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
 use chrono::{DateTime, Utc};
-use std::{path::Path, sync::LazyLock};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+};
 use tokio::{fs, sync::Mutex};
 
 const LAYOUT_PATH: &str = "home_layout.ron";
@@ -17,18 +32,28 @@ pub fn setup_routes(app: Router) -> Router {
     app.route("/load_layout", post(load_layout_server))
         .route("/save_layout", post(save_layout_server))
         .route("/get_states", post(get_states_server))
+        .route("/stream_states", get(stream_states_server))
+        .route("/get_areas", post(get_areas_server))
         .route("/post_actions", post(post_actions_server))
         .route("/login", post(login_server))
 }
 
 pub static HOME: LazyLock<Mutex<Home>> = LazyLock::new(|| Mutex::new(template::default()));
+pub static LAYOUT_REVISION: AtomicU64 = AtomicU64::new(0);
 
 pub async fn start_server() {
     *HOME.lock().await = fs::read_to_string(LAYOUT_PATH)
         .await
         .ok()
-        .and_then(|data| ron::from_str::<Home>(&data).ok())
-        .unwrap_or_else(template::default);
+        .and_then(|data| {
+            ron::from_str::<Home>(&data)
+                .map_err(|e| log::error!("Failed to parse layout: {e}"))
+                .ok()
+        })
+        .map_or_else(template::default, |mut home| {
+            home.migrate();
+            home
+        });
 
     match super::home_assistant::run_server().await {
         Ok(()) => {}
@@ -90,8 +115,10 @@ async fn save_layout_server(body: Bytes) -> impl IntoResponse {
         return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     }
 
-    // Update the in-memory layout
+    // Update the in-memory layout and let other clients know to reload it
     *HOME.lock().await = packet.home;
+    LAYOUT_REVISION.fetch_add(1, Ordering::Relaxed);
+    notify_state_change();
 
     StatusCode::OK.into_response()
 }